@@ -0,0 +1,157 @@
+use serde::Serialize;
+
+/// Why a node's mempool would turn a tx away, grouped from its reject reason.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Rejection {
+    MissingInputs,
+    MinRelayFee,
+    TooLongMempoolChain,
+    NonStandard,
+    Conflict,
+    AlreadyKnown,
+    Other,
+}
+
+// Reject reasons from Bitcoin Core's policy and standardness checks
+const NON_STANDARD: [&str; 14] = [
+    "version",
+    "tx-size",
+    "tx-size-small",
+    "scriptsig-size",
+    "scriptsig-not-pushonly",
+    "scriptpubkey",
+    "bare-multisig",
+    "dust",
+    "multi-op-return",
+    "non-final",
+    "non-BIP68-final",
+    "bad-txns-nonstandard-inputs",
+    "bad-witness-nonstandard",
+    "non-mandatory-script-verify-flag",
+];
+
+impl Rejection {
+    pub(crate) fn code(self) -> &'static str {
+        match self {
+            Rejection::MissingInputs => "missing_inputs",
+            Rejection::MinRelayFee => "min_relay_fee",
+            Rejection::TooLongMempoolChain => "too_long_mempool_chain",
+            Rejection::NonStandard => "non_standard",
+            Rejection::Conflict => "conflict",
+            Rejection::AlreadyKnown => "already_known",
+            Rejection::Other => "rejected",
+        }
+    }
+
+    /// Maps a `testmempoolaccept` reject reason, e.g. `min relay fee not met`.
+    pub(crate) fn classify(reason: &str) -> Rejection {
+        let reason = reason.trim();
+        let token = reason.split([',', ' ', '(']).next().unwrap_or(reason);
+        if reason.starts_with("missing-inputs")
+            || reason.starts_with("bad-txns-inputs-missingorspent")
+        {
+            Rejection::MissingInputs
+        } else if reason.starts_with("min relay fee not met")
+            || reason.starts_with("mempool min fee not met")
+        {
+            Rejection::MinRelayFee
+        } else if reason.starts_with("too-long-mempool-chain") {
+            Rejection::TooLongMempoolChain
+        } else if reason.starts_with("txn-mempool-conflict")
+            || reason.starts_with("bad-txns-spends-conflicting-tx")
+            || reason.starts_with("insufficient fee")
+            || reason.starts_with("replacement-adds-unconfirmed")
+            || reason.starts_with("too many potential replacements")
+        {
+            Rejection::Conflict
+        } else if reason.starts_with("txn-already-in-mempool")
+            || reason.starts_with("txn-already-known")
+        {
+            Rejection::AlreadyKnown
+        } else if NON_STANDARD.contains(&token) {
+            Rejection::NonStandard
+        } else {
+            Rejection::Other
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct Reason {
+    pub code: &'static str,
+    pub detail: String, // the node's own reject reason
+}
+
+pub(crate) fn reason(reject_reason: &str) -> Reason {
+    Reason {
+        code: Rejection::classify(reject_reason).code(),
+        detail: reject_reason.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_inputs_reasons() {
+        for reason in ["missing-inputs", "bad-txns-inputs-missingorspent"] {
+            assert_eq!(Rejection::classify(reason), Rejection::MissingInputs);
+        }
+    }
+
+    #[test]
+    fn test_fee_floor_reasons() {
+        for reason in [
+            "min relay fee not met, 100 < 141",
+            "mempool min fee not met, 100 < 2000",
+        ] {
+            assert_eq!(Rejection::classify(reason), Rejection::MinRelayFee);
+        }
+    }
+
+    #[test]
+    fn test_chain_limit_reason() {
+        assert_eq!(
+            Rejection::classify(
+                "too-long-mempool-chain, too many unconfirmed ancestors [limit: 25]"
+            ),
+            Rejection::TooLongMempoolChain
+        );
+    }
+
+    #[test]
+    fn test_non_standard_reasons_matched_on_first_token() {
+        for reason in [
+            "dust",
+            "scriptpubkey",
+            "tx-size",
+            "non-mandatory-script-verify-flag (Signature must be zero for failed CHECK(MULTI)SIG operation)",
+        ] {
+            assert_eq!(Rejection::classify(reason), Rejection::NonStandard);
+        }
+        assert_eq!(Rejection::classify("dustless"), Rejection::Other);
+    }
+
+    #[test]
+    fn test_conflict_reasons() {
+        for reason in [
+            "txn-mempool-conflict",
+            "bad-txns-spends-conflicting-tx, 1a2b spends conflicting transaction 3c4d",
+            "insufficient fee, rejecting replacement 1a2b; new feerate 0.00001 <= old feerate 0.00002",
+        ] {
+            assert_eq!(Rejection::classify(reason), Rejection::Conflict);
+        }
+    }
+
+    #[test]
+    fn test_already_known_is_its_own_code() {
+        assert_eq!(
+            Rejection::classify("txn-already-in-mempool"),
+            Rejection::AlreadyKnown
+        );
+        let reason = reason(" txn-already-known ");
+        assert_eq!(reason.code, "already_known");
+        assert_eq!(reason.detail, " txn-already-known ");
+    }
+}
@@ -1,11 +1,36 @@
+use crate::acceptance;
+use crate::archive::{self, Retention};
+use crate::audit;
+use crate::blocks;
+use crate::braid::{self, Bead};
+use crate::capacity::{self, Budget};
+use crate::chain;
+use crate::commitment;
+use crate::gc::{self, Limits, LAST_SWEEP, LIMITS};
+use crate::ledger::{self, MinerPayout, Share};
+use crate::metrics::{self, time_rpc};
+use crate::operators::{self, Operator, SignatureError, SignedRequest};
+use crate::pipeline::{Applied, StateStore, Transition, TransitionError, STATE};
+use crate::policy::{self, Policy, POLICY};
+use crate::quorum;
+use crate::reconcile::{self, Reason};
+use crate::template::{self, Payee, PoolTx};
+use crate::timeline::{self, Event};
 use axum::{
-    extract::Path,
-    http::StatusCode,
+    extract::{Path, Query},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::IntoResponse,
     routing::{get, post},
     Json, Router,
 };
+use bitcoincore_rpc::bitcoin::block::Header;
+use bitcoincore_rpc::bitcoin::consensus::encode::{deserialize, serialize_hex};
+use bitcoincore_rpc::bitcoin::hex::FromHex;
 use bitcoincore_rpc::bitcoin::Amount;
-use bitcoincore_rpc::bitcoin::{Transaction, Txid};
+use bitcoincore_rpc::bitcoin::{
+    Address, Block, BlockHash, Network, ScriptBuf, Transaction, TxOut, Txid,
+};
 use bitcoincore_rpc::{Auth, Client, RpcApi};
 use futures::stream::{self, StreamExt};
 use once_cell::sync::Lazy;
@@ -13,7 +38,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::sync::{Arc, Mutex};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[derive(Serialize)]
 struct ApiStatus {
@@ -38,6 +63,10 @@ pub struct ApiTransaction {
     pub confirmations: u32,
     pub timestamp: Option<u64>,
     pub rbf_signaled: bool,
+    pub version: u64,
+    pub bead: Option<String>, // first bead, in braid order, that committed it
+    pub cohort: Option<usize>, // cohort of that bead
+    pub proposal: Option<timeline::Proposal>, // while Proposed or Scheduled
     pub status: ApiStatus,
 }
 
@@ -48,25 +77,154 @@ pub struct ApiMempoolInfo {
     pub total_fee: u64,
     pub fee_histogram: Vec<[f64; 2]>,
 }
-pub(crate) struct StateStore {
-    committed: HashSet<Txid>, // Stage 2: In cmempool
-    proposed: HashSet<Txid>,  // Stage 3: Marked as proposed
-    scheduled: HashSet<Txid>, // Stage 4: Marked as scheduled
+
+#[derive(Deserialize, Default)]
+pub struct ReverseRequest {
+    pub reason: Option<String>,
 }
 
-impl StateStore {
-    fn new() -> Self {
-        StateStore {
-            committed: HashSet::new(),
-            proposed: HashSet::new(),
-            scheduled: HashSet::new(),
-        }
-    }
+#[derive(Deserialize, Default)]
+pub struct ProposalRequest {
+    pub proposer: Option<String>,
+    pub bead: Option<String>, // bead hash expected to carry the tx
+    pub note: Option<String>,
+    pub target_height: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct BatchFilter {
+    pub category: Option<String>,
+    pub min_fee_rate: Option<f64>, // sat/vB
+}
+
+#[derive(Deserialize)]
+pub struct BatchRequest {
+    #[serde(default)]
+    pub txids: Vec<String>,
+    pub filter: Option<BatchFilter>,
+    #[serde(default)]
+    pub atomic: bool, // all-or-nothing
+    #[serde(default)]
+    pub signatures: HashMap<String, String>, // txid -> operator signature, once operators are registered
+}
+
+#[derive(Deserialize)]
+pub struct EvaluateQuery {
+    pub dry_run: Option<bool>, // defaults to the policy's own setting
+}
+
+#[derive(Deserialize)]
+pub struct TemplateQuery {
+    pub address: Option<String>, // what ledger payees leave; anyone-can-spend when omitted
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MineSource {
+    Scheduled,
+    Txids,
+    Template, // whatever the node's own getblocktemplate picks
+}
+
+#[derive(Deserialize)]
+pub struct MineRequest {
+    pub blocks: Option<u32>,
+    pub source: Option<MineSource>,
+    #[serde(default)]
+    pub txids: Vec<String>,
+    pub address: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct BroadcastRequest {
+    pub hex: Option<String>,
+    pub psbt: Option<String>, // base64, finalized by bitcoind
+    #[serde(default)]
+    pub commit: bool,
+}
+
+#[derive(Deserialize)]
+pub struct BeadRequest {
+    pub header: Option<String>, // 80-byte block header as hex; the bead's hash and target
+    pub hash: Option<String>,   // required without a header, checked against it otherwise
+    #[serde(default)]
+    pub parents: Vec<String>,
+    #[serde(default)]
+    pub transactions: Vec<String>,
+    pub miner: String,
+    pub payout_address: Option<String>,
+    pub timestamp: Option<u64>, // defaults to the time it is received
+}
+
+#[derive(Deserialize)]
+pub struct BeadQuery {
+    pub miner: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct CommitQuery {
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct ArchiveQuery {
+    pub category: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct SubmitBlockRequest {
+    pub hex: String,
 }
 
-static STATE: Lazy<Mutex<StateStore>> = Lazy::new(|| Mutex::new(StateStore::new()));
 static SEEN: Lazy<Mutex<HashMap<Txid, u64>>> = Lazy::new(|| Mutex::new(HashMap::new()));
 
+// Page size limits for `/archive`
+const DEFAULT_ARCHIVE_LIMIT: usize = 100;
+const MAX_ARCHIVE_LIMIT: usize = 1_000;
+
+// Page size limits for `/beads` and `/beads/{hash}/ancestors`
+const DEFAULT_BEAD_LIMIT: usize = 100;
+const MAX_BEAD_LIMIT: usize = 10_000;
+
+// Longest proposer id and note kept with a propose or schedule
+const MAX_PROPOSER_LEN: usize = 64;
+const MAX_PROPOSAL_NOTE_LEN: usize = 500;
+
+// Operator signature on a transition; see `operators::digest`
+const OPERATOR_KEY_HEADER: &str = "x-operator-key";
+const NONCE_HEADER: &str = "x-nonce";
+const TIMESTAMP_HEADER: &str = "x-timestamp";
+const SIGNATURE_HEADER: &str = "x-signature";
+
+// How often the chain watcher polls for a new tip
+const CHAIN_POLL_SECS: u64 = 5;
+
+// How often pipeline state is checked against the nodes
+const RECONCILE_INTERVAL_SECS: u64 = 30;
+
+// Upper bound for one call to the regtest mining endpoint
+const MAX_MINE_BLOCKS: u32 = 100;
+
+// Node labels used for RPC metrics
+const BITCOIND: &str = "bitcoind";
+const CMEMPOOL: &str = "cmempool";
+
+// Core has no RPC to evict a mempool entry, so an uncommitted tx is pushed
+// below any block template by this fee delta (1 BTC) until it is recommitted
+const WITHDRAW_FEE_DELTA: i64 = 100_000_000;
+
+const CATEGORIES: [&str; 7] = [
+    "Mempool",
+    "Committed",
+    "Proposed",
+    "Scheduled",
+    "Confirmed",
+    "Replaced",
+    "Unknown",
+];
+
 fn connect_to_bitcoind() -> Client {
     Client::new(
         "http://127.0.0.1:18332",
@@ -98,33 +256,75 @@ fn to_btc_from_sats(sats: u64) -> f64 {
     (sats as f64) / 100_000_000.0
 }
 
-fn detect_category(txid: &Txid, in_std: bool, in_cpool: bool, confirmations: u32) -> String {
-    // Priority: Confirmed > Scheduled > Proposed > Committed > Mempool
-
-    if confirmations > 0 {
-        return "Confirmed".to_string();
-    }
+fn parse_txid(txid: &str) -> Result<Txid, (StatusCode, Json<serde_json::Value>)> {
+    txid.parse::<Txid>().map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "status":"error",
+                "code":"invalid_txid",
+                "error":format!("invalid txid: {e}")
+            })),
+        )
+    })
+}
 
-    let state = STATE.lock().unwrap();
+fn prioritise(committed: &Client, txid: &Txid, fee_delta: i64) -> bool {
+    time_rpc(CMEMPOOL, "prioritisetransaction", || {
+        committed.call::<bool>(
+            "prioritisetransaction",
+            &[json!(txid.to_string()), json!(0), json!(fee_delta)],
+        )
+    })
+    .unwrap_or(false)
+}
 
+// Pipeline category of an unconfirmed tx; None once it is in neither mempool
+// nor any stage. Reads state only, so `/metrics` can use it too.
+fn stage_category(
+    state: &StateStore,
+    txid: &Txid,
+    in_std: bool,
+    in_cpool: bool,
+    in_bead: bool,
+) -> Option<&'static str> {
     // Stage 4: Scheduled (highest priority for unconfirmed)
     if state.scheduled.contains(txid) {
-        return "Scheduled".to_string();
+        return Some("Scheduled");
     }
 
     // Stage 3: Proposed
     if state.proposed.contains(txid) {
-        return "Proposed".to_string();
+        return Some("Proposed");
     }
 
-    // Stage 2: Committed (in cmempool_node)
-    if in_cpool || state.committed.contains(txid) {
-        return "Committed".to_string();
+    // Stage 2: Committed (in cmempool_node, or included in a bead while still
+    // unconfirmed), unless it was uncommitted since
+    if !state.withdrawn.contains(txid)
+        && (in_cpool || state.committed.contains(txid) || (in_bead && in_std))
+    {
+        return Some("Committed");
     }
 
     // Stage 1: Mempool (in bitcoind_node only)
     if in_std {
-        return "Mempool".to_string();
+        return Some("Mempool");
+    }
+
+    None
+}
+
+fn detect_category(txid: &Txid, in_std: bool, in_cpool: bool, confirmations: u32) -> String {
+    // Priority: Confirmed > Scheduled > Proposed > Committed > Mempool
+
+    if confirmations > 0 {
+        return "Confirmed".to_string();
+    }
+
+    let in_bead = braid::attribution(txid).is_some();
+    let category = stage_category(&STATE.lock().unwrap(), txid, in_std, in_cpool, in_bead);
+    if let Some(category) = category {
+        return category.to_string();
     }
 
     // Replaced
@@ -136,20 +336,74 @@ fn detect_category(txid: &Txid, in_std: bool, in_cpool: bool, confirmations: u32
     "Unknown".to_string()
 }
 
-fn record_seen(txid: &Txid, in_any_mempool: bool, ts: u64) {
+// Stamped with the last time the tx was in a mempool, so GC evicts least recently seen first
+fn record_seen(txid: &Txid, in_any_mempool: bool, now: u64) {
     if in_any_mempool {
-        SEEN.lock().unwrap().insert(*txid, ts);
+        SEEN.lock().unwrap().insert(*txid, now);
+    }
+}
+
+// Feeds what build_tx observed into the timeline; returns the confirming height if known
+fn observe_timeline(
+    txid: Txid,
+    std_time: Option<u64>,
+    cpool_time: Option<u64>,
+    confirmed_in: Option<BlockHash>,
+    block_time: Option<u64>,
+    category: &str,
+    standard: &Client,
+) -> Option<u64> {
+    if let Some(t) = std_time {
+        timeline::record(txid, Event::SeenBitcoind(t));
+    }
+    if let Some(t) = cpool_time {
+        timeline::record(txid, Event::SeenCmempool(t));
+    }
+    if category == "Replaced" {
+        timeline::record(txid, Event::Dropped(now_ts()));
+    }
+
+    let known_height = timeline::get(&txid).and_then(|t| t.confirmed_height);
+    match confirmed_in {
+        Some(hash) if known_height.is_none() => {
+            let height = time_rpc(BITCOIND, "getblockheader", || {
+                standard.get_block_header_info(&hash)
+            })
+            .ok()?
+            .height as u64;
+            timeline::record(
+                txid,
+                Event::Confirmed {
+                    height,
+                    time: block_time.unwrap_or_else(now_ts),
+                },
+            );
+            Some(height)
+        }
+        Some(_) => known_height,
+        None => None,
     }
 }
 
 fn build_tx(txid: Txid, standard: &Client, committed: &Client) -> ApiTransaction {
-    let in_std_entry = standard.get_mempool_entry(&txid).ok();
-    let in_cpool_entry = committed.get_mempool_entry(&txid).ok();
+    let in_std_entry = time_rpc(BITCOIND, "getmempoolentry", || {
+        standard.get_mempool_entry(&txid)
+    })
+    .ok();
+    let in_cpool_entry = time_rpc(CMEMPOOL, "getmempoolentry", || {
+        committed.get_mempool_entry(&txid)
+    })
+    .ok();
 
-    let raw_info = standard
-        .get_raw_transaction_info(&txid, None)
-        .or_else(|_| committed.get_raw_transaction_info(&txid, None))
-        .ok();
+    let raw_info = time_rpc(BITCOIND, "getrawtransaction", || {
+        standard.get_raw_transaction_info(&txid, None)
+    })
+    .or_else(|_| {
+        time_rpc(CMEMPOOL, "getrawtransaction", || {
+            committed.get_raw_transaction_info(&txid, None)
+        })
+    })
+    .ok();
 
     let (confirmations, block_hash, block_time, vsize_raw, inputs, outputs) =
         if let Some(info) = &raw_info {
@@ -177,33 +431,109 @@ fn build_tx(txid: Txid, standard: &Client, committed: &Client) -> ApiTransaction
         (vsize_raw, 0, None, false)
     };
 
-    let fee_rate = if vsize > 0 {
-        (fee_sats as f64) / (vsize as f64)
-    } else {
-        0.0
-    };
-
     let in_std = in_std_entry.is_some();
     let in_cpool = in_cpool_entry.is_some();
     let category = detect_category(&txid, in_std, in_cpool, confirmations);
 
-    let ts = ts_mempool_time.or(block_time).unwrap_or_else(now_ts);
-    record_seen(&txid, in_std || in_cpool, ts);
+    record_seen(&txid, in_std || in_cpool, now_ts());
 
-    // Cleanup if confirmed
-    if confirmations > 0 {
-        let mut state = STATE.lock().unwrap();
-        state.committed.remove(&txid);
-        state.proposed.remove(&txid);
-        state.scheduled.remove(&txid);
+    let block_height = observe_timeline(
+        txid,
+        in_std_entry.as_ref().map(|e| e.time),
+        in_cpool_entry.as_ref().map(|e| e.time),
+        raw_info.as_ref().and_then(|i| i.blockhash),
+        block_time,
+        &category,
+        standard,
+    );
+
+    // Confirmed txs are cleared by the chain watcher when their block
+    // connects, after it is audited; the reconciler catches any it misses
+    let version = STATE.lock().unwrap().version(&txid);
+
+    // Keep fee data while the tx is live and freeze it once it leaves
+    let now = now_ts();
+    if let Some(e) = mempool {
+        archive::observe(
+            txid,
+            archive::Observed {
+                category: category.clone(),
+                fee_sats,
+                vsize,
+                weight: e.weight,
+                inputs,
+                outputs,
+                rbf_signaled,
+            },
+            now,
+        );
+    } else if category == "Replaced" {
+        let last_stage = STATE.lock().unwrap().stage_of(&txid).name();
+        archive::finalize(
+            &txid,
+            archive::Final {
+                category: "Replaced",
+                last_stage: Some(last_stage),
+                block_hash: None,
+                block_height: None,
+                block_time: None,
+                timeline: timeline::get(&txid),
+            },
+            now,
+        );
     }
 
+    // Out of both mempools: fill what the nodes no longer know from the archive
+    let archived = mempool.is_none().then(|| archive::get(&txid)).flatten();
+    let weight = mempool.and_then(|e| e.weight);
+    let (category, fee_sats, vsize, weight, inputs, outputs, rbf_signaled) = match &archived {
+        Some(a) => (
+            if category == "Unknown" {
+                a.category.clone()
+            } else {
+                category
+            },
+            if fee_sats == 0 { a.fee_sats } else { fee_sats },
+            if vsize == 0 { a.vsize } else { vsize },
+            weight.or(a.weight),
+            if raw_info.is_none() { a.inputs } else { inputs },
+            if raw_info.is_none() {
+                a.outputs
+            } else {
+                outputs
+            },
+            a.rbf_signaled,
+        ),
+        None => (
+            category,
+            fee_sats,
+            vsize,
+            weight,
+            inputs,
+            outputs,
+            rbf_signaled,
+        ),
+    };
+    let block_hash = block_hash.or_else(|| archived.as_ref()?.block_hash.clone());
+    let block_height = block_height.or_else(|| archived.as_ref()?.block_height);
+    let block_time = block_time.or_else(|| archived.as_ref()?.block_time);
+
+    let fee_rate = if vsize > 0 {
+        (fee_sats as f64) / (vsize as f64)
+    } else {
+        0.0
+    };
+    let attribution = braid::attribution(&txid);
+    let proposal = matches!(category.as_str(), "Proposed" | "Scheduled")
+        .then(|| timeline::get(&txid)?.current_proposal().cloned())
+        .flatten();
+
     ApiTransaction {
         txid: txid.to_string(),
         hash: txid.to_string(),
         category,
         size: vsize,
-        weight: None,
+        weight,
         fee: to_btc_from_sats(fee_sats),
         fee_rate,
         inputs,
@@ -211,21 +541,27 @@ fn build_tx(txid: Txid, standard: &Client, committed: &Client) -> ApiTransaction
         confirmations,
         timestamp: ts_mempool_time.or(block_time),
         rbf_signaled,
+        version,
+        bead: attribution.map(|a| a.bead.to_string()),
+        cohort: attribution.map(|a| a.cohort),
+        proposal,
         status: ApiStatus {
             confirmed: confirmations > 0,
-            block_height: None,
+            block_height,
             block_hash,
             block_time,
         },
     }
 }
 
-pub async fn get_transactions() -> Json<Vec<ApiTransaction>> {
+async fn collect_transactions() -> Vec<ApiTransaction> {
     let standard = Arc::new(connect_to_bitcoind());
     let committed = Arc::new(connect_to_cmempoold());
 
-    let std_txids = standard.get_raw_mempool().unwrap_or_default();
-    let cpool_txids = committed.get_raw_mempool().unwrap_or_default();
+    let std_txids =
+        time_rpc(BITCOIND, "getrawmempool", || standard.get_raw_mempool()).unwrap_or_default();
+    let cpool_txids =
+        time_rpc(CMEMPOOL, "getrawmempool", || committed.get_raw_mempool()).unwrap_or_default();
     let set: BTreeSet<_> = std_txids
         .into_iter()
         .chain(cpool_txids.into_iter())
@@ -241,63 +577,337 @@ pub async fn get_transactions() -> Json<Vec<ApiTransaction>> {
         .collect::<Vec<_>>()
         .await;
 
-    Json(results)
+    results
 }
 
-// Stage 1 -> 2: Mempool → Committed
-pub async fn commit_transaction(Path(txid): Path<String>) -> (StatusCode, Json<serde_json::Value>) {
-    let standard = connect_to_bitcoind();
-    let committed = connect_to_cmempoold();
+pub async fn get_transactions() -> Json<Vec<ApiTransaction>> {
+    Json(collect_transactions().await)
+}
+
+// Records the outcome of a transition handler, keyed by the `code` in error bodies
+fn observe_transition(
+    transition: &str,
+    resp: (StatusCode, Json<serde_json::Value>),
+) -> (StatusCode, Json<serde_json::Value>) {
+    let success = resp.0.is_success();
+    let code = if success {
+        "ok"
+    } else {
+        resp.1["code"].as_str().unwrap_or("unknown")
+    };
+    metrics::record_transition(transition, success, code);
+    resp
+}
+
+// Signature headers as sent; batches pass each tx's signature from the body
+fn signed_request(
+    headers: &HeaderMap,
+    signature: Option<&str>,
+) -> Result<SignedRequest, SignatureError> {
+    let get = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string())
+    };
+    let signature = signature
+        .map(str::to_string)
+        .or_else(|| get(SIGNATURE_HEADER));
+    match (
+        get(OPERATOR_KEY_HEADER),
+        get(NONCE_HEADER),
+        get(TIMESTAMP_HEADER),
+        signature,
+    ) {
+        (None, None, None, None) => Err(SignatureError::Missing),
+        (Some(pubkey), Some(nonce), Some(timestamp), Some(signature)) => Ok(SignedRequest {
+            pubkey,
+            nonce,
+            timestamp: timestamp.parse().map_err(|_| {
+                SignatureError::Malformed(format!("{TIMESTAMP_HEADER} must be unix seconds"))
+            })?,
+            signature,
+        }),
+        _ => Err(SignatureError::Malformed(format!(
+            "Signed requests need {OPERATOR_KEY_HEADER}, {NONCE_HEADER}, {TIMESTAMP_HEADER} and a signature"
+        ))),
+    }
+}
+
+fn signature_error(e: SignatureError) -> (StatusCode, Json<serde_json::Value>) {
+    let status = match e {
+        SignatureError::UnknownOperator => StatusCode::FORBIDDEN,
+        SignatureError::NonceReused => StatusCode::CONFLICT,
+        _ => StatusCode::UNAUTHORIZED,
+    };
+    (
+        status,
+        Json(json!({"status":"error","code":e.code(),"error":e.message()})),
+    )
+}
+
+// Operator id and the request they signed
+type Signer = (String, SignedRequest);
+
+/// Verifies the operator signature on a transition request.
+///
+/// Unsigned requests are let through until an operator is registered;
+/// returns who signed, to be logged with the outcome.
+fn authorize(
+    txid: &str,
+    transition: Transition,
+    headers: &HeaderMap,
+    signature: Option<&str>,
+) -> Result<Option<Signer>, (StatusCode, Json<serde_json::Value>)> {
+    verify_signed(
+        &txid.to_ascii_lowercase(),
+        transition.name(),
+        headers,
+        signature,
+    )
+}
+
+/// Verifies the operator signature on a change to `subject`'s settings.
+///
+/// Signed like a transition with `subject` in place of the txid, e.g.
+/// `policy` and `update`; open until an operator is registered.
+fn authorize_settings(
+    subject: &str,
+    action: &'static str,
+    headers: &HeaderMap,
+) -> Result<Option<Signer>, (StatusCode, Json<serde_json::Value>)> {
+    verify_signed(subject, action, headers, None)
+}
+
+fn verify_signed(
+    subject: &str,
+    action: &str,
+    headers: &HeaderMap,
+    signature: Option<&str>,
+) -> Result<Option<Signer>, (StatusCode, Json<serde_json::Value>)> {
+    if !operators::required() {
+        return Ok(None);
+    }
+    let req = signed_request(headers, signature).map_err(signature_error)?;
+    let operator = operators::verify(subject, action, &req, now_ts()).map_err(signature_error)?;
+    Ok(Some((operator, req)))
+}
+
+fn record_signed(txid: &str, transition: Transition, signer: Option<Signer>, status: u16) {
+    record_signed_as(
+        &txid.to_ascii_lowercase(),
+        transition.name(),
+        signer,
+        status,
+    );
+}
+
+// Settings changes are logged next to transitions, the subject as the txid
+fn record_signed_as(subject: &str, action: &'static str, signer: Option<Signer>, status: u16) {
+    let Some((operator, req)) = signer else {
+        return;
+    };
+    operators::record(operators::SignedTransition {
+        txid: subject.to_string(),
+        transition: action,
+        operator,
+        pubkey: req.pubkey,
+        nonce: req.nonce,
+        timestamp: req.timestamp,
+        signature: req.signature,
+        status,
+        at: now_ts(),
+    });
+}
 
-    let txid = match txid.parse::<Txid>() {
+/// Counts the signer's vote when quorum mode covers `transition`.
+///
+/// Returns the response to send instead of running the transition, i.e.
+/// while fewer than the threshold have voted; None once the quorum is in.
+/// Without `record` the vote is only counted, not kept: an atomic batch
+/// may still roll back, and once it goes through the votes are cleared.
+fn quorum_gate(
+    txid: &str,
+    transition: Transition,
+    signer: Option<&Signer>,
+    record: bool,
+) -> Option<(StatusCode, Json<serde_json::Value>)> {
+    let settings = quorum::SETTINGS.lock().unwrap().clone();
+    if !settings.enabled || !matches!(transition, Transition::Propose | Transition::Schedule) {
+        return None;
+    }
+    let txid = match parse_txid(txid) {
         Ok(t) => t,
-        Err(e) => {
-            return (
+        Err(resp) => return Some(resp),
+    };
+    let Some((operator, _)) = signer else {
+        return Some(signature_error(SignatureError::Missing));
+    };
+
+    let tally = if record {
+        quorum::cast(txid, transition.name(), operator, now_ts(), &settings)
+    } else {
+        quorum::preview(txid, transition.name(), operator, now_ts(), &settings)
+    };
+    if tally.reached {
+        return None;
+    }
+    Some((
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "status":"pending",
+            "txid":txid.to_string(),
+            "message":format!(
+                "Vote {}, {} of {} needed to {}",
+                if record { "recorded" } else { "not recorded by an atomic batch" },
+                tally.count,
+                tally.needed,
+                transition.name()
+            ),
+            "tally":tally
+        })),
+    ))
+}
+
+// Reads an `If-Match` version; `*` or no header means "any version"
+fn expected_version(
+    headers: &HeaderMap,
+) -> Result<Option<u64>, (StatusCode, Json<serde_json::Value>)> {
+    let Some(value) = headers.get(header::IF_MATCH) else {
+        return Ok(None);
+    };
+
+    let raw = value.to_str().unwrap_or("").trim();
+    if raw == "*" {
+        return Ok(None);
+    }
+
+    raw.trim_start_matches("W/")
+        .trim_matches('"')
+        .parse::<u64>()
+        .map(Some)
+        .map_err(|_| {
+            (
                 StatusCode::BAD_REQUEST,
-                Json(json!({"status":"error","error":format!("invalid txid: {e}")})),
+                Json(json!({
+                    "status":"error",
+                    "code":"invalid_if_match",
+                    "error":format!("If-Match must be a tx version, got {raw:?}")
+                })),
+            )
+        })
+}
+
+fn transition_error(txid: &Txid, err: TransitionError) -> (StatusCode, Json<serde_json::Value>) {
+    let (status, code, error) = match err {
+        TransitionError::VersionMismatch { current } => {
+            return (
+                StatusCode::PRECONDITION_FAILED,
+                Json(json!({
+                    "status":"error",
+                    "code":"version_mismatch",
+                    "error":"Transaction changed since the given version",
+                    "txid":txid.to_string(),
+                    "version":current
+                })),
             )
         }
+        TransitionError::CapacityExceeded { needed, remaining } => {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({
+                    "status":"error",
+                    "code":"capacity_exceeded",
+                    "error":"Transaction does not fit in the remaining block capacity",
+                    "txid":txid.to_string(),
+                    "needed":needed,
+                    "remaining":remaining
+                })),
+            )
+        }
+        TransitionError::NotCommitted => (
+            StatusCode::BAD_REQUEST,
+            "not_committed",
+            "Transaction must be committed first",
+        ),
+        TransitionError::NotProposed => (
+            StatusCode::BAD_REQUEST,
+            "not_proposed",
+            "Transaction must be proposed first",
+        ),
+        TransitionError::NotScheduled => (
+            StatusCode::BAD_REQUEST,
+            "not_scheduled",
+            "Transaction is not scheduled",
+        ),
+        TransitionError::StillProposed => (
+            StatusCode::CONFLICT,
+            "proposed",
+            "Transaction must be unproposed first",
+        ),
+        TransitionError::StillScheduled => (
+            StatusCode::CONFLICT,
+            "scheduled",
+            "Transaction must be unscheduled first",
+        ),
     };
 
-    // Check if already in cmempool
-    if committed.get_mempool_entry(&txid).is_ok() {
-        STATE.lock().unwrap().committed.insert(txid);
-        return (
-            StatusCode::OK,
-            Json(json!({
-                "status":"ok",
-                "txid":txid.to_string(),
-                "message":"Transaction already committed"
-            })),
-        );
+    (
+        status,
+        Json(json!({"status":"error","code":code,"error":error})),
+    )
+}
+
+// Makes sure the cmempool node holds the tx, relaying it from bitcoind if needed.
+// Returns whether it was already there.
+fn ensure_in_cmempool(
+    txid: &Txid,
+    standard: &Client,
+    committed: &Client,
+) -> Result<bool, (StatusCode, Json<serde_json::Value>)> {
+    if time_rpc(CMEMPOOL, "getmempoolentry", || {
+        committed.get_mempool_entry(txid)
+    })
+    .is_ok()
+    {
+        return Ok(true);
     }
 
     // Get transaction from bitcoind
-    let tx: Transaction = match standard.get_raw_transaction(&txid, None) {
+    let tx: Transaction = match time_rpc(BITCOIND, "getrawtransaction", || {
+        standard.get_raw_transaction(txid, None)
+    }) {
         Ok(t) => t,
         Err(e) => {
-            return (
+            return Err((
                 StatusCode::NOT_FOUND,
-                Json(json!({"status":"error","error":format!("Transaction not found: {e}")})),
-            )
+                Json(json!({
+                    "status":"error",
+                    "code":"tx_not_found",
+                    "error":format!("Transaction not found: {e}")
+                })),
+            ))
         }
     };
 
-    let std_height = standard.get_block_count().unwrap_or(0);
-    let cm_height = committed.get_block_count().unwrap_or(0);
+    let std_height =
+        time_rpc(BITCOIND, "getblockcount", || standard.get_block_count()).unwrap_or(0);
+    let cm_height =
+        time_rpc(CMEMPOOL, "getblockcount", || committed.get_block_count()).unwrap_or(0);
 
     // Send to cmempool
-    match committed.send_raw_transaction(&tx) {
-        Ok(_) => {
-            STATE.lock().unwrap().committed.insert(txid);
-            (
-                StatusCode::OK,
-                Json(json!({
-                    "status":"ok",
-                    "txid":txid.to_string(),
-                    "message":"Transaction committed to cmempool"
-                })),
-            )
+    match time_rpc(CMEMPOOL, "sendrawtransaction", || {
+        committed.send_raw_transaction(&tx)
+    }) {
+        Ok(_) => Ok(false),
+        // A concurrent commit may have relayed it first
+        Err(_)
+            if time_rpc(CMEMPOOL, "getmempoolentry", || {
+                committed.get_mempool_entry(txid)
+            })
+            .is_ok() =>
+        {
+            Ok(true)
         }
         Err(e) => {
             let error_str = e.to_string();
@@ -307,120 +917,2869 @@ pub async fn commit_transaction(Path(txid): Path<String>) -> (StatusCode, Json<s
                 "cmempool_height": cm_height,
             });
 
-            if error_str.contains("-25") || error_str.contains("missing inputs") {
+            let code = if error_str.contains("-25") || error_str.contains("missing inputs") {
                 diagnostics["hint"] = json!("Nodes not synchronized");
-            }
+                "missing_inputs"
+            } else {
+                "rejected"
+            };
 
-            (
+            Err((
                 StatusCode::BAD_REQUEST,
-                Json(json!({"status":"error","diagnostics":diagnostics})),
-            )
+                Json(json!({"status":"error","code":code,"diagnostics":diagnostics})),
+            ))
         }
     }
 }
 
-// Stage 2 -> 3: Committed → Proposed
-pub async fn propose_transaction(
-    Path(txid): Path<String>,
-) -> (StatusCode, Json<serde_json::Value>) {
-    let committed = connect_to_cmempoold();
+// Facts gathered from the nodes before the state lock is taken
+struct Prepared {
+    in_cpool: bool,
+    already_committed: bool,
+}
 
-    let txid = match txid.parse::<Txid>() {
-        Ok(t) => t,
-        Err(e) => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(json!({"status":"error","error":format!("invalid txid: {e}")})),
-            )
+fn prepare_transition(
+    txid: &Txid,
+    transition: Transition,
+    standard: &Client,
+    committed: &Client,
+) -> Result<Prepared, (StatusCode, Json<serde_json::Value>)> {
+    match transition {
+        Transition::Commit => {
+            let already_committed = ensure_in_cmempool(txid, standard, committed)?;
+            Ok(Prepared {
+                in_cpool: true,
+                already_committed,
+            })
         }
-    };
-
-    // Must be committed first
-    let state = STATE.lock().unwrap();
-    if !state.committed.contains(&txid) {
-        // Check if in cmempool
-        if committed.get_mempool_entry(&txid).is_err() {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(json!({
-                    "status":"error",
-                    "error":"Transaction must be committed first"
-                })),
-            );
+        Transition::Propose | Transition::Uncommit => Ok(Prepared {
+            in_cpool: time_rpc(CMEMPOOL, "getmempoolentry", || {
+                committed.get_mempool_entry(txid)
+            })
+            .is_ok(),
+            already_committed: false,
+        }),
+        Transition::Schedule => {
+            ensure_cost_known(txid, standard, committed)?;
+            Ok(Prepared {
+                in_cpool: false,
+                already_committed: false,
+            })
         }
+        _ => Ok(Prepared {
+            in_cpool: false,
+            already_committed: false,
+        }),
     }
-    drop(state);
+}
 
-    STATE.lock().unwrap().proposed.insert(txid);
+// Scripts spent by the inputs of `tx`, from the parent tx or else the UTXO
+// set; None where neither node has it
+fn spent_scripts(
+    tx: &Transaction,
+    standard: &Client,
+    committed: &Client,
+) -> Vec<Option<ScriptBuf>> {
+    let mut parents: HashMap<Txid, Option<Transaction>> = HashMap::new();
+    tx.input
+        .iter()
+        .map(|input| {
+            let prev = input.previous_output;
+            let parent = parents.entry(prev.txid).or_insert_with(|| {
+                time_rpc(CMEMPOOL, "getrawtransaction", || {
+                    committed.get_raw_transaction(&prev.txid, None)
+                })
+                .or_else(|_| {
+                    time_rpc(BITCOIND, "getrawtransaction", || {
+                        standard.get_raw_transaction(&prev.txid, None)
+                    })
+                })
+                .ok()
+            });
+            if let Some(out) = parent
+                .as_ref()
+                .and_then(|p| p.output.get(prev.vout as usize))
+            {
+                return Some(out.script_pubkey.clone());
+            }
+            time_rpc(BITCOIND, "gettxout", || {
+                standard.get_tx_out(&prev.txid, prev.vout, Some(false))
+            })
+            .ok()
+            .flatten()
+            .map(|out| ScriptBuf::from(out.script_pub_key.hex))
+        })
+        .collect()
+}
 
-    (
-        StatusCode::OK,
-        Json(json!({
-            "status":"ok",
-            "txid":txid.to_string(),
-            "message":"Transaction proposed"
-        })),
-    )
+// Block weight and sigop cost of `tx`, with P2SH and witness sigops counted
+fn resolved_cost(tx: &Transaction, standard: &Client, committed: &Client) -> capacity::Cost {
+    capacity::tx_cost(tx, &spent_scripts(tx, standard, committed))
 }
 
-// Stage 3 -> 4: Proposed → Scheduled
-pub async fn schedule_transaction(
-    Path(txid): Path<String>,
-) -> (StatusCode, Json<serde_json::Value>) {
-    let txid = match txid.parse::<Txid>() {
-        Ok(t) => t,
-        Err(e) => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(json!({"status":"error","error":format!("invalid txid: {e}")})),
-            )
-        }
-    };
+// Looks up the block weight, sigops and fee of a tx about to be scheduled
+fn ensure_cost_known(
+    txid: &Txid,
+    standard: &Client,
+    committed: &Client,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    if STATE.lock().unwrap().costs.contains_key(txid) {
+        return Ok(());
+    }
 
-    // Must be proposed first
-    if !STATE.lock().unwrap().proposed.contains(&txid) {
-        return (
-            StatusCode::BAD_REQUEST,
+    let tx = time_rpc(CMEMPOOL, "getrawtransaction", || {
+        committed.get_raw_transaction(txid, None)
+    })
+    .or_else(|_| {
+        time_rpc(BITCOIND, "getrawtransaction", || {
+            standard.get_raw_transaction(txid, None)
+        })
+    })
+    .map_err(|e| {
+        (
+            StatusCode::NOT_FOUND,
             Json(json!({
                 "status":"error",
-                "error":"Transaction must be proposed first"
+                "code":"tx_not_found",
+                "error":format!("Transaction not found: {e}")
             })),
-        );
+        )
+    })?;
+
+    let fee = time_rpc(CMEMPOOL, "getmempoolentry", || {
+        committed.get_mempool_entry(txid)
+    })
+    .or_else(|_| {
+        time_rpc(BITCOIND, "getmempoolentry", || {
+            standard.get_mempool_entry(txid)
+        })
+    })
+    .map(|e| to_sats(e.fees.base));
+
+    let cost = resolved_cost(&tx, standard, committed);
+    let mut state = STATE.lock().unwrap();
+    state.costs.insert(*txid, cost);
+    if let Ok(fee) = fee {
+        state.fees.insert(*txid, fee);
+    }
+    Ok(())
+}
+
+// Queued txs that were scheduled once capacity freed up
+fn record_promoted(promoted: &[Txid]) {
+    let now = now_ts();
+    for txid in promoted {
+        timeline::record(*txid, Event::Scheduled(now));
+    }
+}
+
+// Side effects once a state change is in; returns the success body
+fn finish_transition(
+    txid: Txid,
+    transition: Transition,
+    prepared: &Prepared,
+    applied: &Applied,
+    reason: Option<String>,
+    proposal: Option<ProposalRequest>,
+    committed: &Client,
+) -> serde_json::Value {
+    let message = match transition {
+        Transition::Commit if prepared.already_committed => "Transaction already committed",
+        Transition::Schedule if applied.queued => {
+            "Transaction queued until block capacity frees up"
+        }
+        Transition::Commit => "Transaction committed to cmempool",
+        Transition::Propose => "Transaction proposed",
+        Transition::Schedule => "Transaction scheduled",
+        Transition::Uncommit => "Transaction uncommitted",
+        Transition::Unpropose => "Transaction unproposed",
+        Transition::Unschedule => "Transaction unscheduled",
+    };
+
+    let mut body = json!({
+        "status":"ok",
+        "txid":txid.to_string(),
+        "version":applied.version,
+        "message":message
+    });
+
+    let now = now_ts();
+    match transition {
+        Transition::Commit => {
+            if applied.was_withdrawn {
+                prioritise(committed, &txid, WITHDRAW_FEE_DELTA);
+            }
+            timeline::record(txid, Event::Committed(now));
+        }
+        Transition::Propose => {
+            quorum::clear(&txid, transition.name());
+            timeline::record(txid, Event::Proposed(now));
+        }
+        Transition::Schedule if applied.queued => {
+            quorum::clear(&txid, transition.name());
+            body["queued"] = json!(true);
+        }
+        Transition::Schedule => {
+            quorum::clear(&txid, transition.name());
+            timeline::record(txid, Event::Scheduled(now));
+        }
+        Transition::Uncommit => {
+            // Still in cmempool, only pushed below any template
+            body["deprioritised"] =
+                json!(prepared.in_cpool && prioritise(committed, &txid, -WITHDRAW_FEE_DELTA));
+        }
+        Transition::Unpropose | Transition::Unschedule => {}
+    }
+
+    if !applied.promoted.is_empty() {
+        record_promoted(&applied.promoted);
+        body["promoted"] = json!(applied
+            .promoted
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>());
+    }
+
+    if let Some(p) = proposal {
+        let proposal = timeline::Proposal {
+            transition: transition.name(),
+            proposer: p.proposer,
+            bead: p.bead,
+            note: p.note,
+            target_height: p.target_height,
+            at: now,
+            withdrawn_at: None,
+        };
+        body["proposal"] = json!(proposal);
+        timeline::record(txid, Event::Proposal(proposal));
+    }
+
+    if transition.is_reverse() {
+        timeline::record(
+            txid,
+            Event::Reverted {
+                transition: transition.name(),
+                reason,
+                at: now,
+            },
+        );
+    }
+
+    body
+}
+
+/// Drives one transition through the state machine.
+///
+/// Node lookups and relays happen first, then guards, version check and
+/// the state change are applied under a single `STATE` lock. A commit that
+/// relayed the tx and then lost the version check leaves it withdrawn, as
+/// an uncommit would, rather than committed in all but name.
+fn run_transition(
+    txid: &str,
+    transition: Transition,
+    expected: Option<u64>,
+    reason: Option<String>,
+    proposal: Option<ProposalRequest>,
+    standard: &Client,
+    committed: &Client,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let txid = match parse_txid(txid) {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
+
+    // Fail fast on a stale version before touching either node
+    if let Some(v) = expected {
+        let current = STATE.lock().unwrap().version(&txid);
+        if v != current {
+            return transition_error(&txid, TransitionError::VersionMismatch { current });
+        }
+    }
+
+    let prepared = match prepare_transition(&txid, transition, standard, committed) {
+        Ok(p) => p,
+        Err(resp) => return resp,
+    };
+
+    let (result, withdrawn) = {
+        let mut state = STATE.lock().unwrap();
+        let result = state.apply(txid, transition, prepared.in_cpool, expected, now_ts());
+        let relayed = transition == Transition::Commit && !prepared.already_committed;
+        let undo = result.is_err() && relayed && state.withdraw(txid);
+        (result, undo)
+    };
+    let applied = match result {
+        Ok(a) => a,
+        Err(e) => {
+            if withdrawn {
+                prioritise(committed, &txid, -WITHDRAW_FEE_DELTA);
+            }
+            return transition_error(&txid, e);
+        }
+    };
+
+    let body = finish_transition(
+        txid, transition, &prepared, &applied, reason, proposal, committed,
+    );
+    (StatusCode::OK, Json(body))
+}
+
+fn transition(
+    txid: &str,
+    transition: Transition,
+    headers: &HeaderMap,
+    reason: Option<String>,
+    proposal: Option<ProposalRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let standard = connect_to_bitcoind();
+    let committed = connect_to_cmempoold();
+    let signer = expected_version(headers)
+        .and_then(|expected| Ok((expected, authorize(txid, transition, headers, None)?)));
+    let resp = match signer {
+        Ok((expected, signer)) => {
+            let resp = match quorum_gate(txid, transition, signer.as_ref(), true) {
+                Some(pending) => pending,
+                None => run_transition(
+                    txid, transition, expected, reason, proposal, &standard, &committed,
+                ),
+            };
+            record_signed(txid, transition, signer, resp.0.as_u16());
+            resp
+        }
+        Err(resp) => resp,
+    };
+    observe_transition(transition.name(), resp)
+}
+
+fn bad_tx_request(code: &str, error: String) -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(json!({"status":"error","code":code,"error":error})),
+    )
+}
+
+// The node's own message, without the JSON-RPC wrapping
+fn rpc_message(e: &bitcoincore_rpc::Error) -> String {
+    match e {
+        bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Rpc(r)) => {
+            r.message.clone()
+        }
+        other => other.to_string(),
+    }
+}
+
+// Raw hex as given, or the tx extracted from a PSBT bitcoind can finalize
+fn broadcast_payload(
+    req: &BroadcastRequest,
+    standard: &Client,
+) -> Result<Transaction, (StatusCode, Json<serde_json::Value>)> {
+    let bytes = match (&req.hex, &req.psbt) {
+        (Some(hex), None) => Vec::<u8>::from_hex(hex.trim())
+            .map_err(|_| bad_tx_request("invalid_hex", "Transaction is not valid hex".into()))?,
+        (None, Some(psbt)) => {
+            let finalized = time_rpc(BITCOIND, "finalizepsbt", || {
+                standard.finalize_psbt(psbt.trim(), Some(true))
+            })
+            .map_err(|e| bad_tx_request("invalid_psbt", rpc_message(&e)))?;
+            match finalized.hex {
+                Some(hex) if finalized.complete => hex,
+                _ => {
+                    return Err(bad_tx_request(
+                        "psbt_incomplete",
+                        "PSBT is missing signatures and cannot be finalized".into(),
+                    ))
+                }
+            }
+        }
+        _ => {
+            return Err(bad_tx_request(
+                "invalid_request",
+                "Provide exactly one of hex or psbt".into(),
+            ))
+        }
+    };
+
+    let tx: Transaction = deserialize(&bytes).map_err(|e| {
+        bad_tx_request(
+            "malformed_tx",
+            format!("Transaction does not deserialize: {e}"),
+        )
+    })?;
+    if tx.is_coinbase() {
+        return Err(bad_tx_request(
+            "malformed_tx",
+            "Coinbase transactions cannot be broadcast".into(),
+        ));
+    }
+    Ok(tx)
+}
+
+/// Validates and broadcasts a raw tx to bitcoind, optionally committing it.
+///
+/// Returns the tx as `/tx/{txid}` would show it; when the broadcast went
+/// through but the commit did not, the commit error comes back alongside.
+pub async fn broadcast_transaction(
+    headers: HeaderMap,
+    Json(req): Json<BroadcastRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let standard = connect_to_bitcoind();
+    let committed = connect_to_cmempoold();
+    let tx = match broadcast_payload(&req, &standard) {
+        Ok(tx) => tx,
+        Err(resp) => return resp,
+    };
+    let txid = tx.txid();
+
+    // A commit must be signed like any other, so check before relaying anything
+    let signer = if req.commit {
+        match authorize(&txid.to_string(), Transition::Commit, &headers, None) {
+            Ok(s) => s,
+            Err(resp) => return observe_transition(Transition::Commit.name(), resp),
+        }
+    } else {
+        None
+    };
+
+    let check = match time_rpc(BITCOIND, "testmempoolaccept", || {
+        standard.test_mempool_accept(&[&tx])
+    }) {
+        Ok(mut r) if !r.is_empty() => r.remove(0),
+        Ok(_) => {
+            return (
+                StatusCode::BAD_GATEWAY,
+                Json(json!({
+                    "status":"error",
+                    "code":"node_unavailable",
+                    "error":"testmempoolaccept returned no result"
+                })),
+            )
+        }
+        Err(e) => {
+            return (
+                StatusCode::BAD_GATEWAY,
+                Json(json!({"status":"error","code":"node_unavailable","error":e.to_string()})),
+            )
+        }
+    };
+
+    let already_known = check
+        .reject_reason
+        .as_deref()
+        .is_some_and(|r| acceptance::Rejection::classify(r) == acceptance::Rejection::AlreadyKnown);
+    if !check.allowed && !already_known {
+        let reason = acceptance::reason(check.reject_reason.as_deref().unwrap_or(""));
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "status":"error",
+                "code":"tx_rejected",
+                "txid":txid.to_string(),
+                "reason":reason
+            })),
+        );
+    }
+
+    if !already_known {
+        if let Err(e) = time_rpc(BITCOIND, "sendrawtransaction", || {
+            standard.send_raw_transaction(&tx)
+        }) {
+            let message = rpc_message(&e);
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "status":"error",
+                    "code":"tx_rejected",
+                    "txid":txid.to_string(),
+                    "reason":acceptance::reason(&message)
+                })),
+            );
+        }
+    }
+
+    let commit = req.commit.then(|| {
+        let resp = run_transition(
+            &txid.to_string(),
+            Transition::Commit,
+            None,
+            None,
+            None,
+            &standard,
+            &committed,
+        );
+        record_signed(
+            &txid.to_string(),
+            Transition::Commit,
+            signer,
+            resp.0.as_u16(),
+        );
+        observe_transition(Transition::Commit.name(), resp)
+    });
+
+    let api_tx = build_tx(txid, &standard, &committed);
+    match commit {
+        Some((status, Json(body))) if !status.is_success() => (
+            StatusCode::MULTI_STATUS,
+            Json(json!({
+                "status":"partial",
+                "transaction":api_tx,
+                "commit":body
+            })),
+        ),
+        _ => (StatusCode::CREATED, Json(json!(api_tx))),
+    }
+}
+
+/// Asks cmempool whether it would take the tx, without relaying it.
+fn check_commit(txid: &str) -> (StatusCode, Json<serde_json::Value>) {
+    let txid = match parse_txid(txid) {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
+    let standard = connect_to_bitcoind();
+    let committed = connect_to_cmempoold();
+
+    let std_height =
+        time_rpc(BITCOIND, "getblockcount", || standard.get_block_count()).unwrap_or(0);
+    let cm_height =
+        time_rpc(CMEMPOOL, "getblockcount", || committed.get_block_count()).unwrap_or(0);
+    let mut body = json!({
+        "status":"ok",
+        "txid":txid.to_string(),
+        "dry_run":true,
+        "bitcoind_height":std_height,
+        "cmempool_height":cm_height
+    });
+
+    if time_rpc(CMEMPOOL, "getmempoolentry", || {
+        committed.get_mempool_entry(&txid)
+    })
+    .is_ok()
+    {
+        body["allowed"] = json!(true);
+        body["already_committed"] = json!(true);
+        return (StatusCode::OK, Json(body));
+    }
+
+    let tx: Transaction = match time_rpc(BITCOIND, "getrawtransaction", || {
+        standard.get_raw_transaction(&txid, None)
+    }) {
+        Ok(t) => t,
+        Err(e) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({
+                    "status":"error",
+                    "code":"tx_not_found",
+                    "error":format!("Transaction not found: {e}")
+                })),
+            )
+        }
+    };
+
+    let result = match time_rpc(CMEMPOOL, "testmempoolaccept", || {
+        committed.test_mempool_accept(&[&tx])
+    }) {
+        Ok(mut r) if !r.is_empty() => r.remove(0),
+        Ok(_) => {
+            return (
+                StatusCode::BAD_GATEWAY,
+                Json(json!({
+                    "status":"error",
+                    "code":"node_unavailable",
+                    "error":"testmempoolaccept returned no result"
+                })),
+            )
+        }
+        Err(e) => {
+            return (
+                StatusCode::BAD_GATEWAY,
+                Json(json!({"status":"error","code":"node_unavailable","error":e.to_string()})),
+            )
+        }
+    };
+
+    body["allowed"] = json!(result.allowed);
+    body["already_committed"] = json!(false);
+    if let Some(vsize) = result.vsize {
+        body["vsize"] = json!(vsize);
+    }
+    if let Some(fees) = result.fees {
+        body["fee_sats"] = json!(to_sats(fees.base));
+    }
+    if let Some(reject) = result.reject_reason.filter(|_| !result.allowed) {
+        let reason = acceptance::reason(&reject);
+        if reason.code == "missing_inputs" && std_height != cm_height {
+            body["hint"] = json!("Nodes not synchronized");
+        }
+        body["reason"] = json!(reason);
+    }
+    (StatusCode::OK, Json(body))
+}
+
+// Stage 1 -> 2: Mempool → Committed
+pub async fn commit_transaction(
+    Path(txid): Path<String>,
+    Query(q): Query<CommitQuery>,
+    headers: HeaderMap,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if q.dry_run.unwrap_or(false) {
+        return check_commit(&txid);
+    }
+    transition(&txid, Transition::Commit, &headers, None, None)
+}
+
+pub async fn check_commit_transaction(
+    Path(txid): Path<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    check_commit(&txid)
+}
+
+// Checks the metadata sent along with a propose or schedule
+fn check_proposal(
+    req: Option<Json<ProposalRequest>>,
+) -> Result<Option<ProposalRequest>, (StatusCode, Json<serde_json::Value>)> {
+    let Some(Json(req)) = req else {
+        return Ok(None);
+    };
+    let invalid = |error: String| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"status":"error","code":"invalid_proposal","error":error})),
+        )
+    };
+    if req
+        .proposer
+        .as_ref()
+        .is_some_and(|p| p.is_empty() || p.len() > MAX_PROPOSER_LEN)
+    {
+        return Err(invalid(format!(
+            "proposer must be 1 to {MAX_PROPOSER_LEN} bytes"
+        )));
+    }
+    if req
+        .note
+        .as_ref()
+        .is_some_and(|n| n.len() > MAX_PROPOSAL_NOTE_LEN)
+    {
+        return Err(invalid(format!(
+            "note must be at most {MAX_PROPOSAL_NOTE_LEN} bytes"
+        )));
+    }
+    if let Some(bead) = &req.bead {
+        parse_bead_hash(bead)?;
+    }
+    Ok(Some(req))
+}
+
+// Stage 2 -> 3: Committed → Proposed
+pub async fn propose_transaction(
+    Path(txid): Path<String>,
+    headers: HeaderMap,
+    body: Option<Json<ProposalRequest>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    match check_proposal(body) {
+        Ok(proposal) => transition(&txid, Transition::Propose, &headers, None, proposal),
+        Err(resp) => observe_transition(Transition::Propose.name(), resp),
+    }
+}
+
+// Stage 3 -> 4: Proposed → Scheduled
+pub async fn schedule_transaction(
+    Path(txid): Path<String>,
+    headers: HeaderMap,
+    body: Option<Json<ProposalRequest>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    match check_proposal(body) {
+        Ok(proposal) => transition(&txid, Transition::Schedule, &headers, None, proposal),
+        Err(resp) => observe_transition(Transition::Schedule.name(), resp),
+    }
+}
+
+// Stage 2 -> 1: Committed → Mempool
+pub async fn uncommit_transaction(
+    Path(txid): Path<String>,
+    headers: HeaderMap,
+    body: Option<Json<ReverseRequest>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let reason = body.and_then(|Json(b)| b.reason);
+    transition(&txid, Transition::Uncommit, &headers, reason, None)
+}
+
+// Stage 3 -> 2: Proposed → Committed
+pub async fn unpropose_transaction(
+    Path(txid): Path<String>,
+    headers: HeaderMap,
+    body: Option<Json<ReverseRequest>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let reason = body.and_then(|Json(b)| b.reason);
+    transition(&txid, Transition::Unpropose, &headers, reason, None)
+}
+
+// Stage 4 -> 3: Scheduled → Proposed
+pub async fn unschedule_transaction(
+    Path(txid): Path<String>,
+    headers: HeaderMap,
+    body: Option<Json<ReverseRequest>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let reason = body.and_then(|Json(b)| b.reason);
+    transition(&txid, Transition::Unschedule, &headers, reason, None)
+}
+
+// Merges a per-tx outcome into one entry of a batch response
+fn batch_entry(txid: &str, resp: &(StatusCode, Json<serde_json::Value>)) -> serde_json::Value {
+    let mut entry = resp.1 .0.clone();
+    entry["txid"] = json!(txid);
+    entry["http_status"] = json!(resp.0.as_u16());
+    entry
+}
+
+async fn resolve_batch_targets(req: &BatchRequest) -> Vec<String> {
+    let txs = match req.filter {
+        Some(_) => collect_transactions().await,
+        None => Vec::new(),
+    };
+    batch_targets(
+        req,
+        txs.iter()
+            .map(|tx| (tx.txid.as_str(), tx.category.as_str(), tx.fee_rate)),
+    )
+}
+
+// Explicit txids first, then `(txid, category, fee_rate)` filter matches,
+// without duplicates
+fn batch_targets<'a>(
+    req: &BatchRequest,
+    txs: impl IntoIterator<Item = (&'a str, &'a str, f64)>,
+) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut targets: Vec<String> = req
+        .txids
+        .iter()
+        .filter(|t| seen.insert((*t).clone()))
+        .cloned()
+        .collect();
+
+    if let Some(filter) = &req.filter {
+        for (txid, category, fee_rate) in txs {
+            let category_ok = filter
+                .category
+                .as_ref()
+                .is_none_or(|c| c.eq_ignore_ascii_case(category));
+            let fee_ok = filter.min_fee_rate.is_none_or(|min| fee_rate >= min);
+            if category_ok && fee_ok && seen.insert(txid.to_string()) {
+                targets.push(txid.to_string());
+            }
+        }
+    }
+
+    targets
+}
+
+// 200 when every entry went through, 202 while some await a quorum,
+// 400 when all failed and 207 for a mix
+fn batch_status(results: &[serde_json::Value]) -> StatusCode {
+    let failed = results.iter().filter(|r| r["status"] == "error").count();
+    let pending = results.iter().any(|r| r["status"] == "pending");
+    match failed {
+        0 if pending => StatusCode::ACCEPTED,
+        0 => StatusCode::OK,
+        n if n == results.len() => StatusCode::BAD_REQUEST,
+        _ => StatusCode::MULTI_STATUS,
+    }
+}
+
+// All-or-nothing: any failure leaves every tx where it was
+fn run_batch_atomic(
+    targets: &[String],
+    transition: Transition,
+    standard: &Client,
+    committed: &Client,
+) -> (StatusCode, Vec<serde_json::Value>) {
+    // Reports the failing entry and marks every other one as not applied
+    let abort = |relayed: &[Txid], failed: usize, resp: (StatusCode, Json<serde_json::Value>)| {
+        let mut state = STATE.lock().unwrap();
+        for txid in relayed {
+            prioritise(committed, txid, -WITHDRAW_FEE_DELTA);
+            state.withdrawn.insert(*txid);
+        }
+        drop(state);
+
+        let resp = observe_transition(transition.name(), resp);
+        let results = targets
+            .iter()
+            .enumerate()
+            .map(|(i, t)| {
+                if i == failed {
+                    batch_entry(t, &resp)
+                } else {
+                    json!({"txid":t,"status":"rolled_back"})
+                }
+            })
+            .collect();
+        (resp.0, results)
+    };
+
+    let mut parsed = Vec::with_capacity(targets.len());
+    for (i, t) in targets.iter().enumerate() {
+        match parse_txid(t) {
+            Ok(txid) => parsed.push(txid),
+            Err(resp) => return abort(&[], i, resp),
+        }
+    }
+
+    // Txs this batch relayed into cmempool, withdrawn again on abort
+    let mut relayed = Vec::new();
+    let mut prepared = Vec::with_capacity(parsed.len());
+    for (i, txid) in parsed.iter().enumerate() {
+        match prepare_transition(txid, transition, standard, committed) {
+            Ok(p) => {
+                if transition == Transition::Commit && !p.already_committed {
+                    relayed.push(*txid);
+                }
+                prepared.push(p);
+            }
+            Err(resp) => return abort(&relayed, i, resp),
+        }
+    }
+
+    let items: Vec<(Txid, bool)> = parsed
+        .iter()
+        .zip(&prepared)
+        .map(|(txid, p)| (*txid, p.in_cpool))
+        .collect();
+    let applied = match STATE
+        .lock()
+        .unwrap()
+        .apply_all(&items, transition, now_ts())
+    {
+        Ok(a) => a,
+        Err((i, e)) => return abort(&relayed, i, transition_error(&parsed[i], e)),
+    };
+
+    let results = parsed
+        .iter()
+        .zip(&prepared)
+        .zip(&applied)
+        .zip(targets)
+        .map(|(((txid, p), a), raw)| {
+            let body = finish_transition(*txid, transition, p, a, None, None, committed);
+            let resp = observe_transition(transition.name(), (StatusCode::OK, Json(body)));
+            batch_entry(raw, &resp)
+        })
+        .collect();
+
+    (StatusCode::OK, results)
+}
+
+async fn run_batch(
+    transition: Transition,
+    headers: HeaderMap,
+    req: BatchRequest,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let targets = resolve_batch_targets(&req).await;
+    if targets.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "status":"error",
+                "code":"empty_batch",
+                "error":"No transactions matched the batch request"
+            })),
+        );
+    }
+
+    // Each tx carries its own signature; key, nonce and timestamp are shared.
+    // Txs turned away or still short of a quorum are settled before anything
+    // runs. Atomic batches keep no votes, so a rollback leaves none behind.
+    let mut signers = HashMap::new();
+    let mut settled = HashMap::new();
+    for t in &targets {
+        let signature = req.signatures.get(t).map(String::as_str);
+        let held = authorize(t, transition, &headers, signature).and_then(|signer| {
+            let pending = quorum_gate(t, transition, signer.as_ref(), !req.atomic);
+            signers.insert(t.clone(), signer);
+            pending.map_or(Ok(()), Err)
+        });
+        if let Err(resp) = held {
+            settled.insert(t.clone(), observe_transition(transition.name(), resp));
+        }
+    }
+
+    // One pair of clients for the whole batch
+    let standard = connect_to_bitcoind();
+    let committed = connect_to_cmempoold();
+
+    let (status, results) = if req.atomic && !settled.is_empty() {
+        let status = targets
+            .iter()
+            .filter_map(|t| settled.get(t))
+            .map(|resp| resp.0)
+            .find(|s| !s.is_success())
+            .unwrap_or(StatusCode::ACCEPTED);
+        let results = targets
+            .iter()
+            .map(|t| match settled.get(t) {
+                Some(resp) => batch_entry(t, resp),
+                None => json!({"txid":t,"status":"rolled_back"}),
+            })
+            .collect();
+        (status, results)
+    } else if req.atomic {
+        run_batch_atomic(&targets, transition, &standard, &committed)
+    } else {
+        let results: Vec<_> = targets
+            .iter()
+            .map(|t| {
+                let resp = match settled.remove(t) {
+                    Some(resp) => resp,
+                    None => observe_transition(
+                        transition.name(),
+                        run_transition(t, transition, None, None, None, &standard, &committed),
+                    ),
+                };
+                batch_entry(t, &resp)
+            })
+            .collect();
+        (batch_status(&results), results)
+    };
+
+    for r in &results {
+        if let Some(t) = r["txid"].as_str() {
+            let entry_status = r["http_status"].as_u64().unwrap_or(status.as_u16() as u64);
+            record_signed(
+                t,
+                transition,
+                signers.remove(t).flatten(),
+                entry_status as u16,
+            );
+        }
+    }
+
+    let succeeded = results.iter().filter(|r| r["status"] == "ok").count();
+    let pending = results.iter().filter(|r| r["status"] == "pending").count();
+    (
+        status,
+        Json(json!({
+            "status": if status.is_success() { "ok" } else { "error" },
+            "transition": transition.name(),
+            "atomic": req.atomic,
+            "requested": targets.len(),
+            "succeeded": succeeded,
+            "pending": pending,
+            "failed": targets.len() - succeeded - pending,
+            "results": results
+        })),
+    )
+}
+
+pub async fn batch_commit(
+    headers: HeaderMap,
+    Json(req): Json<BatchRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    run_batch(Transition::Commit, headers, req).await
+}
+
+pub async fn batch_propose(
+    headers: HeaderMap,
+    Json(req): Json<BatchRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    run_batch(Transition::Propose, headers, req).await
+}
+
+pub async fn batch_schedule(
+    headers: HeaderMap,
+    Json(req): Json<BatchRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    run_batch(Transition::Schedule, headers, req).await
+}
+
+// Mempool view the policy engine evaluates, plus the weight already scheduled
+fn policy_candidates(standard: &Client, committed: &Client) -> (Vec<policy::Candidate>, u64) {
+    let std_entries = time_rpc(BITCOIND, "getrawmempool", || {
+        standard.get_raw_mempool_verbose()
+    })
+    .unwrap_or_default();
+    let cpool_entries = time_rpc(CMEMPOOL, "getrawmempool", || {
+        committed.get_raw_mempool_verbose()
+    })
+    .unwrap_or_default();
+    let txids: BTreeSet<Txid> = std_entries
+        .keys()
+        .chain(cpool_entries.keys())
+        .copied()
+        .collect();
+
+    let now = now_ts();
+    let mut scheduled_weight = 0;
+    let mut candidates = Vec::with_capacity(txids.len());
+    for txid in txids {
+        let std_entry = std_entries.get(&txid);
+        let cpool_entry = cpool_entries.get(&txid);
+        let Some(e) = std_entry.or(cpool_entry) else {
+            continue;
+        };
+
+        let category = detect_category(&txid, std_entry.is_some(), cpool_entry.is_some(), 0);
+        let weight = e.weight.unwrap_or(e.vsize * 4);
+        if category == "Scheduled" {
+            scheduled_weight += weight;
+        }
+
+        // Age counts from when the tx last entered its current stage
+        let entered_at = STATE.lock().unwrap().entered_at(&txid);
+        let entered = match category.as_str() {
+            "Committed" => entered_at.or(cpool_entry.map(|e| e.time)),
+            "Proposed" | "Scheduled" => entered_at,
+            _ => None,
+        }
+        .unwrap_or(e.time);
+
+        let fee_rate = if e.vsize > 0 {
+            (to_sats(e.fees.base) as f64) / (e.vsize as f64)
+        } else {
+            0.0
+        };
+
+        candidates.push(policy::Candidate {
+            txid,
+            category,
+            fee_rate,
+            weight,
+            rbf_signaled: e.bip125_replaceable,
+            age_in_stage: now.saturating_sub(entered),
+            // Both counts include the tx itself
+            package_size: (e.ancestor_count + e.descendant_count).saturating_sub(1),
+        });
+    }
+
+    (candidates, scheduled_weight)
+}
+
+/// Runs the promotion rules once against both mempools.
+///
+/// Decisions go through the same state machine as the HTTP transitions
+/// unless `dry_run` is set; the report is kept for `GET /policy/report`.
+fn evaluate_policy(dry_run: bool) -> policy::Report {
+    let current = POLICY.lock().unwrap().clone();
+    let standard = connect_to_bitcoind();
+    let committed = connect_to_cmempoold();
+
+    let (candidates, scheduled_weight) = policy_candidates(&standard, &committed);
+    let mut decisions = policy::plan(&current, &candidates, scheduled_weight);
+
+    if !dry_run {
+        for d in &mut decisions {
+            let transition = d.action.transition();
+            let resp = observe_transition(
+                transition.name(),
+                run_transition(&d.txid, transition, None, None, None, &standard, &committed),
+            );
+            let mut outcome = resp.1 .0;
+            outcome["http_status"] = json!(resp.0.as_u16());
+            d.outcome = Some(outcome);
+        }
+    }
+
+    let report = policy::Report {
+        evaluated_at: now_ts(),
+        dry_run,
+        candidates: candidates.len(),
+        decisions,
+    };
+    *policy::LAST_REPORT.lock().unwrap() = Some(report.clone());
+    report
+}
+
+/// Background refresh: re-evaluates the policy every `interval_secs` while enabled.
+///
+/// Acts on it only while no operators are registered; afterwards each run
+/// is a dry run, and `POST /policy/evaluate` signed by an operator acts.
+pub async fn run_policy_loop() {
+    loop {
+        let interval = POLICY.lock().unwrap().interval_secs;
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+
+        let (enabled, dry_run) = {
+            let p = POLICY.lock().unwrap();
+            (p.enabled, p.dry_run)
+        };
+        // Only a dry run once operators are registered: promotions then need
+        // an operator's signature, or their votes in quorum mode, and the
+        // loop can give neither
+        let dry_run = dry_run || operators::required();
+        if enabled {
+            let _ = tokio::task::spawn_blocking(move || evaluate_policy(dry_run)).await;
+        }
+    }
+}
+
+/// Registers operator keys from a JSON file; once any are registered,
+/// every transition must carry a signature from one of them.
+pub fn load_operators(path: &str) -> Result<usize, String> {
+    let raw = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+    let parsed: Vec<Operator> = serde_json::from_str(&raw).map_err(|e| format!("{path}: {e}"))?;
+    operators::register(&parsed).map_err(|e| format!("{path}: {e}"))?;
+    Ok(parsed.len())
+}
+
+pub async fn get_operators() -> Json<serde_json::Value> {
+    Json(json!({
+        "signatures_required":operators::required(),
+        "max_clock_skew_secs":operators::MAX_CLOCK_SKEW_SECS,
+        "operators":operators::list()
+    }))
+}
+
+fn quorum_view() -> serde_json::Value {
+    json!({
+        "settings":quorum::SETTINGS.lock().unwrap().clone(),
+        "operators":operators::list().len()
+    })
+}
+
+pub async fn get_quorum() -> Json<serde_json::Value> {
+    Json(quorum_view())
+}
+
+/// Replaces the quorum settings; signed by an operator once any are registered.
+pub async fn put_quorum(
+    headers: HeaderMap,
+    Json(settings): Json<quorum::Settings>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let signer = match authorize_settings("quorum", "update", &headers) {
+        Ok(signer) => signer,
+        Err(resp) => return resp,
+    };
+    if let Err(e) = settings.validate(operators::list().len()) {
+        record_signed_as("quorum", "update", signer, StatusCode::BAD_REQUEST.as_u16());
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "status":"error",
+                "code":"invalid_quorum",
+                "error":e
+            })),
+        );
+    }
+
+    *quorum::SETTINGS.lock().unwrap() = settings;
+    record_signed_as("quorum", "update", signer, StatusCode::OK.as_u16());
+    (StatusCode::OK, Json(quorum_view()))
+}
+
+pub async fn get_transaction_votes(
+    Path(txid): Path<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let txid = match parse_txid(&txid) {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
+    let settings = quorum::SETTINGS.lock().unwrap().clone();
+    let now = now_ts();
+    (
+        StatusCode::OK,
+        Json(json!({
+            "txid":txid.to_string(),
+            "quorum":settings.enabled,
+            "propose":quorum::tally(&txid, Transition::Propose.name(), now, settings.threshold),
+            "schedule":quorum::tally(&txid, Transition::Schedule.name(), now, settings.threshold)
+        })),
+    )
+}
+
+#[derive(Deserialize)]
+pub struct RootQuery {
+    pub epoch: Option<u64>, // a past root instead of the current one
+}
+
+/// Merkle root over the Committed set.
+///
+/// Built like a block's merkle root from the txids alone, ordered by their
+/// raw bytes (the hex form reversed); `epoch` goes up with every change to
+/// the set and a root is taken for each. The set is what went through the
+/// pipeline: txs listed as Committed only because cmempool holds them or a
+/// bead included them, without a commit here, are not under the root.
+pub async fn get_committed_root(
+    Query(q): Query<RootQuery>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let current = {
+        let state = STATE.lock().unwrap();
+        commitment::current(state.committed_epoch(), &state.committed, now_ts())
+    };
+    let root = match q.epoch {
+        Some(epoch) if epoch != current.epoch => commitment::at_epoch(epoch),
+        _ => Some(current),
+    };
+    match root {
+        Some(r) => (StatusCode::OK, Json(json!(r))),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "status":"error",
+                "code":"unknown_epoch",
+                "error":"No root was taken at that epoch, or it is no longer kept"
+            })),
+        ),
+    }
+}
+
+/// Inclusion proof for a tx under the Committed set root, the current one
+/// unless `epoch` asks for a recent past one.
+///
+/// Hashes are in the byte-reversed hex used for txids: starting from the
+/// txid, each path entry is hashed in on the left when that level's index
+/// is odd and on the right when even, halving the index each time.
+pub async fn get_committed_proof(
+    Path(txid): Path<String>,
+    Query(q): Query<RootQuery>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let txid = match parse_txid(&txid) {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
+    let epoch = match q.epoch {
+        Some(epoch) => epoch,
+        None => {
+            let state = STATE.lock().unwrap();
+            commitment::current(state.committed_epoch(), &state.committed, now_ts()).epoch
+        }
+    };
+    match commitment::prove(&txid, epoch) {
+        Ok(p) => (StatusCode::OK, Json(json!(p))),
+        Err(commitment::ProofError::EpochNotKept) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "status":"error",
+                "code":"unknown_epoch",
+                "error":"Leaves for that epoch are no longer kept, or it was never reached"
+            })),
+        ),
+        Err(commitment::ProofError::NotCommitted) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "status":"error",
+                "code":"not_committed",
+                "error":format!("Transaction {txid} is not in the Committed set at epoch {epoch}")
+            })),
+        ),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SignedLogQuery {
+    pub txid: Option<String>,
+}
+
+pub async fn get_signed_transitions(
+    Query(q): Query<SignedLogQuery>,
+) -> Json<Vec<operators::SignedTransition>> {
+    let txid = q.txid.map(|t| t.to_ascii_lowercase());
+    Json(operators::log(txid.as_deref()))
+}
+
+/// Loads the startup policy from a JSON file.
+pub fn load_policy(path: &str) -> Result<(), String> {
+    let raw = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+    let parsed: Policy = serde_json::from_str(&raw).map_err(|e| format!("{path}: {e}"))?;
+    parsed.validate()?;
+    *POLICY.lock().unwrap() = parsed;
+    Ok(())
+}
+
+pub async fn get_policy() -> Json<Policy> {
+    Json(POLICY.lock().unwrap().clone())
+}
+
+/// Replaces the policy; signed by an operator once any are registered,
+/// since the loop then transitions txs on the policy's behalf.
+pub async fn put_policy(
+    headers: HeaderMap,
+    Json(new_policy): Json<Policy>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let signer = match authorize_settings("policy", "update", &headers) {
+        Ok(signer) => signer,
+        Err(resp) => return resp,
+    };
+    if let Err(e) = new_policy.validate() {
+        record_signed_as("policy", "update", signer, StatusCode::BAD_REQUEST.as_u16());
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "status":"error",
+                "code":"invalid_policy",
+                "error":e
+            })),
+        );
+    }
+
+    *POLICY.lock().unwrap() = new_policy.clone();
+    record_signed_as("policy", "update", signer, StatusCode::OK.as_u16());
+    (
+        StatusCode::OK,
+        Json(json!({"status":"ok","policy":new_policy})),
+    )
+}
+
+/// Evaluates the policy now; acting on it takes an operator signature
+/// once any are registered, a dry run does not.
+pub async fn evaluate_policy_now(
+    headers: HeaderMap,
+    Query(q): Query<EvaluateQuery>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let dry_run = q.dry_run.unwrap_or_else(|| POLICY.lock().unwrap().dry_run);
+    if dry_run {
+        return (StatusCode::OK, Json(json!(evaluate_policy(true))));
+    }
+    if quorum::SETTINGS.lock().unwrap().enabled {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({
+                "status":"error",
+                "code":"quorum_enabled",
+                "error":"Policy decisions bypass the operator quorum; only a dry run is allowed while it is enabled"
+            })),
+        );
+    }
+    let signer = match authorize_settings("policy", "evaluate", &headers) {
+        Ok(signer) => signer,
+        Err(resp) => return resp,
+    };
+    let report = evaluate_policy(false);
+    record_signed_as("policy", "evaluate", signer, StatusCode::OK.as_u16());
+    (StatusCode::OK, Json(json!(report)))
+}
+
+pub async fn get_policy_report() -> (StatusCode, Json<serde_json::Value>) {
+    match policy::LAST_REPORT.lock().unwrap().as_ref() {
+        Some(report) => (StatusCode::OK, Json(json!(report))),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "status":"error",
+                "code":"no_report",
+                "error":"Policy has not been evaluated yet"
+            })),
+        ),
+    }
+}
+
+fn capacity_view() -> serde_json::Value {
+    let state = STATE.lock().unwrap();
+    json!({
+        "budget":state.budget,
+        "limit":state.budget.limit(),
+        "used":state.scheduled_cost(),
+        "remaining":state.remaining(),
+        "scheduled":state.scheduled.len(),
+        "queued":state.queue.iter().map(|t| t.to_string()).collect::<Vec<_>>()
+    })
+}
+
+pub async fn get_capacity() -> Json<serde_json::Value> {
+    Json(capacity_view())
+}
+
+pub async fn put_capacity(Json(budget): Json<Budget>) -> (StatusCode, Json<serde_json::Value>) {
+    if let Err(e) = budget.validate() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "status":"error",
+                "code":"invalid_budget",
+                "error":e
+            })),
+        );
+    }
+
+    let promoted = STATE.lock().unwrap().set_budget(budget, now_ts());
+    record_promoted(&promoted);
+
+    let mut body = capacity_view();
+    body["promoted"] = json!(promoted.iter().map(|t| t.to_string()).collect::<Vec<_>>());
+    (StatusCode::OK, Json(body))
+}
+
+// Scheduled txs and their unconfirmed ancestors, from cmempool first
+fn gather_pool(
+    scheduled: &[Txid],
+    standard: &Client,
+    committed: &Client,
+) -> (HashMap<Txid, PoolTx>, Vec<Txid>) {
+    let mut pool = HashMap::new();
+    let mut missing = Vec::new();
+    let mut stack: Vec<Txid> = scheduled.to_vec();
+    while let Some(txid) = stack.pop() {
+        if pool.contains_key(&txid) {
+            continue;
+        }
+
+        let entry = time_rpc(CMEMPOOL, "getmempoolentry", || {
+            committed.get_mempool_entry(&txid)
+        })
+        .or_else(|_| {
+            time_rpc(BITCOIND, "getmempoolentry", || {
+                standard.get_mempool_entry(&txid)
+            })
+        });
+        let tx = time_rpc(CMEMPOOL, "getrawtransaction", || {
+            committed.get_raw_transaction(&txid, None)
+        })
+        .or_else(|_| {
+            time_rpc(BITCOIND, "getrawtransaction", || {
+                standard.get_raw_transaction(&txid, None)
+            })
+        });
+
+        match (entry, tx) {
+            (Ok(entry), Ok(tx)) => {
+                stack.extend(entry.depends.iter().copied());
+                pool.insert(
+                    txid,
+                    PoolTx {
+                        cost: resolved_cost(&tx, standard, committed),
+                        tx,
+                        fee: to_sats(entry.fees.base),
+                        parents: entry.depends,
+                    },
+                );
+            }
+            _ => missing.push(txid),
+        }
+    }
+    (pool, missing)
+}
+
+// Core's chain name: main, test, signet or regtest
+fn node_chain(node: &'static str, client: &Client) -> Result<String, String> {
+    let info = time_rpc(node, "getblockchaininfo", || {
+        client.call::<serde_json::Value>("getblockchaininfo", &[])
+    })
+    .map_err(|e| e.to_string())?;
+    info["chain"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| "getblockchaininfo has no chain".to_string())
+}
+
+fn payout_script(
+    address: Option<&str>,
+    standard: &Client,
+) -> Result<ScriptBuf, (StatusCode, Json<serde_json::Value>)> {
+    let Some(address) = address else {
+        // OP_TRUE: anyone can spend, only sensible on regtest
+        return Ok(ScriptBuf::from_bytes(vec![0x51]));
+    };
+
+    let invalid = |e: String| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "status":"error",
+                "code":"invalid_address",
+                "error":e
+            })),
+        )
+    };
+    let chain = node_chain(BITCOIND, standard)
+        .map_err(|e| invalid(format!("cannot determine network: {e}")))?;
+    let network = Network::from_core_arg(&chain).map_err(|e| invalid(e.to_string()))?;
+
+    address
+        .parse::<Address<_>>()
+        .map_err(|e| invalid(e.to_string()))?
+        .require_network(network)
+        .map(|a| a.script_pubkey())
+        .map_err(|e| invalid(e.to_string()))
+}
+
+/// Builds a getblocktemplate-style template from the Scheduled set.
+///
+/// Chain fields (bits, target, times, rules) come from the cmempool node's
+/// own template; the transaction list, coinbase and commitments are ours.
+fn build_template(
+    address: Option<&str>,
+    standard: &Client,
+    committed: &Client,
+) -> Result<serde_json::Value, (StatusCode, Json<serde_json::Value>)> {
+    let mut gbt = time_rpc(CMEMPOOL, "getblocktemplate", || {
+        committed.call::<serde_json::Value>("getblocktemplate", &[json!({"rules":["segwit"]})])
+    })
+    .map_err(|e| {
+        (
+            StatusCode::BAD_GATEWAY,
+            Json(json!({
+                "status":"error",
+                "code":"template_unavailable",
+                "error":format!("getblocktemplate failed: {e}")
+            })),
+        )
+    })?;
+    let payout = payout_script(address, standard)?;
+
+    // Node's coinbase value minus the fees of its own picks is the subsidy
+    let node_fees: u64 = gbt["transactions"]
+        .as_array()
+        .map(|txs| txs.iter().filter_map(|t| t["fee"].as_u64()).sum())
+        .unwrap_or(0);
+    let subsidy = gbt["coinbasevalue"]
+        .as_u64()
+        .unwrap_or(0)
+        .saturating_sub(node_fees);
+    let height = gbt["height"].as_u64().unwrap_or(0);
+
+    let (scheduled, limit) = {
+        let state = STATE.lock().unwrap();
+        let mut scheduled: Vec<Txid> = state.scheduled.iter().copied().collect();
+        scheduled.sort();
+        (scheduled, state.budget.limit())
+    };
+    let (pool, missing) = gather_pool(&scheduled, standard, committed);
+    let selection = template::select(&scheduled, &pool, limit);
+
+    let wtxids: Vec<_> = selection
+        .included
+        .iter()
+        .map(|t| pool[t].tx.wtxid())
+        .collect();
+    let value = subsidy + selection.fees;
+    let (shares, planned) = ledger_payees(value, standard);
+    let payees: Vec<Payee> = planned
+        .iter()
+        .map(|(m, script)| Payee {
+            script: script.clone(),
+            amount: m.amount_sats,
+        })
+        .collect();
+    let outputs = template::payout_outputs(
+        value,
+        &payees,
+        payout,
+        template::DUST_LIMIT,
+        template::MAX_PAYOUT_OUTPUTS,
+    );
+
+    // Remembered so the ledger can credit what this coinbase pays, should a
+    // block be built on it
+    let credits = ledger::credits(&planned, &outputs);
+    if !credits.is_empty() {
+        let credited: HashSet<&str> = credits.iter().map(|c| c.miner.as_str()).collect();
+        ledger::plan(ledger::Plan {
+            outputs: outputs.clone(),
+            shares: shares
+                .iter()
+                .filter(|s| credited.contains(s.miner.as_str()))
+                .cloned()
+                .collect(),
+            credits,
+        });
+    }
+    let coinbase = template::coinbase(height, outputs, &wtxids);
+    let txids: Vec<Txid> = std::iter::once(coinbase.txid())
+        .chain(selection.included.iter().copied())
+        .collect();
+    let merkle_root = template::merkle_root(&txids).expect("coinbase is always present");
+
+    let depends = template::depends(&selection.included, &pool);
+    let transactions: Vec<_> = selection
+        .included
+        .iter()
+        .zip(depends)
+        .map(|(t, depends)| {
+            let p = &pool[t];
+            json!({
+                "data":serialize_hex(&p.tx),
+                "txid":t.to_string(),
+                "hash":p.tx.wtxid().to_string(),
+                "depends":depends,
+                "fee":p.fee,
+                "sigops":p.cost.sigops,
+                "weight":p.cost.weight,
+                "scheduled":scheduled.binary_search(t).is_ok()
+            })
+        })
+        .collect();
+
+    let (commitment, paid) = coinbase.output.split_last().expect("commitment output");
+    let commitment = &commitment.script_pubkey;
+    gbt["transactions"] = json!(transactions);
+    gbt["coinbasevalue"] = json!(value);
+    gbt["payouts"] = json!(paid
+        .iter()
+        .map(|o| json!({
+            "script":o.script_pubkey.to_hex_string(),
+            "amount_sats":o.value.to_sat()
+        }))
+        .collect::<Vec<_>>());
+    gbt["coinbasetxn"] = json!({
+        "data":serialize_hex(&coinbase),
+        "txid":coinbase.txid().to_string(),
+        "hash":coinbase.wtxid().to_string()
+    });
+    gbt["default_witness_commitment"] = json!(commitment.to_hex_string());
+    gbt["merkleroot"] = json!(merkle_root.to_string());
+    gbt["witness_commitment"] = json!(commitment.to_hex_string());
+    gbt["weight"] = json!(selection.cost.weight);
+    gbt["sigops"] = json!(selection.cost.sigops);
+    gbt["excluded"] = json!(selection
+        .excluded
+        .iter()
+        .map(|t| t.to_string())
+        .collect::<Vec<_>>());
+    gbt["missing"] = json!(missing.iter().map(|t| t.to_string()).collect::<Vec<_>>());
+    Ok(gbt)
+}
+
+pub async fn get_block_template(
+    Query(q): Query<TemplateQuery>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let standard = connect_to_bitcoind();
+    let committed = connect_to_cmempoold();
+    match build_template(q.address.as_deref(), &standard, &committed) {
+        Ok(t) => (StatusCode::OK, Json(t)),
+        Err(resp) => resp,
+    }
+}
+
+fn submit_to(node: &'static str, client: &Client, hex: &str) -> serde_json::Value {
+    match time_rpc(node, "submitblock", || {
+        client.call::<Option<String>>("submitblock", &[json!(hex)])
+    }) {
+        Ok(result) => json!({
+            "accepted":blocks::submit_accepted(result.as_deref()),
+            "result":result.unwrap_or_else(|| "accepted".to_string())
+        }),
+        Err(e) => json!({
+            "accepted":false,
+            "result":"error",
+            "error":e.to_string()
+        }),
+    }
+}
+
+// Moves every tx in an accepted block to Confirmed without waiting for a refresh
+fn confirm_block_txs(block: &Block, height: Option<u64>) -> Vec<Txid> {
+    let txids = blocks::confirmed_txids(block);
+    for txid in &txids {
+        let (promoted, last_stage) = {
+            let mut state = STATE.lock().unwrap();
+            let promoted = state.confirm(*txid, block.block_hash(), now_ts());
+            let last_stage = state.confirmed.get(txid).map(|c| c.stage.name());
+            (promoted, last_stage)
+        };
+        record_promoted(&promoted);
+        if let Some(height) = height {
+            timeline::record(
+                *txid,
+                Event::Confirmed {
+                    height,
+                    time: block.header.time as u64,
+                },
+            );
+        }
+        archive::finalize(
+            txid,
+            archive::Final {
+                category: "Confirmed",
+                last_stage,
+                block_hash: Some(block.block_hash().to_string()),
+                block_height: height,
+                block_time: Some(block.header.time as u64),
+                timeline: timeline::get(txid),
+            },
+            now_ts(),
+        );
+    }
+    txids
+}
+
+// Total fees in a block as reported by the node
+fn block_fees(node: &'static str, client: &Client, hash: &BlockHash) -> Option<u64> {
+    time_rpc(node, "getblockstats", || {
+        client.call::<serde_json::Value>(
+            "getblockstats",
+            &[json!(hash.to_string()), json!(["totalfee"])],
+        )
+    })
+    .ok()?["totalfee"]
+        .as_u64()
+}
+
+// Beads with proven work not yet paid by an earlier block, in braid order
+fn unpaid_shares(miner: Option<&str>) -> Vec<Share> {
+    braid::in_order()
+        .into_iter()
+        .filter(|b| b.work > 0 && miner.is_none_or(|m| b.miner == m) && !ledger::is_paid(&b.hash))
+        .map(|b| Share {
+            bead: b.hash,
+            miner: b.miner,
+            payout_address: b.payout_address,
+            work: b.work,
+        })
+        .collect()
+}
+
+// Unpaid beads' share of `value`, to each miner's latest payout address,
+// with the shares it was split over. Miners without one, or whose address
+// does not parse for this network, are left out until they name one.
+fn ledger_payees(value: u64, standard: &Client) -> (Vec<Share>, Vec<(MinerPayout, ScriptBuf)>) {
+    let shares = unpaid_shares(None);
+    if shares.is_empty() {
+        return (Vec::new(), Vec::new());
+    }
+    let Some(network) = node_chain(BITCOIND, standard)
+        .ok()
+        .and_then(|c| Network::from_core_arg(&c).ok())
+    else {
+        return (Vec::new(), Vec::new());
+    };
+
+    // Latest address per miner, in braid order as `ledger::split` takes it
+    let mut addresses: HashMap<&str, &str> = HashMap::new();
+    for s in &shares {
+        if let Some(address) = s.payout_address.as_deref() {
+            addresses.insert(&s.miner, address);
+        }
+    }
+    let scripts: HashMap<String, ScriptBuf> = addresses
+        .into_iter()
+        .filter_map(|(miner, address)| {
+            let script = address
+                .parse::<Address<_>>()
+                .ok()?
+                .require_network(network)
+                .ok()?
+                .script_pubkey();
+            Some((miner.to_string(), script))
+        })
+        .collect();
+
+    let payable: Vec<Share> = shares
+        .into_iter()
+        .filter(|s| scripts.contains_key(&s.miner))
+        .collect();
+    let planned = ledger::split(value, &payable)
+        .into_iter()
+        .map(|m| {
+            let script = scripts[&m.miner].clone();
+            (m, script)
+        })
+        .collect();
+    (payable, planned)
+}
+
+/// Credits the miners a connected block's coinbase paid, if one of our
+/// templates built it.
+fn record_payout(block: &Block, height: Option<u64>) {
+    let outputs: Vec<TxOut> = block.txdata[0]
+        .output
+        .iter()
+        .filter(|o| !o.script_pubkey.is_op_return())
+        .cloned()
+        .collect();
+    if let Some(payout) = ledger::record(block.block_hash(), height, &outputs, now_ts()) {
+        println!(
+            "Payout: block {} paid {} sats to {} miners",
+            payout.block_hash,
+            payout.payouts.iter().map(|p| p.amount_sats).sum::<u64>(),
+            payout.payouts.len()
+        );
+    }
+}
+
+/// Audits a newly connected block against the pipeline, then confirms its txs.
+///
+/// The audit runs once per block; confirming again is harmless.
+fn connect_block(block: &Block, height: Option<u64>, standard: &Client) -> Vec<Txid> {
+    let hash = block.block_hash();
+    if audit::begin(hash, now_ts()) {
+        let block_txids = blocks::confirmed_txids(block);
+        let (comparison, expected_fees) = {
+            let state = STATE.lock().unwrap();
+            let expected: u64 = state
+                .scheduled
+                .iter()
+                .filter_map(|t| state.fees.get(t))
+                .sum();
+            (
+                audit::compare(&block_txids, &state.scheduled, &state.proposed),
+                expected,
+            )
+        };
+        let mined_fees = block_fees(BITCOIND, standard, &hash);
+        let strings = |txids: &[Txid]| txids.iter().map(|t| t.to_string()).collect();
+
+        audit::store(
+            hash,
+            audit::Audit {
+                hash: hash.to_string(),
+                height,
+                audited_at: now_ts(),
+                scheduled_included: strings(&comparison.scheduled_included),
+                scheduled_missing: strings(&comparison.scheduled_missing),
+                proposed_included: strings(&comparison.proposed_included),
+                unexpected: strings(&comparison.unexpected),
+                expected_fees,
+                mined_fees,
+                fee_difference: mined_fees.map(|m| m as i64 - expected_fees as i64),
+                hit_rate: comparison.hit_rate(),
+            },
+        );
+        record_payout(block, height);
+    }
+
+    confirm_block_txs(block, height)
+}
+
+/// Validates a solved block and submits it to both nodes.
+pub async fn submit_block(
+    Json(req): Json<SubmitBlockRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let block = match blocks::decode(&req.hex).and_then(|b| blocks::validate(&b).map(|_| b)) {
+        Ok(b) => b,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "status":"error",
+                    "code":e.code(),
+                    "error":e.message()
+                })),
+            )
+        }
+    };
+
+    let standard = connect_to_bitcoind();
+    let committed = connect_to_cmempoold();
+    let hex = req.hex.trim();
+    let std_result = submit_to(BITCOIND, &standard, hex);
+    let cpool_result = submit_to(CMEMPOOL, &committed, hex);
+    let accepted = [&std_result, &cpool_result]
+        .iter()
+        .filter(|r| r["accepted"] == true)
+        .count();
+
+    let hash = block.block_hash();
+    let nodes = json!({BITCOIND:std_result, CMEMPOOL:cpool_result});
+    if accepted == 0 {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "status":"error",
+                "code":"block_rejected",
+                "error":"Neither node accepted the block",
+                "hash":hash.to_string(),
+                "nodes":nodes
+            })),
+        );
+    }
+
+    let height = block.bip34_block_height().ok().or_else(|| {
+        time_rpc(BITCOIND, "getblockheader", || {
+            standard.get_block_header_info(&hash)
+        })
+        .ok()
+        .map(|h| h.height as u64)
+    });
+    // The chain watcher follows bitcoind only and would never disconnect a
+    // block bitcoind turned away, so only then are its txs confirmed here
+    let confirmed = if std_result["accepted"] == true {
+        connect_block(&block, height, &standard)
+    } else {
+        Vec::new()
+    };
+
+    let status = if accepted == 2 {
+        StatusCode::OK
+    } else {
+        StatusCode::MULTI_STATUS
+    };
+    (
+        status,
+        Json(json!({
+            "status": if accepted == 2 { "ok" } else { "partial" },
+            "hash":hash.to_string(),
+            "height":height,
+            "nodes":nodes,
+            "confirmed":confirmed.iter().map(|t| t.to_string()).collect::<Vec<_>>()
+        })),
+    )
+}
+
+// Only the first block carries the requested txs; the rest are empty
+fn block_targets(index: u32, requested: &[Txid]) -> &[Txid] {
+    if index == 0 {
+        requested
+    } else {
+        &[]
+    }
+}
+
+// Explicit txids are mined when no source is named, else the Scheduled set
+fn mine_source(req: &MineRequest) -> MineSource {
+    req.source.unwrap_or(if req.txids.is_empty() {
+        MineSource::Scheduled
+    } else {
+        MineSource::Txids
+    })
+}
+
+// Txids the next mined block should contain, before ancestors are added
+fn mine_targets(
+    source: MineSource,
+    explicit: &[Txid],
+    committed: &Client,
+) -> Result<Vec<Txid>, String> {
+    match source {
+        MineSource::Scheduled => {
+            let mut txids: Vec<Txid> = STATE.lock().unwrap().scheduled.iter().copied().collect();
+            txids.sort();
+            Ok(txids)
+        }
+        MineSource::Txids => Ok(explicit.to_vec()),
+        MineSource::Template => {
+            let gbt = time_rpc(CMEMPOOL, "getblocktemplate", || {
+                committed
+                    .call::<serde_json::Value>("getblocktemplate", &[json!({"rules":["segwit"]})])
+            })
+            .map_err(|e| e.to_string())?;
+            Ok(gbt["transactions"]
+                .as_array()
+                .map(|txs| {
+                    txs.iter()
+                        .filter_map(|t| t["txid"].as_str()?.parse().ok())
+                        .collect()
+                })
+                .unwrap_or_default())
+        }
+    }
+}
+
+/// Regtest only: mines blocks on cmempool with `generateblock` and relays
+/// each one to bitcoind.
+pub async fn mine_blocks(Json(req): Json<MineRequest>) -> (StatusCode, Json<serde_json::Value>) {
+    let count = req.blocks.unwrap_or(1);
+    if count == 0 || count > MAX_MINE_BLOCKS {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "status":"error",
+                "code":"invalid_count",
+                "error":format!("blocks must be between 1 and {MAX_MINE_BLOCKS}")
+            })),
+        );
+    }
+
+    let source = mine_source(&req);
+    let mut explicit = Vec::with_capacity(req.txids.len());
+    for t in &req.txids {
+        match parse_txid(t) {
+            Ok(txid) => explicit.push(txid),
+            Err(resp) => return resp,
+        }
+    }
+
+    let standard = connect_to_bitcoind();
+    let committed = connect_to_cmempoold();
+    match node_chain(CMEMPOOL, &committed) {
+        Ok(chain) if chain == "regtest" => {}
+        Ok(chain) => {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(json!({
+                    "status":"error",
+                    "code":"not_regtest",
+                    "error":format!("Mining is only available on regtest, node is on {chain}")
+                })),
+            )
+        }
+        Err(e) => {
+            return (
+                StatusCode::BAD_GATEWAY,
+                Json(json!({"status":"error","code":"node_unavailable","error":e})),
+            )
+        }
+    }
+    let payout = match payout_script(req.address.as_deref(), &standard) {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    let output = format!("raw({})", payout.to_hex_string());
+
+    let mut mined = Vec::new();
+    let mut confirmed = Vec::new();
+    let requested = match mine_targets(source, &explicit, &committed) {
+        Ok(t) => t,
+        Err(e) => {
+            return (
+                StatusCode::BAD_GATEWAY,
+                Json(json!({
+                    "status":"error",
+                    "code":"template_unavailable",
+                    "error":e,
+                    "mined":mined
+                })),
+            )
+        }
+    };
+    for i in 0..count {
+        let targets = block_targets(i, &requested);
+        let limit = STATE.lock().unwrap().budget.limit();
+        let (pool, _) = gather_pool(targets, &standard, &committed);
+        let selection = template::select(targets, &pool, limit);
+        let raw: Vec<String> = selection
+            .included
+            .iter()
+            .map(|t| serialize_hex(&pool[t].tx))
+            .collect();
+
+        let generated = time_rpc(CMEMPOOL, "generateblock", || {
+            committed.call::<serde_json::Value>("generateblock", &[json!(output), json!(raw)])
+        });
+        let hash = match generated.map_err(|e| e.to_string()).and_then(|g| {
+            g["hash"]
+                .as_str()
+                .unwrap_or_default()
+                .parse::<BlockHash>()
+                .map_err(|e| e.to_string())
+        }) {
+            Ok(h) => h,
+            Err(e) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({
+                        "status":"error",
+                        "code":"generate_failed",
+                        "error":e,
+                        "mined":mined
+                    })),
+                )
+            }
+        };
+
+        let block = match time_rpc(CMEMPOOL, "getblock", || committed.get_block(&hash)) {
+            Ok(b) => b,
+            Err(e) => {
+                return (
+                    StatusCode::BAD_GATEWAY,
+                    Json(json!({
+                        "status":"error",
+                        "code":"node_unavailable",
+                        "error":e.to_string(),
+                        "mined":mined
+                    })),
+                )
+            }
+        };
+        let relayed = submit_to(BITCOIND, &standard, &serialize_hex(&block));
+        let height = block.bip34_block_height().ok();
+        // Left to the chain watcher's view of bitcoind otherwise, as in submit_block
+        if relayed["accepted"] == true {
+            confirmed.extend(
+                connect_block(&block, height, &standard)
+                    .iter()
+                    .map(|t| t.to_string()),
+            );
+        }
+        mined.push(json!({
+            "hash":hash.to_string(),
+            "height":height,
+            "txs":block.txdata.len() - 1,
+            "relayed":relayed
+        }));
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "status":"ok",
+            "blocks":mined,
+            "confirmed":confirmed
+        })),
+    )
+}
+
+/// Background watcher: audits every block that connects on bitcoind and
+/// rolls back txs from blocks that a reorg disconnects.
+pub async fn run_chain_watcher() {
+    let mut last_tip: Option<BlockHash> = None;
+    loop {
+        tokio::time::sleep(Duration::from_secs(CHAIN_POLL_SECS)).await;
+        let seen = last_tip;
+        let tip = tokio::task::spawn_blocking(move || {
+            let standard = connect_to_bitcoind();
+            let tip = time_rpc(BITCOIND, "getbestblockhash", || {
+                standard.get_best_block_hash()
+            })
+            .ok()?;
+            match seen {
+                // Startup tip: nothing was lined up for it, so it is only tracked
+                None => {
+                    audit::begin(tip, now_ts());
+                    let height = time_rpc(BITCOIND, "getblockheader", || {
+                        standard.get_block_header_info(&tip)
+                    })
+                    .ok()?
+                    .height as u64;
+                    chain::connect(height, tip, Vec::new());
+                }
+                Some(s) if s != tip => sync_chain(tip, &standard),
+                Some(_) => {}
+            }
+            Some(tip)
+        })
+        .await
+        .ok()
+        .flatten();
+        if tip.is_some() {
+            last_tip = tip;
+        }
+    }
+}
+
+fn block_height(block: &Block, standard: &Client) -> Option<u64> {
+    block.bip34_block_height().ok().or_else(|| {
+        let hash = block.block_hash();
+        time_rpc(BITCOIND, "getblockheader", || {
+            standard.get_block_header_info(&hash)
+        })
+        .ok()
+        .map(|h| h.height as u64)
+    })
+}
+
+// Walks back from `tip` to a tracked block, undoes anything tracked above
+// that fork point, then connects the new blocks in order
+fn sync_chain(tip: BlockHash, standard: &Client) {
+    let mut pending = Vec::new();
+    let mut hash = tip;
+    while chain::height_of(&hash).is_none() && pending.len() < chain::REORG_WINDOW {
+        let Ok(block) = time_rpc(BITCOIND, "getblock", || standard.get_block(&hash)) else {
+            return;
+        };
+        hash = block.header.prev_blockhash;
+        pending.push(block);
+    }
+    let heights: Vec<Option<u64>> = pending.iter().map(|b| block_height(b, standard)).collect();
+
+    let fork_height = chain::height_of(&hash).or_else(|| {
+        heights
+            .last()
+            .copied()
+            .flatten()
+            .map(|h| h.saturating_sub(1))
+    });
+    if let Some(fork_height) = fork_height {
+        for d in chain::disconnect_above(fork_height) {
+            disconnect_block(d, fork_height);
+        }
+    }
+
+    for (block, height) in pending.iter().zip(heights).rev() {
+        let txids = connect_block(block, height, standard);
+        if let Some(height) = height {
+            let finalized = chain::connect(height, block.block_hash(), txids);
+            STATE.lock().unwrap().finalize(&finalized);
+        }
+    }
+}
+
+// Restores the txs of a reorged-out block to the stage they held before it
+fn disconnect_block(d: chain::Disconnected, fork_height: u64) {
+    audit::forget(&d.hash);
+    ledger::revert(&d.hash);
+    let now = now_ts();
+    let mut restored = Vec::new();
+    for txid in &d.txids {
+        let Some(stage) = STATE.lock().unwrap().unconfirm(*txid, &d.hash, now) else {
+            continue;
+        };
+        timeline::record(
+            *txid,
+            Event::Reorged {
+                block: d.hash.to_string(),
+                restored_to: stage.name(),
+                at: now,
+            },
+        );
+        restored.push(chain::Restored {
+            txid: txid.to_string(),
+            stage: stage.name(),
+        });
+    }
+
+    println!(
+        "Reorg: block {} at height {} disconnected, {} txs restored",
+        d.hash,
+        d.height,
+        restored.len()
+    );
+    metrics::record_reorg(d.txids.len());
+    chain::record_reorg(chain::ReorgEvent {
+        at: now,
+        hash: d.hash.to_string(),
+        height: d.height,
+        fork_height,
+        restored,
+    });
+}
+
+pub async fn get_reorgs() -> Json<Vec<chain::ReorgEvent>> {
+    Json(chain::reorgs())
+}
+
+pub async fn get_block_audit(Path(hash): Path<String>) -> (StatusCode, Json<serde_json::Value>) {
+    let hash = match hash.parse::<BlockHash>() {
+        Ok(h) => h,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "status":"error",
+                    "code":"invalid_block_hash",
+                    "error":format!("invalid block hash: {e}")
+                })),
+            )
+        }
+    };
+
+    match audit::get(&hash) {
+        Some(a) => (StatusCode::OK, Json(json!(a))),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "status":"error",
+                "code":"no_audit",
+                "error":"No audit recorded for block"
+            })),
+        ),
+    }
+}
+
+pub async fn get_archive(Query(q): Query<ArchiveQuery>) -> Json<serde_json::Value> {
+    let limit = q
+        .limit
+        .unwrap_or(DEFAULT_ARCHIVE_LIMIT)
+        .min(MAX_ARCHIVE_LIMIT);
+    let entries = archive::list(q.category.as_deref(), limit);
+    Json(json!({
+        "retained":archive::len(),
+        "count":entries.len(),
+        "transactions":entries
+    }))
+}
+
+pub async fn get_archived_transaction(
+    Path(txid): Path<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let txid = match parse_txid(&txid) {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
+
+    match archive::get(&txid) {
+        Some(entry) => (StatusCode::OK, Json(json!(entry))),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "status":"error",
+                "code":"not_archived",
+                "error":"Transaction is not in the archive"
+            })),
+        ),
+    }
+}
+
+pub async fn get_archive_retention() -> Json<Retention> {
+    Json(archive::retention())
+}
+
+pub async fn put_archive_retention(
+    Json(retention): Json<Retention>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Err(e) = retention.validate() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "status":"error",
+                "code":"invalid_retention",
+                "error":e
+            })),
+        );
+    }
+
+    let pruned = archive::set_retention(retention.clone(), now_ts());
+    (
+        StatusCode::OK,
+        Json(json!({"status":"ok","retention":retention,"pruned":pruned})),
+    )
+}
+
+/// One GC pass over SEEN, idle pipeline entries, the archive, expired
+/// votes, timelines, block audits and payout history.
+fn collect_garbage(now: u64) -> gc::Sweep {
+    let limits = LIMITS.lock().unwrap().clone();
+    let seen = gc::evict(
+        &mut SEEN.lock().unwrap(),
+        now,
+        limits.seen_max_age_secs,
+        limits.seen_max_entries,
+    )
+    .len();
+    let idle =
+        STATE
+            .lock()
+            .unwrap()
+            .collect_idle(now, limits.idle_max_age_secs, limits.idle_max_entries);
+    let archived = archive::prune(now);
+    let votes = quorum::prune(now);
+    let timelines = timeline::prune(
+        now,
+        limits.timeline_max_age_secs,
+        limits.timeline_max_entries,
+    );
+    let blocks = audit::prune(now, limits.block_max_age_secs, limits.block_max_entries);
+    let payouts = ledger::prune(
+        now,
+        limits.payout_max_age_secs,
+        limits.payout_max_entries,
+        |bead| braid::get(bead).is_some(),
+    );
+
+    metrics::record_evictions("seen", seen);
+    metrics::record_evictions("idle", idle);
+    metrics::record_evictions("archive", archived);
+    metrics::record_evictions("votes", votes);
+    metrics::record_evictions("timelines", timelines);
+    metrics::record_evictions("blocks", blocks);
+    metrics::record_evictions("payouts", payouts);
+    if seen + idle + archived + votes + timelines + blocks + payouts > 0 {
+        println!(
+            "gc: dropped {seen} seen, {idle} idle, {archived} archived entries, {votes} expired votes, {timelines} timelines, {blocks} block audits and {payouts} payouts"
+        );
+    }
+
+    let sweep = gc::Sweep {
+        at: now,
+        seen,
+        idle,
+        archive: archived,
+        votes,
+        timelines,
+        blocks,
+        payouts,
+    };
+    *LAST_SWEEP.lock().unwrap() = Some(sweep.clone());
+    sweep
+}
+
+pub async fn run_gc_loop() {
+    loop {
+        let interval = LIMITS.lock().unwrap().interval_secs;
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+        collect_garbage(now_ts());
+    }
+}
+
+// Entry counts for everything GC keeps bounded
+fn store_sizes() -> Vec<(&'static str, usize)> {
+    let mut sizes = vec![("seen", SEEN.lock().unwrap().len())];
+    sizes.extend(STATE.lock().unwrap().sizes());
+    sizes.push(("archive", archive::len()));
+    sizes.push(("beads", braid::len()));
+    sizes.push(("votes", quorum::len()));
+    sizes.push(("timelines", timeline::len()));
+    sizes.push(("audits", audit::len()));
+    sizes.extend(ledger::sizes());
+    sizes
+}
+
+fn gc_view() -> serde_json::Value {
+    let sizes: serde_json::Map<String, serde_json::Value> = store_sizes()
+        .into_iter()
+        .map(|(store, count)| (store.to_string(), json!(count)))
+        .collect();
+    json!({
+        "limits":LIMITS.lock().unwrap().clone(),
+        "sizes":sizes,
+        "last_sweep":LAST_SWEEP.lock().unwrap().clone()
+    })
+}
+
+pub async fn get_gc() -> Json<serde_json::Value> {
+    Json(gc_view())
+}
+
+/// Replaces the GC limits and sweeps right away under the new ones.
+pub async fn put_gc(Json(limits): Json<Limits>) -> (StatusCode, Json<serde_json::Value>) {
+    if let Err(e) = limits.validate() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "status":"error",
+                "code":"invalid_limits",
+                "error":e
+            })),
+        );
+    }
+
+    *LIMITS.lock().unwrap() = limits;
+    collect_garbage(now_ts());
+    (StatusCode::OK, Json(gc_view()))
+}
+
+// Inputs of missing txs that are spent or gone, read from bitcoind
+fn inputs_spent(txid: &Txid, standard: &Client) -> bool {
+    reconcile::spends_of(txid).is_some_and(|inputs| {
+        inputs.iter().any(|o| {
+            matches!(
+                time_rpc(BITCOIND, "gettxout", || standard.get_tx_out(
+                    &o.txid,
+                    o.vout,
+                    Some(true)
+                )),
+                Ok(None)
+            )
+        })
+    })
+}
+
+/// Compares pipeline state with both mempools and the tracked chain and
+/// fixes whatever the nodes no longer back up.
+fn reconcile_state(standard: &Client, committed: &Client) -> Vec<reconcile::Correction> {
+    // Snapshot first, so anything tracked shows up in the mempools fetched next
+    let tracked = STATE.lock().unwrap().tracked();
+    let Ok(in_std) = time_rpc(BITCOIND, "getrawmempool", || standard.get_raw_mempool()) else {
+        return Vec::new();
+    };
+    let Ok(in_cpool) = time_rpc(CMEMPOOL, "getrawmempool", || committed.get_raw_mempool()) else {
+        return Vec::new();
+    };
+    let mut view = reconcile::View {
+        in_std: in_std.into_iter().collect(),
+        in_cpool: in_cpool.into_iter().collect(),
+        confirmed_in: HashMap::new(),
+        conflicted: HashSet::new(),
+    };
+
+    for (txid, _, _, _) in &tracked {
+        if view.in_cpool.contains(txid) {
+            if reconcile::spends_of(txid).is_none() {
+                if let Ok(tx) = time_rpc(CMEMPOOL, "getrawtransaction", || {
+                    committed.get_raw_transaction(txid, None)
+                }) {
+                    let inputs = tx.input.iter().map(|i| i.previous_output).collect();
+                    reconcile::note_spends(*txid, inputs);
+                }
+            }
+        } else if let Some(hash) = chain::block_containing(txid) {
+            view.confirmed_in.insert(*txid, hash);
+        } else if !view.in_std.contains(txid) && inputs_spent(txid, standard) {
+            view.conflicted.insert(*txid);
+        }
+    }
+
+    let now = now_ts();
+    let mut corrections = Vec::new();
+    for (txid, stage, withdrawn, version) in tracked {
+        let Some(reason) = reconcile::diagnose(&txid, stage, withdrawn, &view) else {
+            continue;
+        };
+        let confirmed_in = view.confirmed_in.get(&txid).copied();
+        let promoted = {
+            let mut state = STATE.lock().unwrap();
+            // Moved since the snapshot; the next pass takes another look
+            if state.version(&txid) != version {
+                continue;
+            }
+            match confirmed_in {
+                Some(hash) => state.confirm(txid, hash, now),
+                None => state.clear(&txid, now),
+            }
+        };
+        record_promoted(&promoted);
+
+        let departed = match (reason, confirmed_in) {
+            (Reason::Confirmed, Some(hash)) => {
+                let header = time_rpc(BITCOIND, "getblockheader", || {
+                    standard.get_block_header_info(&hash)
+                })
+                .ok();
+                let block_time = header.as_ref().map(|h| h.time as u64);
+                let block_height = header.map(|h| h.height as u64);
+                if let (Some(height), Some(time)) = (block_height, block_time) {
+                    timeline::record(txid, Event::Confirmed { height, time });
+                }
+                Some((
+                    "Confirmed",
+                    Some(hash.to_string()),
+                    block_height,
+                    block_time,
+                ))
+            }
+            (Reason::Conflicted, _) => Some(("Replaced", None, None, None)),
+            (Reason::Dropped, _) => Some(("Dropped", None, None, None)),
+            _ => None,
+        };
+        if let Some((category, block_hash, block_height, block_time)) = departed {
+            if category != "Confirmed" {
+                timeline::record(txid, Event::Dropped(now));
+            }
+            archive::finalize(
+                &txid,
+                archive::Final {
+                    category,
+                    last_stage: Some(stage.name()),
+                    block_hash,
+                    block_height,
+                    block_time,
+                    timeline: timeline::get(&txid),
+                },
+                now,
+            );
+        }
+
+        println!(
+            "reconcile: {txid} held as {} cleared ({})",
+            stage.name(),
+            reason.code()
+        );
+        metrics::record_reconciled(reason.code());
+        let correction = reconcile::Correction {
+            txid: txid.to_string(),
+            stage: stage.name(),
+            reason: reason.code(),
+            at: now,
+        };
+        reconcile::record(correction.clone());
+        corrections.push(correction);
+    }
+
+    let still_tracked: HashSet<Txid> = STATE
+        .lock()
+        .unwrap()
+        .tracked()
+        .into_iter()
+        .map(|(t, ..)| t)
+        .collect();
+    reconcile::retain_spends(&still_tracked);
+    corrections
+}
+
+pub async fn run_reconciler() {
+    loop {
+        tokio::time::sleep(Duration::from_secs(RECONCILE_INTERVAL_SECS)).await;
+        let _ = tokio::task::spawn_blocking(|| {
+            reconcile_state(&connect_to_bitcoind(), &connect_to_cmempoold())
+        })
+        .await;
     }
+}
+
+pub async fn get_reconciliations() -> Json<Vec<reconcile::Correction>> {
+    Json(reconcile::corrections())
+}
+
+/// Runs a reconcile pass now instead of waiting for the next tick.
+pub async fn reconcile_now() -> (StatusCode, Json<serde_json::Value>) {
+    let result = tokio::task::spawn_blocking(|| {
+        reconcile_state(&connect_to_bitcoind(), &connect_to_cmempoold())
+    })
+    .await;
 
-    STATE.lock().unwrap().scheduled.insert(txid);
+    match result {
+        Ok(corrections) => (
+            StatusCode::OK,
+            Json(json!({"status":"ok","corrections":corrections})),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({
+                "status":"error",
+                "code":"reconcile_failed",
+                "error":e.to_string()
+            })),
+        ),
+    }
+}
 
+fn bead_error(e: braid::BeadError) -> (StatusCode, Json<serde_json::Value>) {
+    let status = match e {
+        braid::BeadError::Duplicate => StatusCode::CONFLICT,
+        _ => StatusCode::BAD_REQUEST,
+    };
     (
-        StatusCode::OK,
-        Json(json!({
-            "status":"ok",
-            "txid":txid.to_string(),
-            "message":"Transaction scheduled"
-        })),
+        status,
+        Json(json!({"status":"error","code":e.code(),"error":e.message()})),
     )
 }
 
+fn parse_bead_hash(hash: &str) -> Result<BlockHash, (StatusCode, Json<serde_json::Value>)> {
+    hash.parse::<BlockHash>().map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "status":"error",
+                "code":"invalid_bead_hash",
+                "error":format!("Invalid bead hash {hash}: {e}")
+            })),
+        )
+    })
+}
+
+// Work only counts for a bead that sends its header: the hash is taken
+// from it, so the target it claims has to be met for real
+fn parse_bead(req: BeadRequest) -> Result<Bead, braid::BeadError> {
+    let invalid =
+        |what: &str, value: &str| braid::BeadError::Invalid(format!("Invalid {what}: {value}"));
+    let header = req
+        .header
+        .as_deref()
+        .map(|h| {
+            Vec::<u8>::from_hex(h.trim())
+                .ok()
+                .and_then(|bytes| deserialize::<Header>(&bytes).ok())
+                .ok_or_else(|| invalid("header", h))
+        })
+        .transpose()?;
+    let claimed = req
+        .hash
+        .as_deref()
+        .map(|h| h.parse::<BlockHash>().map_err(|_| invalid("hash", h)))
+        .transpose()?;
+    let hash = match (&header, claimed) {
+        (Some(header), Some(claimed)) if header.block_hash() != claimed => {
+            return Err(braid::BeadError::Invalid(format!(
+                "Hash {claimed} does not match the header, which hashes to {}",
+                header.block_hash()
+            )))
+        }
+        (Some(header), _) => header.block_hash(),
+        (None, Some(claimed)) => claimed,
+        (None, None) => {
+            return Err(braid::BeadError::Invalid(
+                "A bead needs its header or at least its hash".to_string(),
+            ))
+        }
+    };
+    let parents = req
+        .parents
+        .iter()
+        .map(|p| p.parse::<BlockHash>().map_err(|_| invalid("parent", p)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let transactions = req
+        .transactions
+        .iter()
+        .map(|t| t.parse::<Txid>().map_err(|_| invalid("txid", t)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let bits = header.map(|h| h.bits.to_consensus());
+    let work = bits.map(braid::work_from_bits).transpose()?.unwrap_or(0);
+
+    let now = now_ts();
+    Ok(Bead {
+        hash,
+        parents,
+        transactions,
+        miner: req.miner,
+        payout_address: req.payout_address,
+        bits,
+        work,
+        timestamp: req.timestamp.unwrap_or(now),
+        received_at: now,
+    })
+}
+
+/// Stores a bead once its parents are known and it keeps the braid acyclic.
+pub async fn post_bead(Json(req): Json<BeadRequest>) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(address) = req.payout_address.as_deref() {
+        if let Err(resp) = payout_script(Some(address), &connect_to_bitcoind()) {
+            return resp;
+        }
+    }
+    match parse_bead(req).and_then(braid::insert) {
+        Ok(view) => (StatusCode::CREATED, Json(json!(view))),
+        Err(e) => bead_error(e),
+    }
+}
+
+pub async fn get_beads(Query(q): Query<BeadQuery>) -> Json<serde_json::Value> {
+    let limit = q.limit.unwrap_or(DEFAULT_BEAD_LIMIT).min(MAX_BEAD_LIMIT);
+    let beads = braid::list(q.miner.as_deref(), limit);
+    Json(json!({
+        "total":braid::len(),
+        "count":beads.len(),
+        "beads":beads
+    }))
+}
+
+pub async fn get_payout(Path(hash): Path<String>) -> (StatusCode, Json<serde_json::Value>) {
+    let hash = match hash.parse::<BlockHash>() {
+        Ok(h) => h,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "status":"error",
+                    "code":"invalid_block_hash",
+                    "error":format!("Invalid block hash {hash}: {e}")
+                })),
+            )
+        }
+    };
+
+    match ledger::get(&hash) {
+        Some(payout) => (StatusCode::OK, Json(json!(payout))),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "status":"error",
+                "code":"no_payout",
+                "error":"No payout recorded for this block"
+            })),
+        ),
+    }
+}
+
+pub async fn get_miner_earnings(Path(miner): Path<String>) -> Json<serde_json::Value> {
+    let blocks = ledger::earnings(&miner);
+    let pending = unpaid_shares(Some(&miner));
+    let pending_work = pending
+        .iter()
+        .fold(0u128, |acc, s| acc.saturating_add(s.work));
+    Json(json!({
+        "miner":miner,
+        "total_sats":blocks.iter().map(|b| b.amount_sats).sum::<u64>(),
+        "blocks":blocks,
+        "pending_beads":pending.len(),
+        "pending_work":pending_work.to_string()
+    }))
+}
+
+pub async fn get_bead_cohorts() -> Json<serde_json::Value> {
+    let cohorts = braid::cohort_views();
+    Json(json!({"count":cohorts.len(),"cohorts":cohorts}))
+}
+
+pub async fn get_bead_tips() -> Json<serde_json::Value> {
+    let tips = braid::tips();
+    Json(json!({"count":tips.len(),"tips":tips}))
+}
+
+pub async fn get_bead(Path(hash): Path<String>) -> (StatusCode, Json<serde_json::Value>) {
+    let hash = match parse_bead_hash(&hash) {
+        Ok(h) => h,
+        Err(resp) => return resp,
+    };
+
+    match braid::get(&hash) {
+        Some(view) => (StatusCode::OK, Json(json!(view))),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "status":"error",
+                "code":"bead_not_found",
+                "error":"Bead is not known"
+            })),
+        ),
+    }
+}
+
+pub async fn get_bead_ancestors(
+    Path(hash): Path<String>,
+    Query(q): Query<BeadQuery>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let hash = match parse_bead_hash(&hash) {
+        Ok(h) => h,
+        Err(resp) => return resp,
+    };
+    let limit = q.limit.unwrap_or(DEFAULT_BEAD_LIMIT).min(MAX_BEAD_LIMIT);
+
+    match braid::ancestors(&hash, limit) {
+        Some(ancestors) => (
+            StatusCode::OK,
+            Json(json!({
+                "hash":hash.to_string(),
+                "count":ancestors.len(),
+                "ancestors":ancestors.iter().map(|h| h.to_string()).collect::<Vec<_>>()
+            })),
+        ),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "status":"error",
+                "code":"bead_not_found",
+                "error":"Bead is not known"
+            })),
+        ),
+    }
+}
+
 // previous endpoint for commit (for backward compatibility)
 
 pub async fn commit_tx_to_cmempoold(
     Path(txid): Path<String>,
+    headers: HeaderMap,
 ) -> (StatusCode, Json<serde_json::Value>) {
-    commit_transaction(Path(txid)).await
+    commit_transaction(Path(txid), Query(CommitQuery { dry_run: None }), headers).await
 }
 
-pub async fn get_transaction_detail(Path(txid): Path<String>) -> Json<ApiTransaction> {
+pub async fn get_transaction_detail(
+    Path(txid): Path<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let txid = match parse_txid(&txid) {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
     let standard = connect_to_bitcoind();
     let committed = connect_to_cmempoold();
-    let txid_parsed = txid.parse::<Txid>().unwrap();
-    Json(build_tx(txid_parsed, &standard, &committed))
+    (
+        StatusCode::OK,
+        Json(json!(build_tx(txid, &standard, &committed))),
+    )
+}
+
+pub async fn get_transaction_timeline(
+    Path(txid): Path<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let txid = match parse_txid(&txid) {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
+
+    match timeline::get(&txid) {
+        Some(t) => {
+            let stages: BTreeMap<_, _> = t.stage_latencies().into_iter().collect();
+            (
+                StatusCode::OK,
+                Json(json!({
+                    "txid":txid.to_string(),
+                    "timeline":t,
+                    "stage_latencies":stages
+                })),
+            )
+        }
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "status":"error",
+                "code":"no_timeline",
+                "error":"No timeline recorded for transaction"
+            })),
+        ),
+    }
+}
+
+pub async fn get_latency_stats() -> Json<BTreeMap<&'static str, timeline::StageLatency>> {
+    Json(timeline::latency_stats())
 }
 
 pub async fn get_mempool_info() -> Json<ApiMempoolInfo> {
     let standard = connect_to_bitcoind();
     let committed = connect_to_cmempoold();
 
-    let std_txids = standard.get_raw_mempool().unwrap_or_default();
-    let cpool_txids = committed.get_raw_mempool().unwrap_or_default();
+    let std_txids =
+        time_rpc(BITCOIND, "getrawmempool", || standard.get_raw_mempool()).unwrap_or_default();
+    let cpool_txids =
+        time_rpc(CMEMPOOL, "getrawmempool", || committed.get_raw_mempool()).unwrap_or_default();
     let set: BTreeSet<_> = std_txids
         .into_iter()
         .chain(cpool_txids.into_iter())
@@ -431,10 +3790,16 @@ pub async fn get_mempool_info() -> Json<ApiMempoolInfo> {
     let mut histogram: BTreeMap<u64, u64> = BTreeMap::new();
 
     for txid in set.iter() {
-        let entry = standard
-            .get_mempool_entry(txid)
+        let entry = time_rpc(BITCOIND, "getmempoolentry", || {
+            standard.get_mempool_entry(txid)
+        })
+        .ok()
+        .or_else(|| {
+            time_rpc(CMEMPOOL, "getmempoolentry", || {
+                committed.get_mempool_entry(txid)
+            })
             .ok()
-            .or_else(|| committed.get_mempool_entry(txid).ok());
+        });
 
         if let Some(e) = entry {
             let vsize = e.vsize as u64;
@@ -463,13 +3828,540 @@ pub async fn get_mempool_info() -> Json<ApiMempoolInfo> {
     })
 }
 
+fn node_snapshot(node: &'static str, client: &Client) -> metrics::NodeSnapshot {
+    let height = time_rpc(node, "getblockcount", || client.get_block_count()).unwrap_or(0);
+    let info = time_rpc(node, "getmempoolinfo", || client.get_mempool_info()).ok();
+
+    metrics::NodeSnapshot {
+        node,
+        height,
+        mempool_vsize: info.as_ref().map(|i| i.bytes as u64).unwrap_or(0),
+        mempool_fee_sats: info.and_then(|i| i.total_fee).map(to_sats).unwrap_or(0),
+    }
+}
+
+// Category counts over both mempools, read from pipeline state without the
+// cleanup `build_tx` does, so a scrape never changes what it measures
+fn category_counts(standard: &Client, committed: &Client) -> Vec<(&'static str, usize)> {
+    let std_txids: HashSet<Txid> =
+        time_rpc(BITCOIND, "getrawmempool", || standard.get_raw_mempool())
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+    let cpool_txids: HashSet<Txid> =
+        time_rpc(CMEMPOOL, "getrawmempool", || committed.get_raw_mempool())
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+    let txids: Vec<(Txid, bool)> = std_txids
+        .union(&cpool_txids)
+        .map(|t| (*t, braid::attribution(t).is_some()))
+        .collect();
+
+    let mut counts: HashMap<&'static str, usize> = HashMap::new();
+    {
+        let state = STATE.lock().unwrap();
+        let seen = SEEN.lock().unwrap();
+        for (txid, in_bead) in &txids {
+            let in_std = std_txids.contains(txid);
+            let in_cpool = cpool_txids.contains(txid);
+            let category = stage_category(&state, txid, in_std, in_cpool, *in_bead).unwrap_or(
+                if seen.contains_key(txid) {
+                    "Replaced"
+                } else {
+                    "Unknown"
+                },
+            );
+            *counts.entry(category).or_insert(0) += 1;
+        }
+    }
+    CATEGORIES
+        .iter()
+        .map(|c| (*c, counts.get(c).copied().unwrap_or(0)))
+        .collect()
+}
+
+pub async fn get_metrics() -> impl IntoResponse {
+    let standard = connect_to_bitcoind();
+    let committed = connect_to_cmempoold();
+    let categories = category_counts(&standard, &committed);
+    let snapshot = metrics::Snapshot {
+        categories,
+        nodes: vec![
+            node_snapshot(BITCOIND, &standard),
+            node_snapshot(CMEMPOOL, &committed),
+        ],
+        store_sizes: store_sizes(),
+    };
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::render(&snapshot),
+    )
+}
+
 pub fn build_router() -> Router {
     Router::new()
-        .route("/transactions", get(get_transactions))
+        .route(
+            "/transactions",
+            get(get_transactions).post(broadcast_transaction),
+        )
         .route("/tx/{txid}", get(get_transaction_detail))
+        .route("/tx/{txid}/timeline", get(get_transaction_timeline))
+        .route("/tx/{txid}/committed-proof", get(get_committed_proof))
+        .route("/committed/root", get(get_committed_root))
+        .route("/stats/latency", get(get_latency_stats))
         .route("/mempool/info", get(get_mempool_info))
         .route("/beads/commit/{txid}", post(commit_tx_to_cmempoold)) // backward compatibility
+        .route("/beads", get(get_beads).post(post_bead))
+        .route("/beads/tips", get(get_bead_tips))
+        .route("/beads/cohorts", get(get_bead_cohorts))
+        .route("/beads/{hash}", get(get_bead))
+        .route("/beads/{hash}/ancestors", get(get_bead_ancestors))
         .route("/transactions/{txid}/commit", post(commit_transaction))
+        .route(
+            "/transactions/{txid}/commit/check",
+            get(check_commit_transaction),
+        )
         .route("/transactions/{txid}/propose", post(propose_transaction))
         .route("/transactions/{txid}/schedule", post(schedule_transaction))
+        .route("/transactions/{txid}/uncommit", post(uncommit_transaction))
+        .route(
+            "/transactions/{txid}/unpropose",
+            post(unpropose_transaction),
+        )
+        .route(
+            "/transactions/{txid}/unschedule",
+            post(unschedule_transaction),
+        )
+        .route("/operators", get(get_operators))
+        .route("/quorum", get(get_quorum).put(put_quorum))
+        .route("/transactions/{txid}/votes", get(get_transaction_votes))
+        .route("/audit/transitions", get(get_signed_transitions))
+        .route("/transactions/batch/commit", post(batch_commit))
+        .route("/transactions/batch/propose", post(batch_propose))
+        .route("/transactions/batch/schedule", post(batch_schedule))
+        .route("/capacity", get(get_capacity).put(put_capacity))
+        .route("/blocks", post(submit_block))
+        .route("/admin/mine", post(mine_blocks))
+        .route("/blocks/template", get(get_block_template))
+        .route("/blocks/{hash}/audit", get(get_block_audit))
+        .route("/reorgs", get(get_reorgs))
+        .route("/payouts/{block_hash}", get(get_payout))
+        .route("/miners/{id}/earnings", get(get_miner_earnings))
+        .route("/archive", get(get_archive))
+        .route(
+            "/archive/retention",
+            get(get_archive_retention).put(put_archive_retention),
+        )
+        .route("/archive/{txid}", get(get_archived_transaction))
+        .route("/admin/gc", get(get_gc).put(put_gc))
+        .route("/admin/reconcile", post(reconcile_now))
+        .route("/reconciliations", get(get_reconciliations))
+        .route("/policy", get(get_policy).put(put_policy))
+        .route("/policy/evaluate", post(evaluate_policy_now))
+        .route("/policy/report", get(get_policy_report))
+        .route("/metrics", get(get_metrics))
+        .route_layer(middleware::from_fn(metrics::track_http))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::blockdata::constants::genesis_block;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+
+    fn txid(n: u8) -> Txid {
+        Txid::from_byte_array([n; 32])
+    }
+
+    #[test]
+    fn test_withdrawn_tx_in_bead_is_not_committed() {
+        let mut state = StateStore::new();
+        let tx = txid(1);
+        state.apply(tx, Transition::Commit, true, None, 10).unwrap();
+        state
+            .apply(tx, Transition::Uncommit, true, None, 20)
+            .unwrap();
+
+        // Still in both mempools and attributed to a bead
+        assert_eq!(
+            stage_category(&state, &tx, true, true, true),
+            Some("Mempool")
+        );
+        assert_eq!(stage_category(&state, &tx, false, true, true), None);
+    }
+
+    #[test]
+    fn test_uncommitted_tx_left_in_cmempool_reads_mempool() {
+        let tx = txid(201);
+        STATE
+            .lock()
+            .unwrap()
+            .apply(tx, Transition::Commit, true, None, 10)
+            .unwrap();
+        assert_eq!(detect_category(&tx, true, true, 0), "Committed");
+
+        STATE
+            .lock()
+            .unwrap()
+            .apply(tx, Transition::Uncommit, true, None, 20)
+            .unwrap();
+        assert_eq!(detect_category(&tx, true, true, 0), "Mempool");
+        assert_eq!(detect_category(&tx, true, true, 1), "Confirmed");
+    }
+
+    #[test]
+    fn test_only_first_block_carries_requested_txs() {
+        let requested = [txid(1), txid(2)];
+        assert_eq!(block_targets(0, &requested), &requested);
+        assert!(block_targets(1, &requested).is_empty());
+        assert!(block_targets(2, &requested).is_empty());
+    }
+
+    fn mine_request(
+        blocks: Option<u32>,
+        source: Option<MineSource>,
+        txids: &[&str],
+    ) -> MineRequest {
+        MineRequest {
+            blocks,
+            source,
+            txids: txids.iter().map(|t| t.to_string()).collect(),
+            address: None,
+        }
+    }
+
+    #[test]
+    fn test_mine_source_defaults() {
+        let txid = txid(3).to_string();
+        assert!(mine_source(&mine_request(None, None, &[])) == MineSource::Scheduled);
+        assert!(mine_source(&mine_request(None, None, &[&txid])) == MineSource::Txids);
+        assert!(
+            mine_source(&mine_request(None, Some(MineSource::Template), &[&txid]))
+                == MineSource::Template
+        );
+    }
+
+    #[tokio::test]
+    async fn test_block_count_bounds() {
+        // Rejected before any node is contacted
+        for blocks in [0, MAX_MINE_BLOCKS + 1] {
+            let (status, Json(body)) =
+                mine_blocks(Json(mine_request(Some(blocks), None, &[]))).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(body["code"], "invalid_count");
+        }
+    }
+
+    #[test]
+    fn test_payout_defaults_to_anyone_can_spend() {
+        // No address means no node lookup
+        let script = payout_script(None, &connect_to_bitcoind()).unwrap();
+        assert_eq!(script.to_hex_string(), "51");
+    }
+
+    // Regtest genesis: bits 207fffff, met with nonce 2
+    const GENESIS_HEADER: &str = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4adae5494dffff7f2002000000";
+    const GENESIS_HASH: &str = "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206";
+
+    fn bead_request(header: Option<&str>, hash: Option<&str>) -> BeadRequest {
+        BeadRequest {
+            header: header.map(str::to_string),
+            hash: hash.map(str::to_string),
+            parents: Vec::new(),
+            transactions: Vec::new(),
+            miner: "alice".to_string(),
+            payout_address: None,
+            timestamp: Some(1),
+        }
+    }
+
+    #[test]
+    fn test_bead_hash_and_work_come_from_header() {
+        let bead = parse_bead(bead_request(Some(GENESIS_HEADER), None)).unwrap();
+        assert_eq!(bead.hash.to_string(), GENESIS_HASH);
+        assert_eq!(bead.bits, Some(0x207fffff));
+        assert_eq!(bead.work, 2);
+
+        let other = "00".repeat(31) + "01";
+        let err = parse_bead(bead_request(Some(GENESIS_HEADER), Some(&other)));
+        assert!(matches!(err, Err(braid::BeadError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_bead_without_header_carries_no_work() {
+        let bead = parse_bead(bead_request(None, Some(GENESIS_HASH))).unwrap();
+        assert_eq!(bead.bits, None);
+        assert_eq!(bead.work, 0);
+        assert!(parse_bead(bead_request(None, None)).is_err());
+    }
+
+    #[test]
+    fn test_bead_inclusion_counts_as_committed() {
+        let state = StateStore::new();
+        assert_eq!(
+            stage_category(&state, &txid(2), true, false, true),
+            Some("Committed")
+        );
+        assert_eq!(
+            stage_category(&state, &txid(2), true, false, false),
+            Some("Mempool")
+        );
+    }
+
+    fn batch_request(txids: &[&str], filter: Option<BatchFilter>) -> BatchRequest {
+        BatchRequest {
+            txids: txids.iter().map(|t| t.to_string()).collect(),
+            filter,
+            atomic: false,
+            signatures: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_batch_targets_deduplicated_in_order() {
+        let filter = BatchFilter {
+            category: Some("mempool".to_string()),
+            min_fee_rate: Some(10.0),
+        };
+        let req = batch_request(&["tx2", "tx1", "tx2"], Some(filter));
+        let txs = [("tx1", "Mempool", 12.0), ("tx3", "Mempool", 15.0)];
+
+        assert_eq!(batch_targets(&req, txs), vec!["tx2", "tx1", "tx3"]);
+    }
+
+    #[test]
+    fn test_batch_filter_fee_rate_and_category() {
+        let txs = [
+            ("low", "Mempool", 2.0),
+            ("high", "Mempool", 20.0),
+            ("committed", "Committed", 50.0),
+        ];
+
+        let filter = BatchFilter {
+            category: Some("Mempool".to_string()),
+            min_fee_rate: Some(10.0),
+        };
+        assert_eq!(
+            batch_targets(&batch_request(&[], Some(filter)), txs),
+            vec!["high"]
+        );
+        // Without a filter only the explicit txids are targeted
+        assert_eq!(
+            batch_targets(&batch_request(&["low"], None), txs),
+            vec!["low"]
+        );
+    }
+
+    #[test]
+    fn test_batch_status_codes() {
+        let entries = |statuses: &[&str]| -> Vec<serde_json::Value> {
+            statuses.iter().map(|s| json!({ "status": s })).collect()
+        };
+
+        assert_eq!(batch_status(&entries(&["ok", "ok"])), StatusCode::OK);
+        assert_eq!(
+            batch_status(&entries(&["ok", "pending"])),
+            StatusCode::ACCEPTED
+        );
+        // Votes still waiting on the quorum are not failures
+        assert_eq!(
+            batch_status(&entries(&["pending", "pending"])),
+            StatusCode::ACCEPTED
+        );
+        assert_eq!(
+            batch_status(&entries(&["pending", "error"])),
+            StatusCode::MULTI_STATUS
+        );
+        assert_eq!(
+            batch_status(&entries(&["ok", "error", "pending"])),
+            StatusCode::MULTI_STATUS
+        );
+        assert_eq!(
+            batch_status(&entries(&["error", "error"])),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    fn broadcast_request(hex: Option<String>, psbt: Option<&str>) -> BroadcastRequest {
+        BroadcastRequest {
+            hex,
+            psbt: psbt.map(str::to_string),
+            commit: false,
+        }
+    }
+
+    fn broadcast_code(req: &BroadcastRequest) -> Result<Txid, String> {
+        broadcast_payload(req, &connect_to_bitcoind())
+            .map(|tx| tx.txid())
+            .map_err(|(_, Json(body))| body["code"].as_str().unwrap_or_default().to_string())
+    }
+
+    #[test]
+    fn test_exactly_one_payload_required() {
+        let hex = Some("0200".to_string());
+        let both = broadcast_request(hex, Some("cHNidP8B"));
+        assert_eq!(
+            broadcast_code(&both).err().as_deref(),
+            Some("invalid_request")
+        );
+        let neither = broadcast_request(None, None);
+        assert_eq!(
+            broadcast_code(&neither).err().as_deref(),
+            Some("invalid_request")
+        );
+    }
+
+    #[test]
+    fn test_broadcast_hex_decoded_and_coinbase_refused() {
+        let coinbase = genesis_block(Network::Regtest).txdata.remove(0);
+        let mut spend = coinbase.clone();
+        spend.input[0].previous_output.txid = txid(5);
+
+        let req = broadcast_request(Some(format!(" {}\n", serialize_hex(&spend))), None);
+        assert_eq!(broadcast_code(&req), Ok(spend.txid()));
+
+        for (hex, code) in [
+            ("zz".to_string(), "invalid_hex"),
+            ("0200".to_string(), "malformed_tx"),
+            (serialize_hex(&coinbase), "malformed_tx"),
+        ] {
+            let req = broadcast_request(Some(hex), None);
+            assert_eq!(broadcast_code(&req).err().as_deref(), Some(code));
+        }
+    }
+
+    #[test]
+    fn test_if_match_header_parsing() {
+        let parse = |raw: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::IF_MATCH, raw.parse().unwrap());
+            expected_version(&headers).map_err(|(status, _)| status)
+        };
+
+        assert_eq!(parse("7"), Ok(Some(7)));
+        assert_eq!(parse("\"7\""), Ok(Some(7)));
+        assert_eq!(parse("W/\"7\""), Ok(Some(7)));
+        assert_eq!(parse("*"), Ok(None));
+        assert_eq!(parse("abc"), Err(StatusCode::BAD_REQUEST));
+        assert_eq!(expected_version(&HeaderMap::new()).ok(), Some(None));
+    }
+
+    #[test]
+    fn test_reverse_transition_errors_map_to_codes() {
+        for (err, status, code) in [
+            (
+                TransitionError::NotCommitted,
+                StatusCode::BAD_REQUEST,
+                "not_committed",
+            ),
+            (
+                TransitionError::NotProposed,
+                StatusCode::BAD_REQUEST,
+                "not_proposed",
+            ),
+            (
+                TransitionError::NotScheduled,
+                StatusCode::BAD_REQUEST,
+                "not_scheduled",
+            ),
+            (
+                TransitionError::StillProposed,
+                StatusCode::CONFLICT,
+                "proposed",
+            ),
+            (
+                TransitionError::StillScheduled,
+                StatusCode::CONFLICT,
+                "scheduled",
+            ),
+        ] {
+            let (got, Json(body)) = transition_error(&txid(9), err);
+            assert_eq!(got, status);
+            assert_eq!(body["code"], code);
+        }
+    }
+
+    #[test]
+    fn test_transition_metrics_are_labelled_by_error_code() {
+        let empty = metrics::Snapshot {
+            categories: Vec::new(),
+            nodes: Vec::new(),
+            store_sizes: Vec::new(),
+        };
+        for resp in [
+            (StatusCode::OK, json!({ "status": "ok" })),
+            (
+                StatusCode::BAD_REQUEST,
+                json!({ "status": "error", "code": "not_committed" }),
+            ),
+            (StatusCode::BAD_REQUEST, json!({ "status": "error" })),
+        ] {
+            let _ = observe_transition("label_test", (resp.0, Json(resp.1)));
+        }
+
+        let out = metrics::render(&empty);
+        for (result, code) in [
+            ("success", "ok"),
+            ("failure", "not_committed"),
+            ("failure", "unknown"),
+        ] {
+            let line = format!(
+                "braidpool_transitions_total{{transition=\"label_test\",result=\"{result}\",code=\"{code}\"}} 1"
+            );
+            assert!(out.contains(&line), "missing {line}");
+        }
+    }
+
+    fn proposal_code(
+        proposer: Option<&str>,
+        note: Option<&str>,
+        bead: Option<&str>,
+    ) -> Option<String> {
+        let req = ProposalRequest {
+            proposer: proposer.map(str::to_string),
+            note: note.map(str::to_string),
+            bead: bead.map(str::to_string),
+            target_height: None,
+        };
+        check_proposal(Some(Json(req)))
+            .err()
+            .map(|(_, Json(body))| body["code"].as_str().unwrap_or_default().to_string())
+    }
+
+    #[test]
+    fn test_proposal_metadata_is_optional() {
+        assert!(check_proposal(None).unwrap().is_none());
+        assert_eq!(proposal_code(Some("miner-1"), Some("fee bump"), None), None);
+    }
+
+    #[test]
+    fn test_proposal_rejects_bad_proposer_or_long_note() {
+        let long_proposer = "p".repeat(MAX_PROPOSER_LEN + 1);
+        let long_note = "n".repeat(MAX_PROPOSAL_NOTE_LEN + 1);
+        let full_note = "n".repeat(MAX_PROPOSAL_NOTE_LEN);
+        assert_eq!(
+            proposal_code(Some(""), None, None).as_deref(),
+            Some("invalid_proposal")
+        );
+        assert_eq!(
+            proposal_code(Some(&long_proposer), None, None).as_deref(),
+            Some("invalid_proposal")
+        );
+        assert_eq!(
+            proposal_code(None, Some(&long_note), None).as_deref(),
+            Some("invalid_proposal")
+        );
+        assert_eq!(proposal_code(None, Some(&full_note), None), None);
+    }
+
+    #[test]
+    fn test_proposal_rejects_malformed_bead_hash() {
+        assert_eq!(
+            proposal_code(None, None, Some("abc")).as_deref(),
+            Some("invalid_bead_hash")
+        );
+        assert_eq!(proposal_code(None, None, Some(&"0f".repeat(32))), None);
+    }
 }
@@ -0,0 +1,334 @@
+use crate::timeline::Timeline;
+use bitcoincore_rpc::bitcoin::Txid;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// What is remembered about a tx once the nodes may have forgotten it.
+#[derive(Clone, Serialize)]
+pub(crate) struct ArchivedTx {
+    pub txid: String,
+    pub category: String, // last category seen, final once archived
+    pub last_stage: Option<&'static str>, // pipeline stage held before it left
+    pub fee_sats: u64,
+    pub fee_rate: f64,
+    pub vsize: u64,
+    pub weight: Option<u64>,
+    pub inputs: usize,
+    pub outputs: usize,
+    pub rbf_signaled: bool,
+    pub first_seen: u64,
+    pub updated_at: u64,
+    pub finalized_at: Option<u64>,
+    pub block_hash: Option<String>,
+    pub block_height: Option<u64>,
+    pub block_time: Option<u64>,
+    pub timeline: Option<Timeline>,
+}
+
+/// Fee and size data taken from a mempool entry.
+pub(crate) struct Observed {
+    pub category: String,
+    pub fee_sats: u64,
+    pub vsize: u64,
+    pub weight: Option<u64>,
+    pub inputs: usize,
+    pub outputs: usize,
+    pub rbf_signaled: bool,
+}
+
+/// How the tx left the pipeline.
+pub(crate) struct Final {
+    pub category: &'static str, // Confirmed | Replaced | Dropped
+    pub last_stage: Option<&'static str>,
+    pub block_hash: Option<String>,
+    pub block_height: Option<u64>,
+    pub block_time: Option<u64>,
+    pub timeline: Option<Timeline>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Retention {
+    pub max_entries: usize,
+    pub max_age_secs: u64, // counted from the last update
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention {
+            max_entries: 50_000,
+            max_age_secs: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+impl Retention {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.max_entries == 0 {
+            return Err("max_entries must be at least 1".to_string());
+        }
+        if self.max_age_secs == 0 {
+            return Err("max_age_secs must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+struct Archive {
+    entries: HashMap<Txid, ArchivedTx>,
+    retention: Retention,
+}
+
+impl Archive {
+    fn new() -> Self {
+        Archive {
+            entries: HashMap::new(),
+            retention: Retention::default(),
+        }
+    }
+
+    fn observe(&mut self, txid: Txid, seen: Observed, now: u64) {
+        let fee_rate = if seen.vsize > 0 {
+            seen.fee_sats as f64 / seen.vsize as f64
+        } else {
+            0.0
+        };
+
+        let entry = self.entries.entry(txid).or_insert_with(|| ArchivedTx {
+            txid: txid.to_string(),
+            category: seen.category.clone(),
+            last_stage: None,
+            fee_sats: 0,
+            fee_rate: 0.0,
+            vsize: 0,
+            weight: None,
+            inputs: 0,
+            outputs: 0,
+            rbf_signaled: false,
+            first_seen: now,
+            updated_at: now,
+            finalized_at: None,
+            block_hash: None,
+            block_height: None,
+            block_time: None,
+            timeline: None,
+        });
+        entry.category = seen.category;
+        entry.fee_sats = seen.fee_sats;
+        entry.fee_rate = fee_rate;
+        entry.vsize = seen.vsize;
+        entry.weight = seen.weight.or(entry.weight);
+        entry.inputs = seen.inputs;
+        entry.outputs = seen.outputs;
+        entry.rbf_signaled = seen.rbf_signaled;
+        entry.updated_at = now;
+        // Back in a mempool, e.g. after a reorg
+        entry.finalized_at = None;
+    }
+
+    fn finalize(&mut self, txid: &Txid, done: Final, now: u64) {
+        let Some(entry) = self.entries.get_mut(txid) else {
+            return;
+        };
+        if entry.finalized_at.is_some() && entry.category == done.category {
+            return;
+        }
+        entry.category = done.category.to_string();
+        entry.last_stage = done.last_stage.or(entry.last_stage);
+        entry.block_hash = done.block_hash;
+        entry.block_height = done.block_height;
+        entry.block_time = done.block_time;
+        entry.timeline = done.timeline;
+        entry.finalized_at = Some(now);
+        entry.updated_at = now;
+    }
+
+    fn list(&self, category: Option<&str>, limit: usize) -> Vec<ArchivedTx> {
+        let mut out: Vec<ArchivedTx> = self
+            .entries
+            .values()
+            .filter(|e| e.finalized_at.is_some())
+            .filter(|e| category.is_none_or(|c| c.eq_ignore_ascii_case(&e.category)))
+            .cloned()
+            .collect();
+        out.sort_by(|a, b| {
+            b.finalized_at
+                .cmp(&a.finalized_at)
+                .then(a.txid.cmp(&b.txid))
+        });
+        out.truncate(limit);
+        out
+    }
+
+    // Expired entries go first, then the least recently updated over the cap
+    fn prune(&mut self, now: u64) -> usize {
+        let before = self.entries.len();
+        let max_age = self.retention.max_age_secs;
+        self.entries
+            .retain(|_, e| now.saturating_sub(e.updated_at) <= max_age);
+
+        let excess = self
+            .entries
+            .len()
+            .saturating_sub(self.retention.max_entries);
+        if excess > 0 {
+            let mut oldest: Vec<(u64, Txid)> = self
+                .entries
+                .iter()
+                .map(|(txid, e)| (e.updated_at, *txid))
+                .collect();
+            oldest.sort_unstable();
+            for (_, txid) in oldest.into_iter().take(excess) {
+                self.entries.remove(&txid);
+            }
+        }
+        before - self.entries.len()
+    }
+}
+
+static ARCHIVE: Lazy<Mutex<Archive>> = Lazy::new(|| Mutex::new(Archive::new()));
+
+/// Refreshes fee and size data for a tx still sitting in a mempool.
+pub(crate) fn observe(txid: Txid, seen: Observed, now: u64) {
+    ARCHIVE.lock().unwrap().observe(txid, seen, now);
+}
+
+/// Marks a tx the pipeline has seen as done; unknown txs are not archived.
+pub(crate) fn finalize(txid: &Txid, done: Final, now: u64) {
+    ARCHIVE.lock().unwrap().finalize(txid, done, now);
+}
+
+pub(crate) fn get(txid: &Txid) -> Option<ArchivedTx> {
+    ARCHIVE.lock().unwrap().entries.get(txid).cloned()
+}
+
+/// Finalized entries, most recently finalized first.
+pub(crate) fn list(category: Option<&str>, limit: usize) -> Vec<ArchivedTx> {
+    ARCHIVE.lock().unwrap().list(category, limit)
+}
+
+pub(crate) fn len() -> usize {
+    ARCHIVE.lock().unwrap().entries.len()
+}
+
+pub(crate) fn retention() -> Retention {
+    ARCHIVE.lock().unwrap().retention.clone()
+}
+
+pub(crate) fn set_retention(retention: Retention, now: u64) -> usize {
+    let mut archive = ARCHIVE.lock().unwrap();
+    archive.retention = retention;
+    archive.prune(now)
+}
+
+/// Applies the retention limits; returns how many entries were dropped.
+pub(crate) fn prune(now: u64) -> usize {
+    ARCHIVE.lock().unwrap().prune(now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+
+    fn txid(n: u8) -> Txid {
+        Txid::from_byte_array([n; 32])
+    }
+
+    fn seen(fee_sats: u64) -> Observed {
+        Observed {
+            category: "Mempool".to_string(),
+            fee_sats,
+            vsize: 100,
+            weight: Some(400),
+            inputs: 1,
+            outputs: 2,
+            rbf_signaled: false,
+        }
+    }
+
+    fn done(category: &'static str) -> Final {
+        Final {
+            category,
+            last_stage: Some("Scheduled"),
+            block_hash: None,
+            block_height: None,
+            block_time: None,
+            timeline: None,
+        }
+    }
+
+    #[test]
+    fn test_expired_entries_dropped_first() {
+        let mut archive = Archive::new();
+        archive.retention = Retention {
+            max_entries: 10,
+            max_age_secs: 500,
+        };
+        archive.observe(txid(1), seen(100), 100);
+        archive.observe(txid(2), seen(100), 900);
+
+        assert_eq!(archive.prune(1000), 1);
+        assert!(archive.entries.contains_key(&txid(2)));
+    }
+
+    #[test]
+    fn test_oldest_dropped_over_the_cap() {
+        let mut archive = Archive::new();
+        archive.retention = Retention {
+            max_entries: 2,
+            max_age_secs: 1000,
+        };
+        archive.observe(txid(1), seen(100), 10);
+        archive.observe(txid(2), seen(100), 20);
+        archive.observe(txid(3), seen(100), 20);
+        archive.finalize(&txid(2), done("Confirmed"), 30);
+
+        assert_eq!(archive.prune(40), 1);
+        assert!(!archive.entries.contains_key(&txid(1)));
+    }
+
+    #[test]
+    fn test_list_newest_finalized_first_and_skips_live() {
+        let mut archive = Archive::new();
+        for n in 1..=3 {
+            archive.observe(txid(n), seen(100 * n as u64), 10);
+        }
+        archive.finalize(&txid(2), done("Confirmed"), 30);
+        archive.finalize(&txid(3), done("Dropped"), 20);
+
+        let listed: Vec<String> = archive.list(None, 10).into_iter().map(|e| e.txid).collect();
+        assert_eq!(listed, vec![txid(2).to_string(), txid(3).to_string()]);
+
+        let confirmed = archive.list(Some("confirmed"), 10);
+        assert_eq!(confirmed.len(), 1);
+        assert_eq!(confirmed[0].last_stage, Some("Scheduled"));
+        assert_eq!(confirmed[0].fee_rate, 2.0);
+    }
+
+    #[test]
+    fn test_unknown_tx_not_archived_and_reobserved_tx_is_live() {
+        let mut archive = Archive::new();
+        archive.finalize(&txid(1), done("Confirmed"), 10);
+        assert!(archive.entries.is_empty());
+
+        // Back in a mempool after a reorg
+        archive.observe(txid(2), seen(100), 10);
+        archive.finalize(&txid(2), done("Confirmed"), 20);
+        archive.observe(txid(2), seen(100), 30);
+        assert!(archive.list(None, 10).is_empty());
+    }
+
+    #[test]
+    fn test_zero_retention_rejected() {
+        let retention = |max_entries, max_age_secs| Retention {
+            max_entries,
+            max_age_secs,
+        };
+        assert!(retention(0, 60).validate().is_err());
+        assert!(retention(10, 0).validate().is_err());
+        assert!(retention(10, 60).validate().is_ok());
+    }
+}
@@ -0,0 +1,160 @@
+use crate::gc;
+use bitcoincore_rpc::bitcoin::{BlockHash, Txid};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+/// How a connected block compares with what the pipeline had lined up.
+#[derive(Clone, Serialize)]
+pub(crate) struct Audit {
+    pub hash: String,
+    pub height: Option<u64>,
+    pub audited_at: u64,
+    pub scheduled_included: Vec<String>,
+    pub scheduled_missing: Vec<String>,
+    pub proposed_included: Vec<String>, // proposed but not yet scheduled
+    pub unexpected: Vec<String>,        // neither scheduled nor proposed
+    pub expected_fees: u64,             // sats across the Scheduled set
+    pub mined_fees: Option<u64>,        // sats across the block, when the node reports it
+    pub fee_difference: Option<i64>,    // mined minus expected
+    pub hit_rate: Option<f64>,          // share of scheduled txs that made it in
+}
+
+pub(crate) struct Comparison {
+    pub scheduled_included: Vec<Txid>,
+    pub scheduled_missing: Vec<Txid>,
+    pub proposed_included: Vec<Txid>,
+    pub unexpected: Vec<Txid>,
+}
+
+impl Comparison {
+    /// Share of scheduled txs that made it into the block; None when
+    /// nothing was scheduled.
+    pub(crate) fn hit_rate(&self) -> Option<f64> {
+        let total = self.scheduled_included.len() + self.scheduled_missing.len();
+        (total > 0).then(|| self.scheduled_included.len() as f64 / total as f64)
+    }
+}
+
+static AUDITS: Lazy<Mutex<HashMap<BlockHash, Audit>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// Blocks already handed to the audit, so each one is processed once, with
+// when they were claimed
+static CONNECTED: Lazy<Mutex<HashMap<BlockHash, u64>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Claims `hash` for auditing; false if it was already claimed.
+pub(crate) fn begin(hash: BlockHash, now: u64) -> bool {
+    CONNECTED.lock().unwrap().insert(hash, now).is_none()
+}
+
+/// Lets a block disconnected by a reorg be audited again if it comes back.
+pub(crate) fn forget(hash: &BlockHash) {
+    CONNECTED.lock().unwrap().remove(hash);
+}
+
+pub(crate) fn store(hash: BlockHash, audit: Audit) {
+    AUDITS.lock().unwrap().insert(hash, audit);
+}
+
+pub(crate) fn get(hash: &BlockHash) -> Option<Audit> {
+    AUDITS.lock().unwrap().get(hash).cloned()
+}
+
+/// Drops audits and claims older than `max_age`, then the oldest past
+/// `max_entries`; a block that old is too deep to connect again. Returns
+/// how many audits went.
+pub(crate) fn prune(now: u64, max_age: u64, max_entries: usize) -> usize {
+    gc::evict(&mut CONNECTED.lock().unwrap(), now, max_age, max_entries);
+
+    let mut audits = AUDITS.lock().unwrap();
+    let mut stamps: HashMap<BlockHash, u64> =
+        audits.iter().map(|(h, a)| (*h, a.audited_at)).collect();
+    let evicted = gc::evict(&mut stamps, now, max_age, max_entries);
+    for h in &evicted {
+        audits.remove(h);
+    }
+    evicted.len()
+}
+
+pub(crate) fn len() -> usize {
+    AUDITS.lock().unwrap().len()
+}
+
+/// Splits a block's txids against the Scheduled and Proposed sets.
+///
+/// Results keep block order; missing txs are sorted by txid.
+pub(crate) fn compare(
+    block_txids: &[Txid],
+    scheduled: &HashSet<Txid>,
+    proposed: &HashSet<Txid>,
+) -> Comparison {
+    let mut scheduled_included = Vec::new();
+    let mut proposed_included = Vec::new();
+    let mut unexpected = Vec::new();
+    for txid in block_txids {
+        if scheduled.contains(txid) {
+            scheduled_included.push(*txid);
+        } else if proposed.contains(txid) {
+            proposed_included.push(*txid);
+        } else {
+            unexpected.push(*txid);
+        }
+    }
+
+    let in_block: HashSet<&Txid> = block_txids.iter().collect();
+    let mut scheduled_missing: Vec<Txid> = scheduled
+        .iter()
+        .filter(|t| !in_block.contains(t))
+        .copied()
+        .collect();
+    scheduled_missing.sort();
+
+    Comparison {
+        scheduled_included,
+        scheduled_missing,
+        proposed_included,
+        unexpected,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+
+    fn txid(n: u8) -> Txid {
+        Txid::from_byte_array([n; 32])
+    }
+
+    #[test]
+    fn test_block_split_against_pipeline_sets() {
+        let scheduled = HashSet::from([txid(1), txid(2), txid(3)]);
+        let proposed = HashSet::from([txid(4)]);
+        let block = [txid(2), txid(9), txid(4), txid(1)];
+
+        let comparison = compare(&block, &scheduled, &proposed);
+        assert_eq!(comparison.scheduled_included, vec![txid(2), txid(1)]);
+        assert_eq!(comparison.scheduled_missing, vec![txid(3)]);
+        assert_eq!(comparison.proposed_included, vec![txid(4)]);
+        assert_eq!(comparison.unexpected, vec![txid(9)]);
+        assert_eq!(comparison.hit_rate(), Some(2.0 / 3.0));
+    }
+
+    #[test]
+    fn test_no_hit_rate_without_scheduled_txs() {
+        let comparison = compare(&[txid(5)], &HashSet::new(), &HashSet::new());
+        assert_eq!(comparison.hit_rate(), None);
+        assert_eq!(comparison.unexpected, vec![txid(5)]);
+    }
+
+    #[test]
+    fn test_block_claimed_once_until_forgotten() {
+        let hash = BlockHash::from_byte_array([0xa1; 32]);
+        assert!(begin(hash, 10));
+        assert!(!begin(hash, 20));
+
+        forget(&hash);
+        assert!(begin(hash, 30));
+    }
+}
@@ -0,0 +1,140 @@
+use bitcoincore_rpc::bitcoin::consensus::deserialize;
+use bitcoincore_rpc::bitcoin::hex::FromHex;
+use bitcoincore_rpc::bitcoin::{Block, Txid};
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum BlockError {
+    InvalidHex,
+    Malformed(String),
+    NoTransactions,
+    BadMerkleRoot,
+    BadWitnessCommitment,
+    HighHash,
+}
+
+impl BlockError {
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            BlockError::InvalidHex => "invalid_hex",
+            BlockError::Malformed(_) => "malformed_block",
+            BlockError::NoTransactions => "no_coinbase",
+            BlockError::BadMerkleRoot => "bad_merkle_root",
+            BlockError::BadWitnessCommitment => "bad_witness_commitment",
+            BlockError::HighHash => "high_hash",
+        }
+    }
+
+    pub(crate) fn message(&self) -> String {
+        match self {
+            BlockError::InvalidHex => "Block is not valid hex".to_string(),
+            BlockError::Malformed(e) => format!("Block does not deserialize: {e}"),
+            BlockError::NoTransactions => "Block has no coinbase".to_string(),
+            BlockError::BadMerkleRoot => "Merkle root does not match the transactions".to_string(),
+            BlockError::BadWitnessCommitment => {
+                "Witness commitment does not match the transactions".to_string()
+            }
+            BlockError::HighHash => "Block hash does not meet its target".to_string(),
+        }
+    }
+}
+
+pub(crate) fn decode(hex: &str) -> Result<Block, BlockError> {
+    let bytes = Vec::<u8>::from_hex(hex.trim()).map_err(|_| BlockError::InvalidHex)?;
+    deserialize(&bytes).map_err(|e| BlockError::Malformed(e.to_string()))
+}
+
+/// Context-free checks run before a block is handed to the nodes.
+///
+/// Anything that needs chain state (prev block, difficulty, spent
+/// outputs) is left to `submitblock`.
+pub(crate) fn validate(block: &Block) -> Result<(), BlockError> {
+    if block.txdata.first().is_none_or(|tx| !tx.is_coinbase()) {
+        return Err(BlockError::NoTransactions);
+    }
+    if !block.check_merkle_root() {
+        return Err(BlockError::BadMerkleRoot);
+    }
+    if !block.check_witness_commitment() {
+        return Err(BlockError::BadWitnessCommitment);
+    }
+    block
+        .header
+        .validate_pow(block.header.target())
+        .map_err(|_| BlockError::HighHash)?;
+    Ok(())
+}
+
+/// `submitblock` returns null on success and a reason string otherwise;
+/// a node that already has the block counts as accepted.
+pub(crate) fn submit_accepted(result: Option<&str>) -> bool {
+    matches!(result, None | Some("duplicate"))
+}
+
+/// Every non-coinbase txid in block order.
+pub(crate) fn confirmed_txids(block: &Block) -> Vec<Txid> {
+    block.txdata.iter().skip(1).map(|tx| tx.txid()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::absolute::LockTime;
+    use bitcoincore_rpc::bitcoin::blockdata::constants::genesis_block;
+    use bitcoincore_rpc::bitcoin::consensus::encode::serialize_hex;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use bitcoincore_rpc::bitcoin::{Network, TxMerkleNode};
+
+    #[test]
+    fn test_coinbase_not_reported_confirmed() {
+        let mut block = genesis_block(Network::Regtest);
+        let coinbase = block.txdata[0].txid();
+        let mut spend = block.txdata[0].clone();
+        // Any tx other than the coinbase will do; a different lock time gives it its own txid
+        spend.lock_time = LockTime::from_consensus(1);
+        block.txdata.push(spend.clone());
+
+        assert_eq!(confirmed_txids(&block), vec![spend.txid()]);
+        assert!(!confirmed_txids(&block).contains(&coinbase));
+    }
+
+    #[test]
+    fn test_submitblock_result_interpretation() {
+        assert!(submit_accepted(None));
+        assert!(submit_accepted(Some("duplicate")));
+        assert!(!submit_accepted(Some("inconclusive")));
+        assert!(!submit_accepted(Some("high-hash")));
+        assert!(!submit_accepted(Some("bad-txnmrklroot")));
+    }
+
+    #[test]
+    fn test_invalid_hex_rejected_before_submission() {
+        assert_eq!(decode("zz").err(), Some(BlockError::InvalidHex));
+        assert_eq!(decode("abc").err(), Some(BlockError::InvalidHex));
+        assert!(matches!(decode("00ff"), Err(BlockError::Malformed(_))));
+
+        let genesis = genesis_block(Network::Regtest);
+        let hex = format!(" {}\n", serialize_hex(&genesis));
+        assert_eq!(decode(&hex).unwrap().block_hash(), genesis.block_hash());
+    }
+
+    #[test]
+    fn test_validate_catches_tampered_blocks() {
+        let genesis = genesis_block(Network::Regtest);
+        assert_eq!(validate(&genesis), Ok(()));
+
+        let mut no_coinbase = genesis.clone();
+        no_coinbase.txdata.clear();
+        assert_eq!(validate(&no_coinbase), Err(BlockError::NoTransactions));
+
+        let mut bad_root = genesis.clone();
+        bad_root.header.merkle_root = TxMerkleNode::all_zeros();
+        assert_eq!(validate(&bad_root), Err(BlockError::BadMerkleRoot));
+
+        // Regtest's target is met by about half of all hashes; find a miss
+        let mut high = genesis;
+        while high.header.validate_pow(high.header.target()).is_ok() {
+            high.header.nonce += 1;
+        }
+        assert_eq!(validate(&high), Err(BlockError::HighHash));
+    }
+}
@@ -0,0 +1,559 @@
+use bitcoincore_rpc::bitcoin::{BlockHash, CompactTarget, Target, Txid};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+
+// Longest miner identifier accepted on a bead
+pub(crate) const MAX_MINER_LEN: usize = 128;
+
+/// A share in the braid: a block candidate that may name several parents.
+#[derive(Clone)]
+pub(crate) struct Bead {
+    pub hash: BlockHash,
+    pub parents: Vec<BlockHash>,
+    pub transactions: Vec<Txid>, // committed tx set, in bead order
+    pub miner: String,
+    pub payout_address: Option<String>,
+    pub bits: Option<u32>, // share target from the header; beads sent without one carry no work
+    pub work: u128,
+    pub timestamp: u64,   // as claimed by the miner
+    pub received_at: u64, // when this node stored it
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum BeadError {
+    Duplicate,
+    UnknownParent(BlockHash),
+    Cycle,
+    HighHash,
+    Invalid(String),
+}
+
+impl BeadError {
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            BeadError::Duplicate => "bead_exists",
+            BeadError::UnknownParent(_) => "unknown_parent",
+            BeadError::Cycle => "bead_cycle",
+            BeadError::HighHash => "high_hash",
+            BeadError::Invalid(_) => "invalid_bead",
+        }
+    }
+
+    pub(crate) fn message(&self) -> String {
+        match self {
+            BeadError::Duplicate => "Bead is already stored".to_string(),
+            BeadError::UnknownParent(p) => format!("Parent bead {p} is not known"),
+            BeadError::Cycle => "Bead would make the braid cyclic".to_string(),
+            BeadError::HighHash => "Bead hash does not meet its target".to_string(),
+            BeadError::Invalid(e) => e.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct BeadView {
+    pub hash: String,
+    pub parents: Vec<String>,
+    pub children: Vec<String>,
+    pub transactions: Vec<String>,
+    pub miner: String,
+    pub payout_address: Option<String>,
+    pub bits: Option<String>, // compact target as hex, e.g. 207fffff
+    pub work: String,         // decimal, may exceed 2^53
+    pub timestamp: u64,
+    pub received_at: u64,
+}
+
+/// Where a committed tx first appears in the braid's total order.
+#[derive(Clone, Copy)]
+pub(crate) struct Attribution {
+    pub bead: BlockHash,
+    pub cohort: usize,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct CohortView {
+    pub index: usize,
+    pub beads: Vec<String>, // in total order
+    pub open: bool,         // the latest cohort, which new beads may still extend
+}
+
+// Derived from the beads and rebuilt after every insert
+struct Ordering {
+    cohorts: Vec<Vec<BlockHash>>,
+    attribution: HashMap<Txid, Attribution>,
+}
+
+struct Braid {
+    beads: HashMap<BlockHash, Bead>,
+    children: HashMap<BlockHash, Vec<BlockHash>>,
+    tips: BTreeSet<BlockHash>, // beads nothing builds on yet
+    ordering: Option<Ordering>,
+}
+
+static BRAID: Lazy<Mutex<Braid>> = Lazy::new(|| Mutex::new(Braid::new()));
+
+impl Braid {
+    fn new() -> Self {
+        Braid {
+            beads: HashMap::new(),
+            children: HashMap::new(),
+            tips: BTreeSet::new(),
+            ordering: None,
+        }
+    }
+
+    fn insert(&mut self, bead: Bead) -> Result<BeadView, BeadError> {
+        check(self, &bead)?;
+
+        for p in &bead.parents {
+            self.tips.remove(p);
+            self.children.entry(*p).or_default().push(bead.hash);
+        }
+        self.tips.insert(bead.hash);
+        self.ordering = None;
+        let view = self.view(&bead);
+        self.beads.insert(bead.hash, bead);
+        Ok(view)
+    }
+
+    fn ancestors(&self, hash: &BlockHash, limit: usize) -> Option<Vec<BlockHash>> {
+        let bead = self.beads.get(hash)?;
+
+        let mut out = Vec::new();
+        let mut seen = HashSet::new();
+        let mut queue: VecDeque<BlockHash> = bead.parents.iter().copied().collect();
+        while let Some(h) = queue.pop_front() {
+            if out.len() >= limit {
+                break;
+            }
+            if !seen.insert(h) {
+                continue;
+            }
+            out.push(h);
+            if let Some(b) = self.beads.get(&h) {
+                queue.extend(b.parents.iter().copied());
+            }
+        }
+        Some(out)
+    }
+
+    fn view(&self, bead: &Bead) -> BeadView {
+        BeadView {
+            hash: bead.hash.to_string(),
+            parents: bead.parents.iter().map(|p| p.to_string()).collect(),
+            children: self
+                .children
+                .get(&bead.hash)
+                .map(|c| c.iter().map(|h| h.to_string()).collect())
+                .unwrap_or_default(),
+            transactions: bead.transactions.iter().map(|t| t.to_string()).collect(),
+            miner: bead.miner.clone(),
+            payout_address: bead.payout_address.clone(),
+            bits: bead.bits.map(|b| format!("{b:08x}")),
+            work: bead.work.to_string(),
+            timestamp: bead.timestamp,
+            received_at: bead.received_at,
+        }
+    }
+
+    fn ordering(&mut self) -> &Ordering {
+        if self.ordering.is_none() {
+            let cohorts = cohorts(&self.beads);
+            let mut attribution = HashMap::new();
+            for (index, cohort) in cohorts.iter().enumerate() {
+                for hash in cohort {
+                    for txid in &self.beads[hash].transactions {
+                        attribution.entry(*txid).or_insert(Attribution {
+                            bead: *hash,
+                            cohort: index,
+                        });
+                    }
+                }
+            }
+            self.ordering = Some(Ordering {
+                cohorts,
+                attribution,
+            });
+        }
+        self.ordering.as_ref().expect("just built")
+    }
+
+    // Whether `target` can be reached by walking parent links from `from`
+    fn reaches(&self, from: &[BlockHash], target: &BlockHash) -> bool {
+        let mut seen = HashSet::new();
+        let mut stack = from.to_vec();
+        while let Some(h) = stack.pop() {
+            if h == *target {
+                return true;
+            }
+            if seen.insert(h) {
+                if let Some(b) = self.beads.get(&h) {
+                    stack.extend(b.parents.iter().copied());
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Expected hashes needed to meet the target in `bits`.
+///
+/// Targets easier than regtest's are refused; work beyond u128 saturates.
+pub(crate) fn work_from_bits(bits: u32) -> Result<u128, BeadError> {
+    let target = Target::from_compact(CompactTarget::from_consensus(bits));
+    if target == Target::ZERO || target > Target::MAX_ATTAINABLE_REGTEST {
+        return Err(BeadError::Invalid(format!(
+            "bits {bits:08x} is out of range"
+        )));
+    }
+    let bytes = target.to_work().to_be_bytes();
+    if bytes[..16].iter().any(|b| *b != 0) {
+        return Ok(u128::MAX);
+    }
+    let mut low = [0u8; 16];
+    low.copy_from_slice(&bytes[16..]);
+    Ok(u128::from_be_bytes(low))
+}
+
+/// Checks a bead before it goes in: parents must be stored, distinct and
+/// must not lead back to the bead itself.
+fn check(braid: &Braid, bead: &Bead) -> Result<(), BeadError> {
+    if braid.beads.contains_key(&bead.hash) {
+        return Err(BeadError::Duplicate);
+    }
+    if bead.miner.trim().is_empty() || bead.miner.len() > MAX_MINER_LEN {
+        return Err(BeadError::Invalid(format!(
+            "miner must be 1 to {MAX_MINER_LEN} characters"
+        )));
+    }
+    let distinct: HashSet<&BlockHash> = bead.parents.iter().collect();
+    if distinct.len() != bead.parents.len() {
+        return Err(BeadError::Invalid("parents must be distinct".to_string()));
+    }
+    let txs: HashSet<&Txid> = bead.transactions.iter().collect();
+    if txs.len() != bead.transactions.len() {
+        return Err(BeadError::Invalid(
+            "transactions must be distinct".to_string(),
+        ));
+    }
+    if let Some(p) = bead.parents.iter().find(|p| !braid.beads.contains_key(*p)) {
+        if *p == bead.hash {
+            return Err(BeadError::Cycle);
+        }
+        return Err(BeadError::UnknownParent(*p));
+    }
+    if braid.reaches(&bead.parents, &bead.hash) {
+        return Err(BeadError::Cycle);
+    }
+    if let Some(bits) = bead.bits {
+        if !Target::from_compact(CompactTarget::from_consensus(bits)).is_met_by(bead.hash) {
+            return Err(BeadError::HighHash);
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn insert(bead: Bead) -> Result<BeadView, BeadError> {
+    BRAID.lock().unwrap().insert(bead)
+}
+
+pub(crate) fn get(hash: &BlockHash) -> Option<BeadView> {
+    let braid = BRAID.lock().unwrap();
+    braid.beads.get(hash).map(|b| braid.view(b))
+}
+
+/// Stored beads, most recently received first.
+pub(crate) fn list(miner: Option<&str>, limit: usize) -> Vec<BeadView> {
+    let braid = BRAID.lock().unwrap();
+    let mut beads: Vec<&Bead> = braid
+        .beads
+        .values()
+        .filter(|b| miner.is_none_or(|m| b.miner == m))
+        .collect();
+    beads.sort_by(|a, b| b.received_at.cmp(&a.received_at).then(a.hash.cmp(&b.hash)));
+    beads
+        .into_iter()
+        .take(limit)
+        .map(|b| braid.view(b))
+        .collect()
+}
+
+pub(crate) fn tips() -> Vec<BeadView> {
+    let braid = BRAID.lock().unwrap();
+    braid
+        .tips
+        .iter()
+        .filter_map(|h| braid.beads.get(h))
+        .map(|b| braid.view(b))
+        .collect()
+}
+
+pub(crate) fn len() -> usize {
+    BRAID.lock().unwrap().beads.len()
+}
+
+/// Every ancestor of `hash`, nearest first; None if the bead is unknown.
+pub(crate) fn ancestors(hash: &BlockHash, limit: usize) -> Option<Vec<BlockHash>> {
+    BRAID.lock().unwrap().ancestors(hash, limit)
+}
+
+/// Splits the braid into cohorts, oldest first.
+///
+/// A cohort ends wherever the graph has a cut: every later bead descends
+/// from every bead so far. Beads are walked in topological order with
+/// ties broken by hash, and since a cut's beads precede all others in any
+/// such order, it is enough to test each prefix. Only the beads whose
+/// parents are all in the prefix need checking, as every other later bead
+/// descends from one of them.
+pub(crate) fn cohorts(beads: &HashMap<BlockHash, Bead>) -> Vec<Vec<BlockHash>> {
+    let mut children: HashMap<BlockHash, Vec<BlockHash>> = HashMap::new();
+    for b in beads.values() {
+        for p in &b.parents {
+            children.entry(*p).or_default().push(b.hash);
+        }
+    }
+    let mut pending: HashMap<BlockHash, usize> =
+        beads.values().map(|b| (b.hash, b.parents.len())).collect();
+    let mut ancestors: HashMap<BlockHash, HashSet<BlockHash>> = HashMap::new();
+    let mut ready: BTreeSet<BlockHash> = BTreeSet::new();
+    for (hash, n) in &pending {
+        if *n == 0 {
+            make_ready(*hash, beads, &mut ancestors, &mut ready);
+        }
+    }
+
+    let mut out = Vec::new();
+    let mut current = Vec::new();
+    let mut frontier: HashSet<BlockHash> = HashSet::new(); // prefix beads with no child in it
+    while let Some(hash) = ready.pop_first() {
+        for p in &beads[&hash].parents {
+            frontier.remove(p);
+        }
+        frontier.insert(hash);
+        current.push(hash);
+        for child in children.get(&hash).into_iter().flatten() {
+            let n = pending.get_mut(child).expect("every child is stored");
+            *n -= 1;
+            if *n == 0 {
+                make_ready(*child, beads, &mut ancestors, &mut ready);
+            }
+        }
+
+        let is_cut = ready
+            .iter()
+            .all(|next| frontier.iter().all(|f| ancestors[next].contains(f)));
+        if is_cut {
+            out.push(std::mem::take(&mut current));
+        }
+    }
+    out
+}
+
+// All parents are placed, so the bead's full ancestor set is known
+fn make_ready(
+    hash: BlockHash,
+    beads: &HashMap<BlockHash, Bead>,
+    ancestors: &mut HashMap<BlockHash, HashSet<BlockHash>>,
+    ready: &mut BTreeSet<BlockHash>,
+) {
+    let mut set = HashSet::new();
+    for p in &beads[&hash].parents {
+        set.insert(*p);
+        set.extend(ancestors[p].iter().copied());
+    }
+    ancestors.insert(hash, set);
+    ready.insert(hash);
+}
+
+pub(crate) fn cohort_views() -> Vec<CohortView> {
+    let mut braid = BRAID.lock().unwrap();
+    let cohorts = &braid.ordering().cohorts;
+    let last = cohorts.len().saturating_sub(1);
+    cohorts
+        .iter()
+        .enumerate()
+        .map(|(index, beads)| CohortView {
+            index,
+            beads: beads.iter().map(|h| h.to_string()).collect(),
+            open: index == last,
+        })
+        .collect()
+}
+
+/// Every bead in the braid's total order, cohort by cohort.
+pub(crate) fn in_order() -> Vec<Bead> {
+    let mut braid = BRAID.lock().unwrap();
+    let order: Vec<BlockHash> = braid.ordering().cohorts.concat();
+    order.iter().map(|h| braid.beads[h].clone()).collect()
+}
+
+/// The first bead, in total order, whose committed set holds `txid`.
+pub(crate) fn attribution(txid: &Txid) -> Option<Attribution> {
+    BRAID
+        .lock()
+        .unwrap()
+        .ordering()
+        .attribution
+        .get(txid)
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+
+    fn hash(n: u8) -> BlockHash {
+        BlockHash::from_byte_array([n; 32])
+    }
+
+    fn bead(n: u8, parents: &[u8]) -> Bead {
+        Bead {
+            hash: hash(n),
+            parents: parents.iter().map(|p| hash(*p)).collect(),
+            transactions: Vec::new(),
+            miner: "miner".to_string(),
+            payout_address: None,
+            bits: None,
+            work: 0,
+            timestamp: 0,
+            received_at: 0,
+        }
+    }
+
+    // 1 <- 2, 1 <- 3, {2, 3} <- 4
+    fn diamond() -> Braid {
+        let mut braid = Braid::new();
+        for (n, parents) in [(1, &[][..]), (2, &[1]), (3, &[1]), (4, &[2, 3])] {
+            braid.insert(bead(n, parents)).unwrap();
+        }
+        braid
+    }
+
+    #[test]
+    fn test_tips_are_beads_without_children() {
+        let mut braid = Braid::new();
+        braid.insert(bead(1, &[])).unwrap();
+        braid.insert(bead(2, &[1])).unwrap();
+        braid.insert(bead(3, &[1])).unwrap();
+        assert_eq!(braid.tips, BTreeSet::from([hash(2), hash(3)]));
+
+        let view = braid.insert(bead(4, &[2, 3])).unwrap();
+        assert_eq!(braid.tips, BTreeSet::from([hash(4)]));
+        assert_eq!(view.parents.len(), 2);
+    }
+
+    #[test]
+    fn test_unknown_parent_rejected() {
+        let mut braid = diamond();
+        assert_eq!(
+            braid.insert(bead(5, &[9])).err(),
+            Some(BeadError::UnknownParent(hash(9)))
+        );
+    }
+
+    #[test]
+    fn test_self_parent_rejected_as_cycle() {
+        let mut braid = diamond();
+        assert_eq!(braid.insert(bead(5, &[5])).err(), Some(BeadError::Cycle));
+    }
+
+    #[test]
+    fn test_duplicate_bead_rejected() {
+        let mut braid = diamond();
+        assert_eq!(
+            braid.insert(bead(2, &[1])).err(),
+            Some(BeadError::Duplicate)
+        );
+    }
+
+    #[test]
+    fn test_repeated_parent_rejected() {
+        let mut braid = diamond();
+        assert!(matches!(
+            braid.insert(bead(5, &[4, 4])),
+            Err(BeadError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_ancestors_nearest_first_without_repeats() {
+        let braid = diamond();
+        assert_eq!(
+            braid.ancestors(&hash(4), 10),
+            Some(vec![hash(2), hash(3), hash(1)])
+        );
+        assert_eq!(braid.ancestors(&hash(4), 1), Some(vec![hash(2)]));
+        assert_eq!(braid.ancestors(&hash(1), 10), Some(vec![]));
+        assert_eq!(braid.ancestors(&hash(9), 10), None);
+    }
+
+    fn cohorts_of(dag: &[(u8, &[u8])]) -> Vec<Vec<BlockHash>> {
+        let beads = dag
+            .iter()
+            .map(|(n, parents)| (hash(*n), bead(*n, parents)))
+            .collect();
+        cohorts(&beads)
+    }
+
+    fn hashes(cohorts: &[&[u8]]) -> Vec<Vec<BlockHash>> {
+        cohorts
+            .iter()
+            .map(|c| c.iter().map(|n| hash(*n)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_chain_gives_one_bead_per_cohort() {
+        let dag: [(u8, &[u8]); 3] = [(1, &[]), (2, &[1]), (3, &[2])];
+        assert_eq!(cohorts_of(&dag), hashes(&[&[1], &[2], &[3]]));
+    }
+
+    #[test]
+    fn test_diamond_sides_share_a_cohort() {
+        let dag: [(u8, &[u8]); 4] = [(1, &[]), (2, &[1]), (3, &[1]), (4, &[2, 3])];
+        assert_eq!(cohorts_of(&dag), hashes(&[&[1], &[2, 3], &[4]]));
+    }
+
+    #[test]
+    fn test_late_bead_on_old_parent_merges_cohorts() {
+        // 4 builds on 1 only, so nothing after 1 is a cut until 5 joins both
+        let dag: [(u8, &[u8]); 5] = [(1, &[]), (2, &[1]), (3, &[2]), (4, &[1]), (5, &[3, 4])];
+        assert_eq!(cohorts_of(&dag), hashes(&[&[1], &[2, 3, 4], &[5]]));
+    }
+
+    #[test]
+    fn test_two_genesis_beads_form_one_cohort() {
+        let dag: [(u8, &[u8]); 3] = [(1, &[]), (2, &[]), (3, &[1, 2])];
+        assert_eq!(cohorts_of(&dag), hashes(&[&[1, 2], &[3]]));
+    }
+
+    #[test]
+    fn test_tx_attributed_to_first_bead_in_order() {
+        let (t1, t2) = (
+            Txid::from_byte_array([1; 32]),
+            Txid::from_byte_array([2; 32]),
+        );
+        let mut braid = Braid::new();
+        for (n, parents, txs) in [
+            (1, &[][..], vec![t1]),
+            (2, &[1][..], vec![t2, t1]),
+            (3, &[2][..], vec![t2]),
+        ] {
+            braid
+                .insert(Bead {
+                    transactions: txs,
+                    ..bead(n, parents)
+                })
+                .unwrap();
+        }
+
+        let attribution = &braid.ordering().attribution;
+        assert_eq!(attribution[&t1].bead, hash(1));
+        assert_eq!(attribution[&t2].bead, hash(2));
+        assert_eq!(attribution[&t2].cohort, 1);
+    }
+}
@@ -0,0 +1,330 @@
+use bitcoincore_rpc::bitcoin::opcodes::all::OP_PUSHNUM_16;
+use bitcoincore_rpc::bitcoin::script::Instruction;
+use bitcoincore_rpc::bitcoin::{Script, ScriptBuf, Transaction, Witness};
+use serde::{Deserialize, Serialize};
+
+// Consensus limits for one block
+pub(crate) const MAX_BLOCK_WEIGHT: u64 = 4_000_000;
+pub(crate) const MAX_BLOCK_SIGOPS_COST: u64 = 80_000;
+
+// Kept free for the coinbase, as in Core's block assembler
+pub(crate) const COINBASE_RESERVE_WEIGHT: u64 = 4_000;
+pub(crate) const COINBASE_RESERVE_SIGOPS: u64 = 400;
+
+const WITNESS_SCALE_FACTOR: u64 = 4;
+
+/// Block resources a transaction uses.
+#[derive(Clone, Copy, Default, Serialize, Debug, PartialEq, Eq)]
+pub(crate) struct Cost {
+    pub weight: u64,
+    pub sigops: u64, // sigop cost, i.e. legacy sigops count 4x
+}
+
+impl Cost {
+    pub(crate) fn add(self, other: Cost) -> Cost {
+        Cost {
+            weight: self.weight + other.weight,
+            sigops: self.sigops + other.sigops,
+        }
+    }
+
+    pub(crate) fn saturating_sub(self, other: Cost) -> Cost {
+        Cost {
+            weight: self.weight.saturating_sub(other.weight),
+            sigops: self.sigops.saturating_sub(other.sigops),
+        }
+    }
+
+    pub(crate) fn fits_in(self, room: Cost) -> bool {
+        self.weight <= room.weight && self.sigops <= room.sigops
+    }
+}
+
+/// What happens to a schedule request that does not fit the budget.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Overflow {
+    Reject,
+    Queue, // held back until capacity frees up
+}
+
+/// Block budget the Scheduled set has to fit in.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Budget {
+    pub max_weight: u64,
+    pub coinbase_reserve_weight: u64,
+    pub max_sigops: u64,
+    pub coinbase_reserve_sigops: u64,
+    pub overflow: Overflow,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Budget {
+            max_weight: MAX_BLOCK_WEIGHT,
+            coinbase_reserve_weight: COINBASE_RESERVE_WEIGHT,
+            max_sigops: MAX_BLOCK_SIGOPS_COST,
+            coinbase_reserve_sigops: COINBASE_RESERVE_SIGOPS,
+            overflow: Overflow::Reject,
+        }
+    }
+}
+
+impl Budget {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.max_weight > MAX_BLOCK_WEIGHT {
+            return Err(format!("max_weight cannot exceed {MAX_BLOCK_WEIGHT}"));
+        }
+        if self.max_sigops > MAX_BLOCK_SIGOPS_COST {
+            return Err(format!("max_sigops cannot exceed {MAX_BLOCK_SIGOPS_COST}"));
+        }
+        if self.coinbase_reserve_weight >= self.max_weight {
+            return Err("coinbase_reserve_weight must be below max_weight".to_string());
+        }
+        if self.coinbase_reserve_sigops >= self.max_sigops {
+            return Err("coinbase_reserve_sigops must be below max_sigops".to_string());
+        }
+        Ok(())
+    }
+
+    /// Room available to scheduled transactions once the coinbase is set aside.
+    pub(crate) fn limit(&self) -> Cost {
+        Cost {
+            weight: self.max_weight.saturating_sub(self.coinbase_reserve_weight),
+            sigops: self.max_sigops.saturating_sub(self.coinbase_reserve_sigops),
+        }
+    }
+}
+
+// Taproot control blocks are 33 + 32n bytes with a 0xc0/0xc1 leaf version
+fn is_control_block(item: &[u8]) -> bool {
+    item.len() >= 33 && (item.len() - 33).is_multiple_of(32) && item[0] & 0xfe == 0xc0
+}
+
+// Data of the last push in a push-only scriptSig, i.e. a P2SH redeem script
+fn redeem_script(script_sig: &Script) -> Option<&Script> {
+    let mut last: Option<&[u8]> = None;
+    for instruction in script_sig.instructions() {
+        match instruction.ok()? {
+            Instruction::PushBytes(data) => last = Some(data.as_bytes()),
+            // OP_1..OP_16 push a number, with no data to run as a script
+            Instruction::Op(op) if op.to_u8() <= OP_PUSHNUM_16.to_u8() => last = Some(&[]),
+            Instruction::Op(_) => return None,
+        }
+    }
+    last.map(Script::from_bytes)
+}
+
+// BIP141 sigops of spending the witness `program` with `witness`
+fn witness_sigops(program: &Script, witness: &Witness) -> u64 {
+    if program.is_p2wpkh() {
+        1
+    } else if program.is_p2wsh() {
+        witness
+            .last()
+            .map_or(0, |script| Script::from_bytes(script).count_sigops() as u64)
+    } else {
+        0
+    }
+}
+
+/// Sigop cost of `tx` as Core counts it, `spent[i]` being the script
+/// spent by input `i`.
+///
+/// Where the spent script is unknown, a redeem script in the scriptSig is
+/// counted as P2SH and the witness is told apart by its shape, which errs
+/// towards counting too many.
+pub(crate) fn sigop_cost(tx: &Transaction, spent: &[Option<ScriptBuf>]) -> u64 {
+    let mut legacy = 0;
+    let mut p2sh = 0;
+    let mut witness = 0;
+
+    for (i, input) in tx.input.iter().enumerate() {
+        legacy += input.script_sig.count_sigops_legacy() as u64;
+        let redeem = redeem_script(&input.script_sig);
+
+        match spent.get(i).and_then(Option::as_ref) {
+            Some(spk) if spk.is_p2sh() => {
+                if let Some(redeem) = redeem {
+                    p2sh += redeem.count_sigops() as u64;
+                    if redeem.is_witness_program() {
+                        witness += witness_sigops(redeem, &input.witness);
+                    }
+                }
+            }
+            Some(spk) if spk.is_witness_program() => {
+                witness += witness_sigops(spk, &input.witness);
+            }
+            Some(_) => {}
+            None => {
+                p2sh += redeem.map_or(0, |r| r.count_sigops() as u64);
+                let items: Vec<&[u8]> = input.witness.iter().collect();
+                witness += match items[..] {
+                    [] => 0,
+                    // P2WPKH, native or nested
+                    [_, pubkey] if pubkey.len() == 33 => 1,
+                    // Taproot spends carry no sigop cost
+                    [_] => 0,
+                    [.., last] if is_control_block(last) => 0,
+                    // P2WSH: the last item is the witness script
+                    [.., script] => Script::from_bytes(script).count_sigops() as u64,
+                };
+            }
+        }
+    }
+    for output in &tx.output {
+        legacy += output.script_pubkey.count_sigops_legacy() as u64;
+    }
+
+    (legacy + p2sh) * WITNESS_SCALE_FACTOR + witness
+}
+
+pub(crate) fn tx_cost(tx: &Transaction, spent: &[Option<ScriptBuf>]) -> Cost {
+    Cost {
+        weight: tx.weight().to_wu(),
+        sigops: sigop_cost(tx, spent),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::absolute::LockTime;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use bitcoincore_rpc::bitcoin::opcodes::all::{
+        OP_CHECKMULTISIG, OP_CHECKSIG, OP_PUSHNUM_2, OP_PUSHNUM_3,
+    };
+    use bitcoincore_rpc::bitcoin::script::{Builder, PushBytesBuf};
+    use bitcoincore_rpc::bitcoin::transaction::Version;
+    use bitcoincore_rpc::bitcoin::{Amount, OutPoint, Sequence, TxIn, TxOut, WPubkeyHash};
+
+    fn spend(script_sig: ScriptBuf, witness: Witness) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig,
+                sequence: Sequence::MAX,
+                witness,
+            }],
+            output: vec![],
+        }
+    }
+
+    fn push(data: &[u8]) -> PushBytesBuf {
+        PushBytesBuf::try_from(data.to_vec()).unwrap()
+    }
+
+    // 2-of-3 bare multisig, spent through P2SH
+    fn multisig_spend() -> (Transaction, ScriptBuf) {
+        let redeem = Builder::new()
+            .push_opcode(OP_PUSHNUM_2)
+            .push_slice([2u8; 33])
+            .push_slice([3u8; 33])
+            .push_slice([4u8; 33])
+            .push_opcode(OP_PUSHNUM_3)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+        let script_sig = Builder::new()
+            .push_int(0)
+            .push_slice(push(&[0x30; 71]))
+            .push_slice(push(&[0x30; 71]))
+            .push_slice(push(redeem.as_bytes()))
+            .into_script();
+        let spk = ScriptBuf::new_p2sh(&redeem.script_hash());
+        (spend(script_sig, Witness::new()), spk)
+    }
+
+    #[test]
+    fn test_p2sh_multisig_counts_redeem_script_keys() {
+        let (tx, spk) = multisig_spend();
+        assert_eq!(sigop_cost(&tx, &[Some(spk)]), 3 * WITNESS_SCALE_FACTOR);
+    }
+
+    #[test]
+    fn test_redeem_script_ignored_when_spent_script_is_not_p2sh() {
+        let (tx, _) = multisig_spend();
+        let p2pkh = ScriptBuf::from_bytes(vec![0x76, 0xa9, 0x14]);
+        assert_eq!(sigop_cost(&tx, &[Some(p2pkh)]), 0);
+    }
+
+    #[test]
+    fn test_unknown_spent_script_counts_redeem_script() {
+        let (tx, _) = multisig_spend();
+        assert_eq!(sigop_cost(&tx, &[None]), 3 * WITNESS_SCALE_FACTOR);
+    }
+
+    #[test]
+    fn test_nested_p2wpkh_counts_one_witness_sigop() {
+        let program = Builder::new()
+            .push_int(0)
+            .push_slice([7u8; 20])
+            .into_script();
+        let script_sig = Builder::new()
+            .push_slice(push(program.as_bytes()))
+            .into_script();
+        let witness = Witness::from_slice(&[vec![0x30; 71], vec![2; 33]]);
+        let tx = spend(script_sig, witness);
+
+        let spk = ScriptBuf::new_p2sh(&program.script_hash());
+        assert_eq!(sigop_cost(&tx, &[Some(spk)]), 1);
+    }
+
+    #[test]
+    fn test_legacy_sigops_scaled_witness_sigops_not() {
+        let witness = Witness::from_slice(&[vec![0x30; 71], vec![2; 33]]);
+        let mut tx = spend(ScriptBuf::new(), witness);
+        tx.output.push(TxOut {
+            value: Amount::from_sat(1_000),
+            script_pubkey: Builder::new().push_opcode(OP_CHECKSIG).into_script(),
+        });
+
+        // One legacy CHECKSIG output plus one P2WPKH input
+        let p2wpkh = ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([7; 20]));
+        assert_eq!(sigop_cost(&tx, &[Some(p2wpkh)]), 5);
+    }
+
+    #[test]
+    fn test_default_budget_reserves_coinbase() {
+        let budget = Budget::default();
+        assert_eq!(
+            budget.limit(),
+            Cost {
+                weight: 3_996_000,
+                sigops: 79_600
+            }
+        );
+        assert!(budget.validate().is_ok());
+
+        let over = Budget {
+            max_weight: MAX_BLOCK_WEIGHT + 1,
+            ..Budget::default()
+        };
+        assert!(over.validate().is_err());
+    }
+
+    #[test]
+    fn test_cost_fits_in_both_dimensions() {
+        let room = Cost {
+            weight: 1_000,
+            sigops: 20,
+        };
+        assert!(Cost {
+            weight: 1_000,
+            sigops: 20
+        }
+        .fits_in(room));
+        assert!(!Cost {
+            weight: 1_001,
+            sigops: 0
+        }
+        .fits_in(room));
+        assert!(!Cost {
+            weight: 0,
+            sigops: 21
+        }
+        .fits_in(room));
+    }
+}
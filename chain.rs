@@ -0,0 +1,184 @@
+use bitcoincore_rpc::bitcoin::{BlockHash, Txid};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+// Blocks kept for reorg detection; anything deeper is treated as final
+pub(crate) const REORG_WINDOW: usize = 100;
+
+// Reorg events kept for `/reorgs`
+const MAX_REORG_EVENTS: usize = 100;
+
+/// Recently connected blocks on the active chain, with the txs they confirmed.
+struct Tracker {
+    by_height: BTreeMap<u64, BlockHash>,
+    txids: HashMap<BlockHash, Vec<Txid>>,
+    heights: HashMap<BlockHash, u64>,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct Restored {
+    pub txid: String,
+    pub stage: &'static str,
+}
+
+/// One block disconnected by a reorg and what happened to its txs.
+#[derive(Clone, Serialize)]
+pub(crate) struct ReorgEvent {
+    pub at: u64,
+    pub hash: String,
+    pub height: u64,
+    pub fork_height: u64,
+    pub restored: Vec<Restored>,
+}
+
+pub(crate) struct Disconnected {
+    pub hash: BlockHash,
+    pub height: u64,
+    pub txids: Vec<Txid>,
+}
+
+impl Tracker {
+    fn new() -> Self {
+        Tracker {
+            by_height: BTreeMap::new(),
+            txids: HashMap::new(),
+            heights: HashMap::new(),
+        }
+    }
+
+    fn connect(&mut self, height: u64, hash: BlockHash, txids: Vec<Txid>) -> Vec<Txid> {
+        if let Some(old) = self.by_height.insert(height, hash) {
+            if old != hash {
+                self.txids.remove(&old);
+                self.heights.remove(&old);
+            }
+        }
+        self.txids.insert(hash, txids);
+        self.heights.insert(hash, height);
+
+        let mut expired = Vec::new();
+        while self.by_height.len() > REORG_WINDOW {
+            let Some((_, old)) = self.by_height.pop_first() else {
+                break;
+            };
+            self.heights.remove(&old);
+            expired.extend(self.txids.remove(&old).unwrap_or_default());
+        }
+        expired
+    }
+
+    fn disconnect_above(&mut self, fork_height: u64) -> Vec<Disconnected> {
+        let stale = self.by_height.split_off(&(fork_height + 1));
+        stale
+            .into_iter()
+            .rev()
+            .map(|(height, hash)| {
+                self.heights.remove(&hash);
+                Disconnected {
+                    hash,
+                    height,
+                    txids: self.txids.remove(&hash).unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+}
+
+static CHAIN: Lazy<Mutex<Tracker>> = Lazy::new(|| Mutex::new(Tracker::new()));
+
+static REORGS: Lazy<Mutex<Vec<ReorgEvent>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Records a connected block; returns the txids of blocks that fell out of the window.
+pub(crate) fn connect(height: u64, hash: BlockHash, txids: Vec<Txid>) -> Vec<Txid> {
+    CHAIN.lock().unwrap().connect(height, hash, txids)
+}
+
+/// The tracked block that confirmed `txid`, if any.
+pub(crate) fn block_containing(txid: &Txid) -> Option<BlockHash> {
+    let chain = CHAIN.lock().unwrap();
+    chain
+        .txids
+        .iter()
+        .find(|(_, txids)| txids.contains(txid))
+        .map(|(hash, _)| *hash)
+}
+
+pub(crate) fn height_of(hash: &BlockHash) -> Option<u64> {
+    CHAIN.lock().unwrap().heights.get(hash).copied()
+}
+
+/// Removes every tracked block above `fork_height`, highest first.
+pub(crate) fn disconnect_above(fork_height: u64) -> Vec<Disconnected> {
+    CHAIN.lock().unwrap().disconnect_above(fork_height)
+}
+
+pub(crate) fn record_reorg(event: ReorgEvent) {
+    let mut reorgs = REORGS.lock().unwrap();
+    reorgs.push(event);
+    if reorgs.len() > MAX_REORG_EVENTS {
+        let excess = reorgs.len() - MAX_REORG_EVENTS;
+        reorgs.drain(..excess);
+    }
+}
+
+pub(crate) fn reorgs() -> Vec<ReorgEvent> {
+    REORGS.lock().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+
+    fn hash(n: u64) -> BlockHash {
+        let mut bytes = [0; 32];
+        bytes[..8].copy_from_slice(&n.to_le_bytes());
+        BlockHash::from_byte_array(bytes)
+    }
+
+    fn txid(n: u64) -> Txid {
+        let mut bytes = [0; 32];
+        bytes[..8].copy_from_slice(&n.to_le_bytes());
+        Txid::from_byte_array(bytes)
+    }
+
+    #[test]
+    fn test_blocks_above_fork_disconnected_highest_first() {
+        let mut chain = Tracker::new();
+        for height in 100..103 {
+            chain.connect(height, hash(height), vec![txid(height)]);
+        }
+
+        let stale = chain.disconnect_above(100);
+        let heights: Vec<u64> = stale.iter().map(|d| d.height).collect();
+        assert_eq!(heights, vec![102, 101]);
+        assert_eq!(stale[0].txids, vec![txid(102)]);
+        assert_eq!(chain.by_height.len(), 1);
+        assert!(!chain.heights.contains_key(&hash(101)));
+    }
+
+    #[test]
+    fn test_window_expires_oldest_blocks() {
+        let mut chain = Tracker::new();
+        let mut expired = Vec::new();
+        for height in 0..=REORG_WINDOW as u64 {
+            expired.extend(chain.connect(height, hash(height), vec![txid(height)]));
+        }
+
+        assert_eq!(expired, vec![txid(0)]);
+        assert_eq!(chain.by_height.len(), REORG_WINDOW);
+        assert!(!chain.txids.contains_key(&hash(0)));
+    }
+
+    #[test]
+    fn test_replaced_block_at_same_height_is_dropped() {
+        let mut chain = Tracker::new();
+        chain.connect(5, hash(1), vec![txid(1)]);
+        chain.connect(5, hash(2), vec![txid(2)]);
+
+        assert!(!chain.txids.contains_key(&hash(1)));
+        assert_eq!(chain.heights.get(&hash(2)), Some(&5));
+    }
+}
@@ -0,0 +1,264 @@
+use bitcoincore_rpc::bitcoin::hashes::{sha256d, Hash};
+use bitcoincore_rpc::bitcoin::{TxMerkleNode, Txid};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+
+// Past roots kept for `/committed/root?epoch=`
+const MAX_ROOTS: usize = 1_000;
+
+// Most recent epochs whose leaves are kept for `/tx/{txid}/committed-proof?epoch=`
+const MAX_PROOF_EPOCHS: usize = 64;
+
+/// The Merkle root of the Committed set at one epoch.
+#[derive(Clone, Serialize)]
+pub(crate) struct Root {
+    pub epoch: u64,
+    pub root: String,
+    pub size: usize,
+    pub at: u64, // when this root was taken
+}
+
+/// Where a tx sits under a root, and the siblings to hash it up with.
+#[derive(Serialize)]
+pub(crate) struct Proof {
+    pub txid: String,
+    pub epoch: u64,
+    pub root: String,
+    pub size: usize,
+    pub index: usize,
+    pub path: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ProofError {
+    EpochNotKept,
+    NotCommitted,
+}
+
+struct Snapshot {
+    root: Root,
+    leaves: Option<Vec<Txid>>, // dropped past the last MAX_PROOF_EPOCHS
+}
+
+// Oldest first, one per epoch
+static HISTORY: Lazy<Mutex<VecDeque<Snapshot>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+
+fn parent(left: &TxMerkleNode, right: &TxMerkleNode) -> TxMerkleNode {
+    let mut data = [0u8; 64];
+    data[..32].copy_from_slice(left.as_byte_array());
+    data[32..].copy_from_slice(right.as_byte_array());
+    TxMerkleNode::from_raw_hash(sha256d::Hash::hash(&data))
+}
+
+/// Every level of the tree, leaves first, as in a block's merkle root:
+/// a level with an odd count pairs its last node with itself.
+fn levels(leaves: &[Txid]) -> Vec<Vec<TxMerkleNode>> {
+    let mut level: Vec<TxMerkleNode> = leaves
+        .iter()
+        .map(|t| TxMerkleNode::from_raw_hash(t.to_raw_hash()))
+        .collect();
+    let mut out = Vec::new();
+    while level.len() > 1 {
+        let next = level
+            .chunks(2)
+            .map(|pair| parent(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+        out.push(level);
+        level = next;
+    }
+    out.push(level);
+    out
+}
+
+/// Canonical root over `leaves`, sorted by txid; all zeros for an empty set.
+pub(crate) fn merkle_root(leaves: &[Txid]) -> TxMerkleNode {
+    levels(leaves)
+        .last()
+        .and_then(|top| top.first().copied())
+        .unwrap_or_else(TxMerkleNode::all_zeros)
+}
+
+/// Sibling hashes from the leaf at `index` up to the root.
+pub(crate) fn path(leaves: &[Txid], index: usize) -> Vec<TxMerkleNode> {
+    let levels = levels(leaves);
+    let mut out = Vec::new();
+    let mut i = index;
+    for level in &levels[..levels.len() - 1] {
+        out.push(*level.get(i ^ 1).unwrap_or(&level[i]));
+        i /= 2;
+    }
+    out
+}
+
+/// Takes the root of `committed` as of `epoch`.
+///
+/// Called each time the store's epoch changes, so every root a client
+/// could have seen stays answerable while it is kept.
+pub(crate) fn record(epoch: u64, committed: &HashSet<Txid>, now: u64) -> Root {
+    let mut leaves: Vec<Txid> = committed.iter().copied().collect();
+    leaves.sort();
+    let root = Root {
+        epoch,
+        root: merkle_root(&leaves).to_string(),
+        size: leaves.len(),
+        at: now,
+    };
+
+    let mut history = HISTORY.lock().unwrap();
+    history.retain(|s| s.root.epoch != epoch);
+    history.push_back(Snapshot {
+        root: root.clone(),
+        leaves: Some(leaves),
+    });
+    if history.len() > MAX_ROOTS {
+        history.pop_front();
+    }
+    if let Some(i) = history.len().checked_sub(MAX_PROOF_EPOCHS + 1) {
+        history[i].leaves = None;
+    }
+    root
+}
+
+/// The root of `committed` as of `epoch`, taking it if it was not yet.
+pub(crate) fn current(epoch: u64, committed: &HashSet<Txid>, now: u64) -> Root {
+    match at_epoch(epoch) {
+        Some(root) => root,
+        None => record(epoch, committed, now),
+    }
+}
+
+/// A root recorded earlier, if it is still kept.
+pub(crate) fn at_epoch(epoch: u64) -> Option<Root> {
+    HISTORY
+        .lock()
+        .unwrap()
+        .iter()
+        .rev()
+        .find(|s| s.root.epoch == epoch)
+        .map(|s| s.root.clone())
+}
+
+/// An inclusion proof for `txid` under the root taken at `epoch`.
+pub(crate) fn prove(txid: &Txid, epoch: u64) -> Result<Proof, ProofError> {
+    let history = HISTORY.lock().unwrap();
+    let snapshot = history
+        .iter()
+        .rev()
+        .find(|s| s.root.epoch == epoch)
+        .ok_or(ProofError::EpochNotKept)?;
+    let leaves = snapshot.leaves.as_ref().ok_or(ProofError::EpochNotKept)?;
+    let index = leaves
+        .binary_search(txid)
+        .map_err(|_| ProofError::NotCommitted)?;
+    Ok(Proof {
+        txid: txid.to_string(),
+        epoch,
+        root: snapshot.root.root.clone(),
+        size: snapshot.root.size,
+        index,
+        path: path(leaves, index).iter().map(|h| h.to_string()).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use bitcoincore_rpc::bitcoin::merkle_tree;
+
+    // Well clear of the small epochs other tests' stores go through
+    const BASE: u64 = 1 << 40;
+
+    fn txid(n: u8) -> Txid {
+        Txid::from_byte_array([n; 32])
+    }
+
+    fn sorted(n: u8) -> Vec<Txid> {
+        let mut leaves: Vec<Txid> = (0..n)
+            .map(|i| Txid::from_raw_hash(sha256d::Hash::hash(&[i])))
+            .collect();
+        leaves.sort();
+        leaves
+    }
+
+    // What an external verifier does with a proof
+    fn fold(txid: &Txid, index: usize, path: &[TxMerkleNode]) -> TxMerkleNode {
+        let mut hash = TxMerkleNode::from_raw_hash(txid.to_raw_hash());
+        let mut i = index;
+        for sibling in path {
+            hash = if i % 2 == 1 {
+                parent(sibling, &hash)
+            } else {
+                parent(&hash, sibling)
+            };
+            i /= 2;
+        }
+        hash
+    }
+
+    #[test]
+    fn test_proof_at_past_epoch_outlives_uncommit() {
+        let (a, b) = (txid(1), txid(2));
+        let before = record(BASE, &HashSet::from([a, b]), 0);
+        let after = record(BASE + 1, &HashSet::from([b]), 0);
+        assert_ne!(before.root, after.root);
+
+        let proof = prove(&a, BASE).unwrap();
+        assert_eq!(proof.root, before.root);
+        assert_eq!(proof.size, 2);
+        assert_eq!(prove(&a, BASE + 1).err(), Some(ProofError::NotCommitted));
+        assert_eq!(at_epoch(BASE).unwrap().root, before.root);
+    }
+
+    #[test]
+    fn test_epoch_never_recorded_is_not_proven() {
+        let a = txid(3);
+        record(BASE + 12, &HashSet::from([a]), 0);
+        assert!(at_epoch(BASE + 11).is_none());
+        assert_eq!(prove(&a, BASE + 11).err(), Some(ProofError::EpochNotKept));
+    }
+
+    #[test]
+    fn test_root_matches_block_merkle_root() {
+        for n in 1..=9 {
+            let leaves = sorted(n);
+            let expected =
+                merkle_tree::calculate_root(leaves.iter().map(|t| t.to_raw_hash())).unwrap();
+            assert_eq!(merkle_root(&leaves).to_raw_hash(), expected, "{n} leaves");
+        }
+    }
+
+    #[test]
+    fn test_every_path_folds_back_to_root() {
+        // Odd counts make the last node of a level pair with itself
+        for n in 1..=9 {
+            let leaves = sorted(n);
+            let root = merkle_root(&leaves);
+            for (i, leaf) in leaves.iter().enumerate() {
+                assert_eq!(fold(leaf, i, &path(&leaves, i)), root, "leaf {i} of {n}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_path_fails_for_other_tx_index_or_root() {
+        let leaves = sorted(5);
+        let root = merkle_root(&leaves);
+        let p = path(&leaves, 2);
+
+        assert_ne!(fold(&txid(99), 2, &p), root);
+        assert_ne!(fold(&leaves[2], 3, &p), root);
+        assert_ne!(fold(&leaves[2], 2, &p), merkle_root(&sorted(4)));
+    }
+
+    #[test]
+    fn test_empty_and_single_sets() {
+        assert_eq!(merkle_root(&[]), TxMerkleNode::all_zeros());
+        // One tx: the root is the txid itself and the path is empty
+        let one = [txid(1)];
+        assert_eq!(merkle_root(&one).to_raw_hash(), one[0].to_raw_hash());
+        assert!(path(&one, 0).is_empty());
+    }
+}
@@ -0,0 +1,162 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+
+/// Caps on the bookkeeping that grows with every tx and block the API looks at.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Limits {
+    pub interval_secs: u64,
+    pub seen_max_entries: usize,
+    pub seen_max_age_secs: u64, // since the tx was last in a mempool
+    pub idle_max_entries: usize,
+    pub idle_max_age_secs: u64, // since the tx left the pipeline
+    pub timeline_max_entries: usize,
+    pub timeline_max_age_secs: u64, // since the tx's latest event
+    pub block_max_entries: usize,   // block audits, and blocks marked as audited
+    pub block_max_age_secs: u64,    // since the block was audited
+    pub payout_max_entries: usize,
+    pub payout_max_age_secs: u64, // since the payout was recorded
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            interval_secs: 60,
+            seen_max_entries: 200_000,
+            // bitcoind's default mempool expiry
+            seen_max_age_secs: 14 * 24 * 60 * 60,
+            idle_max_entries: 100_000,
+            idle_max_age_secs: 24 * 60 * 60,
+            timeline_max_entries: 200_000,
+            timeline_max_age_secs: 14 * 24 * 60 * 60,
+            block_max_entries: 10_000,
+            block_max_age_secs: 30 * 24 * 60 * 60,
+            payout_max_entries: 10_000,
+            payout_max_age_secs: 90 * 24 * 60 * 60,
+        }
+    }
+}
+
+impl Limits {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.interval_secs == 0 {
+            return Err("interval_secs must be at least 1".to_string());
+        }
+        let max_entries = [
+            self.seen_max_entries,
+            self.idle_max_entries,
+            self.timeline_max_entries,
+            self.block_max_entries,
+            self.payout_max_entries,
+        ];
+        if max_entries.contains(&0) {
+            return Err("max_entries limits must be at least 1".to_string());
+        }
+        let max_ages = [
+            self.seen_max_age_secs,
+            self.idle_max_age_secs,
+            self.timeline_max_age_secs,
+            self.block_max_age_secs,
+            self.payout_max_age_secs,
+        ];
+        if max_ages.contains(&0) {
+            return Err("max_age_secs limits must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Entries dropped by one pass, per store.
+#[derive(Clone, Default, Serialize)]
+pub(crate) struct Sweep {
+    pub at: u64,
+    pub seen: usize,
+    pub idle: usize,
+    pub archive: usize,
+    pub votes: usize,
+    pub timelines: usize,
+    pub blocks: usize,
+    pub payouts: usize,
+}
+
+pub(crate) static LIMITS: Lazy<Mutex<Limits>> = Lazy::new(|| Mutex::new(Limits::default()));
+
+pub(crate) static LAST_SWEEP: Lazy<Mutex<Option<Sweep>>> = Lazy::new(|| Mutex::new(None));
+
+/// Drops entries stamped more than `max_age` ago, then the oldest until
+/// at most `max_entries` remain. Returns the evicted keys.
+pub(crate) fn evict<K: Copy + Eq + Hash + Ord>(
+    stamps: &mut HashMap<K, u64>,
+    now: u64,
+    max_age: u64,
+    max_entries: usize,
+) -> Vec<K> {
+    let mut evicted: Vec<K> = stamps
+        .iter()
+        .filter(|(_, at)| now.saturating_sub(**at) > max_age)
+        .map(|(k, _)| *k)
+        .collect();
+    for k in &evicted {
+        stamps.remove(k);
+    }
+
+    let excess = stamps.len().saturating_sub(max_entries);
+    if excess > 0 {
+        let mut oldest: Vec<(u64, K)> = stamps.iter().map(|(k, at)| (*at, *k)).collect();
+        oldest.sort_unstable();
+        for (_, k) in oldest.into_iter().take(excess) {
+            stamps.remove(&k);
+            evicted.push(k);
+        }
+    }
+    evicted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_past_max_age_evicted() {
+        let mut seen = HashMap::from([(1, 100), (2, 950)]);
+
+        assert_eq!(evict(&mut seen, 1000, 500, 10), vec![1]);
+        assert!(seen.contains_key(&2));
+    }
+
+    #[test]
+    fn test_least_recently_seen_evicted_over_cap() {
+        let mut seen = HashMap::from([(1, 30), (2, 10), (3, 20)]);
+
+        // Oldest first
+        assert_eq!(evict(&mut seen, 40, 1000, 1), vec![2, 3]);
+        assert_eq!(seen.len(), 1);
+    }
+
+    #[test]
+    fn test_refreshed_stamp_survives() {
+        let mut seen = HashMap::from([(1, 100)]);
+        // Seen again in a mempool before the sweep
+        seen.insert(1, 990);
+
+        assert!(evict(&mut seen, 1000, 500, 10).is_empty());
+    }
+
+    #[test]
+    fn test_zero_limits_rejected() {
+        assert!(Limits::default().validate().is_ok());
+        let no_room = Limits {
+            block_max_entries: 0,
+            ..Limits::default()
+        };
+        assert!(no_room.validate().is_err());
+        let no_age = Limits {
+            payout_max_age_secs: 0,
+            ..Limits::default()
+        };
+        assert!(no_age.validate().is_err());
+    }
+}
@@ -0,0 +1,455 @@
+use crate::gc;
+use bitcoincore_rpc::bitcoin::{BlockHash, ScriptBuf, TxOut};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+
+// Templates kept for matching against the coinbase of a connected block
+const MAX_PLANS: usize = 100;
+
+/// One bead's claim on the next block reward.
+#[derive(Clone)]
+pub(crate) struct Share {
+    pub bead: BlockHash,
+    pub miner: String,
+    pub payout_address: Option<String>,
+    pub work: u128,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct MinerPayout {
+    pub miner: String,
+    pub payout_address: Option<String>, // from the miner's latest bead that named one
+    pub beads: usize,
+    pub work: String, // decimal, may exceed 2^53
+    pub amount_sats: u64,
+}
+
+/// How one block's coinbase value was split across the beads it paid for.
+#[derive(Clone, Serialize)]
+pub(crate) struct BlockPayout {
+    pub block_hash: String,
+    pub height: Option<u64>,
+    pub reward_sats: u64, // payout outputs of the coinbase: subsidy plus fees
+    pub total_work: String,
+    pub beads: Vec<String>,
+    pub payouts: Vec<MinerPayout>,
+    pub recorded_at: u64,
+}
+
+#[derive(Serialize)]
+pub(crate) struct BlockEarning {
+    pub block_hash: String,
+    pub height: Option<u64>,
+    pub amount_sats: u64,
+    pub work: String,
+}
+
+/// What a template's coinbase pays, kept until a block turns out to use it.
+#[derive(Clone)]
+pub(crate) struct Plan {
+    pub outputs: Vec<TxOut>, // payout outputs, witness commitment left out
+    pub shares: Vec<Share>,  // beads of the miners it pays
+    pub credits: Vec<MinerPayout>,
+}
+
+struct Ledger {
+    payouts: HashMap<BlockHash, BlockPayout>,
+    paid: HashMap<BlockHash, BlockHash>, // bead -> block that paid it
+    plans: VecDeque<Plan>,
+}
+
+static LEDGER: Lazy<Mutex<Ledger>> = Lazy::new(|| {
+    Mutex::new(Ledger {
+        payouts: HashMap::new(),
+        paid: HashMap::new(),
+        plans: VecDeque::new(),
+    })
+});
+
+/// Splits `reward` across miners in proportion to the work of their beads.
+///
+/// Each miner gets the floor of its exact share; the sats left over go one
+/// each to the largest remainders, ties broken by miner id, so the amounts
+/// always add up to `reward`. Results are sorted by miner id.
+pub(crate) fn split(reward: u64, shares: &[Share]) -> Vec<MinerPayout> {
+    let mut miners: BTreeMap<&str, (Option<&str>, usize, u128)> = BTreeMap::new();
+    for s in shares {
+        let m = miners.entry(&s.miner).or_insert((None, 0, 0));
+        if s.payout_address.is_some() {
+            m.0 = s.payout_address.as_deref();
+        }
+        m.1 += 1;
+        m.2 = m.2.saturating_add(s.work);
+    }
+
+    // Keep reward * work within u128
+    let total: u128 = miners
+        .values()
+        .fold(0u128, |acc, m| acc.saturating_add(m.2));
+    let shift = (128 - total.leading_zeros()).saturating_sub(64);
+    let scaled: Vec<u128> = miners.values().map(|m| m.2 >> shift).collect();
+    let scaled_total: u128 = scaled.iter().sum();
+
+    let mut amounts: Vec<(u64, u128)> = scaled
+        .iter()
+        .map(|w| {
+            if scaled_total == 0 {
+                return (0, 0);
+            }
+            let exact = reward as u128 * w;
+            ((exact / scaled_total) as u64, exact % scaled_total)
+        })
+        .collect();
+    if scaled_total > 0 {
+        let left = reward - amounts.iter().map(|a| a.0).sum::<u64>();
+        let mut by_remainder: Vec<usize> = (0..amounts.len()).collect();
+        // Stable sort keeps miner id order among equal remainders
+        by_remainder.sort_by(|a, b| amounts[*b].1.cmp(&amounts[*a].1));
+        for i in by_remainder.into_iter().take(left as usize) {
+            amounts[i].0 += 1;
+        }
+    }
+
+    miners
+        .into_iter()
+        .zip(amounts)
+        .map(
+            |((miner, (address, beads, work)), (amount, _))| MinerPayout {
+                miner: miner.to_string(),
+                payout_address: address.map(str::to_string),
+                beads,
+                work: work.to_string(),
+                amount_sats: amount,
+            },
+        )
+        .collect()
+}
+
+pub(crate) fn is_paid(bead: &BlockHash) -> bool {
+    LEDGER.lock().unwrap().paid.contains_key(bead)
+}
+
+/// What a coinbase paying `planned` actually gives each miner.
+///
+/// `planned` pairs each miner's split with the script it is paid to. A
+/// miner is credited from the first output to that script, shared in
+/// proportion to the split with any miner paid to the same script. Miners
+/// whose amount went to the fallback, as dust or past the output cap, get
+/// no credit and keep their beads for a later block. Sorted by miner id.
+pub(crate) fn credits(planned: &[(MinerPayout, ScriptBuf)], outputs: &[TxOut]) -> Vec<MinerPayout> {
+    let mut by_script: BTreeMap<&ScriptBuf, Vec<&MinerPayout>> = BTreeMap::new();
+    for (miner, script) in planned {
+        by_script.entry(script).or_default().push(miner);
+    }
+
+    let mut out = Vec::new();
+    for (script, miners) in by_script {
+        let Some(paid) = outputs.iter().find(|o| o.script_pubkey == *script) else {
+            continue;
+        };
+        let paid = paid.value.to_sat();
+        let total: u128 = miners.iter().map(|m| m.amount_sats as u128).sum();
+        let mut left = paid;
+        for (i, m) in miners.iter().enumerate() {
+            let amount = if i + 1 == miners.len() {
+                left
+            } else {
+                (paid as u128 * m.amount_sats as u128 / total.max(1)) as u64
+            };
+            left -= amount;
+            out.push(MinerPayout {
+                amount_sats: amount,
+                ..(*m).clone()
+            });
+        }
+    }
+    out.sort_by(|a, b| a.miner.cmp(&b.miner));
+    out
+}
+
+/// Keeps a template's payouts until a block with the same coinbase
+/// outputs connects; the newest plan for a given set of outputs wins.
+pub(crate) fn plan(plan: Plan) {
+    let mut ledger = LEDGER.lock().unwrap();
+    ledger.plans.retain(|p| p.outputs != plan.outputs);
+    ledger.plans.push_back(plan);
+    if ledger.plans.len() > MAX_PLANS {
+        ledger.plans.pop_front();
+    }
+}
+
+/// Credits the miners a block's coinbase paid, if its payout `outputs`
+/// are those of a template built here; None for any other block, or one
+/// already recorded.
+pub(crate) fn record(
+    block: BlockHash,
+    height: Option<u64>,
+    outputs: &[TxOut],
+    now: u64,
+) -> Option<BlockPayout> {
+    let mut ledger = LEDGER.lock().unwrap();
+    if ledger.payouts.contains_key(&block) {
+        return None;
+    }
+    let plan = ledger
+        .plans
+        .iter()
+        .rev()
+        .find(|p| p.outputs == outputs)?
+        .clone();
+
+    let total_work = plan
+        .shares
+        .iter()
+        .fold(0u128, |acc, s| acc.saturating_add(s.work));
+    let payout = BlockPayout {
+        block_hash: block.to_string(),
+        height,
+        reward_sats: outputs.iter().map(|o| o.value.to_sat()).sum(),
+        total_work: total_work.to_string(),
+        beads: plan.shares.iter().map(|s| s.bead.to_string()).collect(),
+        payouts: plan.credits,
+        recorded_at: now,
+    };
+    for s in &plan.shares {
+        ledger.paid.insert(s.bead, block);
+    }
+    ledger.payouts.insert(block, payout.clone());
+    Some(payout)
+}
+
+/// Undoes a block's payout after a reorg, so its beads count towards the next one.
+pub(crate) fn revert(block: &BlockHash) -> bool {
+    let mut ledger = LEDGER.lock().unwrap();
+    if ledger.payouts.remove(block).is_none() {
+        return false;
+    }
+    ledger.paid.retain(|_, b| b != block);
+    true
+}
+
+/// Drops payouts recorded longer than `max_age` ago, then the oldest past
+/// `max_entries`; a block that deep is not reverted any more. A bead stays
+/// marked paid while `in_braid` still holds it, or it would be paid again.
+/// Returns how many payouts went.
+pub(crate) fn prune(
+    now: u64,
+    max_age: u64,
+    max_entries: usize,
+    in_braid: impl Fn(&BlockHash) -> bool,
+) -> usize {
+    let mut ledger = LEDGER.lock().unwrap();
+    let mut stamps: HashMap<BlockHash, u64> = ledger
+        .payouts
+        .iter()
+        .map(|(b, p)| (*b, p.recorded_at))
+        .collect();
+    let evicted = gc::evict(&mut stamps, now, max_age, max_entries);
+    for b in &evicted {
+        ledger.payouts.remove(b);
+    }
+    ledger.paid.retain(|bead, _| in_braid(bead));
+    evicted.len()
+}
+
+/// Entry counts of the payout history and the paid-bead index.
+pub(crate) fn sizes() -> Vec<(&'static str, usize)> {
+    let ledger = LEDGER.lock().unwrap();
+    vec![
+        ("payouts", ledger.payouts.len()),
+        ("paid", ledger.paid.len()),
+    ]
+}
+
+pub(crate) fn get(block: &BlockHash) -> Option<BlockPayout> {
+    LEDGER.lock().unwrap().payouts.get(block).cloned()
+}
+
+/// Every recorded payout to `miner`, by height.
+pub(crate) fn earnings(miner: &str) -> Vec<BlockEarning> {
+    let ledger = LEDGER.lock().unwrap();
+    let mut out: Vec<BlockEarning> = ledger
+        .payouts
+        .values()
+        .filter_map(|p| {
+            let m = p.payouts.iter().find(|m| m.miner == miner)?;
+            Some(BlockEarning {
+                block_hash: p.block_hash.clone(),
+                height: p.height,
+                amount_sats: m.amount_sats,
+                work: m.work.clone(),
+            })
+        })
+        .collect();
+    out.sort_by(|a, b| {
+        a.height
+            .cmp(&b.height)
+            .then(a.block_hash.cmp(&b.block_hash))
+    });
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use bitcoincore_rpc::bitcoin::Amount;
+
+    fn payout(miner: &str, amount_sats: u64) -> MinerPayout {
+        MinerPayout {
+            miner: miner.to_string(),
+            payout_address: None,
+            beads: 1,
+            work: "1".to_string(),
+            amount_sats,
+        }
+    }
+
+    fn share(miner: &str, work: u128) -> Share {
+        Share {
+            bead: BlockHash::all_zeros(),
+            miner: miner.to_string(),
+            payout_address: None,
+            work,
+        }
+    }
+
+    fn amounts(reward: u64, shares: &[(&str, u128)]) -> Vec<(String, u64)> {
+        let shares: Vec<Share> = shares.iter().map(|(m, w)| share(m, *w)).collect();
+        split(reward, &shares)
+            .into_iter()
+            .map(|p| (p.miner, p.amount_sats))
+            .collect()
+    }
+
+    fn owned(v: &[(&str, u64)]) -> Vec<(String, u64)> {
+        v.iter().map(|(m, a)| (m.to_string(), *a)).collect()
+    }
+
+    #[test]
+    fn test_vector_regtest_beads_with_fees() {
+        // 50 BTC subsidy plus 12_345 sats of fees, regtest beads carry work 2
+        let payouts = amounts(
+            5_000_012_345,
+            &[("alice", 2), ("bob", 2), ("carol", 2), ("carol", 2)],
+        );
+        assert_eq!(
+            payouts,
+            owned(&[
+                ("alice", 1_250_003_086),
+                ("bob", 1_250_003_086),
+                ("carol", 2_500_006_173)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_vector_remainder_ties_go_to_lower_miner_id() {
+        let payouts = amounts(100, &[("b", 1), ("a", 1), ("c", 1)]);
+        assert_eq!(payouts, owned(&[("a", 34), ("b", 33), ("c", 33)]));
+    }
+
+    #[test]
+    fn test_vector_uneven_work() {
+        let payouts = amounts(1_000, &[("x", 3), ("y", 7), ("z", 90)]);
+        assert_eq!(payouts, owned(&[("x", 30), ("y", 70), ("z", 900)]));
+    }
+
+    #[test]
+    fn test_vector_zero_work_share() {
+        // A bead sent without a header carries no work and earns nothing
+        let payouts = amounts(1_001, &[("a", 0), ("b", 1), ("c", 1)]);
+        assert_eq!(payouts, owned(&[("a", 0), ("b", 501), ("c", 500)]));
+
+        // Nothing to split by when no bead carries work
+        assert_eq!(amounts(1_000, &[("a", 0)]), owned(&[("a", 0)]));
+    }
+
+    #[test]
+    fn test_work_above_2_64_is_shifted_without_overflow() {
+        let big = 1u128 << 100;
+        let payouts = amounts(2_100_000_000_000_000, &[("a", big), ("b", big * 3)]);
+        assert_eq!(
+            payouts,
+            owned(&[("a", 525_000_000_000_000), ("b", 1_575_000_000_000_000)])
+        );
+
+        // Work that shifts away to nothing next to a large share gets nothing
+        let payouts = amounts(1_000, &[("a", 1), ("b", 1 << 80)]);
+        assert_eq!(payouts, owned(&[("a", 0), ("b", 1_000)]));
+    }
+
+    #[test]
+    fn test_amounts_always_sum_to_reward() {
+        for reward in [1u64, 7, 999, 5_000_000_000, 312_500_123] {
+            let payouts = amounts(reward, &[("a", 3), ("b", 5), ("c", 11), ("d", 13)]);
+            assert_eq!(payouts.iter().map(|p| p.1).sum::<u64>(), reward);
+        }
+    }
+
+    #[test]
+    fn test_split_counts_beads_and_keeps_an_address() {
+        let shares = [
+            Share {
+                payout_address: Some("bcrt1qminer".to_string()),
+                ..share("a", 2)
+            },
+            share("a", 3),
+        ];
+        let payouts = split(10, &shares);
+        assert_eq!(payouts.len(), 1);
+        assert_eq!(payouts[0].beads, 2);
+        assert_eq!(payouts[0].work, "5");
+        assert_eq!(payouts[0].payout_address.as_deref(), Some("bcrt1qminer"));
+    }
+
+    fn out(script: &ScriptBuf, sats: u64) -> TxOut {
+        TxOut {
+            value: Amount::from_sat(sats),
+            script_pubkey: script.clone(),
+        }
+    }
+
+    #[test]
+    fn test_dust_folded_into_fallback_is_not_credited() {
+        let a = ScriptBuf::from_bytes(vec![0x00, 0x14, 0xaa]);
+        let b = ScriptBuf::from_bytes(vec![0x00, 0x14, 0xbb]);
+        let fallback = ScriptBuf::from_bytes(vec![0x51]);
+        let planned = vec![(payout("a", 9_000), a.clone()), (payout("b", 100), b)];
+        let outputs = vec![out(&a, 9_000), out(&fallback, 100)];
+
+        let credits = credits(&planned, &outputs);
+        assert_eq!(credits.len(), 1);
+        assert_eq!(credits[0].miner, "a");
+        assert_eq!(credits[0].amount_sats, 9_000);
+    }
+
+    #[test]
+    fn test_only_blocks_built_from_a_plan_are_recorded() {
+        let a = ScriptBuf::from_bytes(vec![0x00, 0x14, 0xcc]);
+        let outputs = vec![out(&a, 5_000)];
+        let bead = BlockHash::from_byte_array([7; 32]);
+        plan(Plan {
+            outputs: outputs.clone(),
+            shares: vec![Share {
+                bead,
+                miner: "a".to_string(),
+                payout_address: None,
+                work: 2,
+            }],
+            credits: vec![payout("a", 5_000)],
+        });
+
+        let external = BlockHash::from_byte_array([8; 32]);
+        assert!(record(external, None, &[out(&a, 4_000)], 0).is_none());
+        assert!(!is_paid(&bead));
+
+        let ours = BlockHash::from_byte_array([9; 32]);
+        let recorded = record(ours, Some(1), &outputs, 0).unwrap();
+        assert_eq!(recorded.reward_sats, 5_000);
+        assert!(is_paid(&bead));
+        assert!(record(ours, Some(1), &outputs, 0).is_none());
+    }
+}
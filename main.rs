@@ -3,7 +3,25 @@ use std::error::Error;
 use std::net::SocketAddr;
 use tower_http::cors::{Any, CorsLayer};
 
+mod acceptance;
 mod api;
+mod archive;
+mod audit;
+mod blocks;
+mod braid;
+mod capacity;
+mod chain;
+mod commitment;
+mod gc;
+mod ledger;
+mod metrics;
+mod operators;
+mod pipeline;
+mod policy;
+mod quorum;
+mod reconcile;
+mod template;
+mod timeline;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -25,6 +43,21 @@ async fn main() -> Result<(), Box<dyn Error>> {
         committed.get_block_count()?
     );
 
+    // Optional auto-promotion rules; they can also be set via PUT /policy
+    if let Ok(path) = std::env::var("POLICY_FILE") {
+        api::load_policy(&path)?;
+        println!("Loaded promotion policy from {}", path);
+    }
+    // Operator keys; once any are loaded, every transition must be signed
+    if let Ok(path) = std::env::var("OPERATORS_FILE") {
+        let count = api::load_operators(&path)?;
+        println!("Loaded {} operator keys from {}", count, path);
+    }
+    tokio::spawn(api::run_policy_loop());
+    tokio::spawn(api::run_chain_watcher());
+    tokio::spawn(api::run_gc_loop());
+    tokio::spawn(api::run_reconciler());
+
     // API with CORS enabled
     let app = api::build_router().layer(
         CorsLayer::new()
@@ -0,0 +1,377 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+
+// Seconds; shared by RPC and HTTP latency histograms
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+struct Histogram {
+    counts: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Histogram {
+            counts: [0; LATENCY_BUCKETS.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, secs: f64) {
+        for (i, bound) in LATENCY_BUCKETS.iter().enumerate() {
+            if secs <= *bound {
+                self.counts[i] += 1;
+            }
+        }
+        self.sum += secs;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        for (i, bound) in LATENCY_BUCKETS.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{{labels},le=\"{bound}\"}} {}",
+                self.counts[i]
+            );
+        }
+        let _ = writeln!(out, "{name}_bucket{{{labels},le=\"+Inf\"}} {}", self.count);
+        let _ = writeln!(out, "{name}_sum{{{labels}}} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{{{labels}}} {}", self.count);
+    }
+}
+
+struct Registry {
+    transitions: BTreeMap<(String, &'static str, String), u64>, // (transition, result, code)
+    rpc_latency: BTreeMap<(&'static str, &'static str), Histogram>, // (node, method)
+    http_requests: BTreeMap<(String, String, u16), u64>,        // (method, route, status)
+    http_latency: BTreeMap<(String, String), Histogram>,        // (method, route)
+    reorgs: u64,
+    reorged_txs: u64,
+    evicted: BTreeMap<&'static str, u64>, // store -> entries dropped by GC
+    reconciled: BTreeMap<&'static str, u64>, // reason -> stale entries fixed
+}
+
+static REGISTRY: Lazy<Mutex<Registry>> = Lazy::new(|| {
+    Mutex::new(Registry {
+        transitions: BTreeMap::new(),
+        rpc_latency: BTreeMap::new(),
+        http_requests: BTreeMap::new(),
+        http_latency: BTreeMap::new(),
+        reorgs: 0,
+        reorged_txs: 0,
+        evicted: BTreeMap::new(),
+        reconciled: BTreeMap::new(),
+    })
+});
+
+/// Point-in-time values gathered from the nodes when `/metrics` is scraped.
+pub(crate) struct NodeSnapshot {
+    pub node: &'static str,
+    pub height: u64,
+    pub mempool_vsize: u64,
+    pub mempool_fee_sats: u64,
+}
+
+pub(crate) struct Snapshot {
+    pub categories: Vec<(&'static str, usize)>,
+    pub nodes: Vec<NodeSnapshot>,
+    pub store_sizes: Vec<(&'static str, usize)>, // in-memory entries per store
+}
+
+pub(crate) fn record_transition(transition: &str, success: bool, code: &str) {
+    let result = if success { "success" } else { "failure" };
+    let mut reg = REGISTRY.lock().unwrap();
+    *reg.transitions
+        .entry((transition.to_string(), result, code.to_string()))
+        .or_insert(0) += 1;
+}
+
+/// Counts one disconnected block and the txs it had confirmed.
+pub(crate) fn record_reorg(txs: usize) {
+    let mut reg = REGISTRY.lock().unwrap();
+    reg.reorgs += 1;
+    reg.reorged_txs += txs as u64;
+}
+
+/// Counts entries a GC pass dropped from `store`.
+pub(crate) fn record_evictions(store: &'static str, count: usize) {
+    if count > 0 {
+        *REGISTRY.lock().unwrap().evicted.entry(store).or_insert(0) += count as u64;
+    }
+}
+
+/// Counts one stale pipeline entry fixed by the reconciler.
+pub(crate) fn record_reconciled(reason: &'static str) {
+    *REGISTRY
+        .lock()
+        .unwrap()
+        .reconciled
+        .entry(reason)
+        .or_insert(0) += 1;
+}
+
+/// Runs a single RPC call against `node` and records its latency under `method`.
+pub(crate) fn time_rpc<T>(node: &'static str, method: &'static str, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let out = f();
+    let elapsed = start.elapsed().as_secs_f64();
+    REGISTRY
+        .lock()
+        .unwrap()
+        .rpc_latency
+        .entry((node, method))
+        .or_insert_with(Histogram::new)
+        .observe(elapsed);
+    out
+}
+
+/// Route-level middleware: counts requests and their latency per matched route.
+pub(crate) async fn track_http(req: Request, next: Next) -> Response {
+    let method = req.method().to_string();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let start = Instant::now();
+    let response = next.run(req).await;
+    let elapsed = start.elapsed().as_secs_f64();
+    let status = response.status().as_u16();
+
+    let mut reg = REGISTRY.lock().unwrap();
+    *reg.http_requests
+        .entry((method.clone(), route.clone(), status))
+        .or_insert(0) += 1;
+    reg.http_latency
+        .entry((method, route))
+        .or_insert_with(Histogram::new)
+        .observe(elapsed);
+
+    response
+}
+
+/// Renders every metric in the Prometheus text exposition format.
+pub(crate) fn render(snapshot: &Snapshot) -> String {
+    let mut out = String::new();
+
+    out.push_str("# HELP braidpool_transactions Transactions per pipeline category\n");
+    out.push_str("# TYPE braidpool_transactions gauge\n");
+    for (category, count) in &snapshot.categories {
+        let _ = writeln!(
+            out,
+            "braidpool_transactions{{category=\"{category}\"}} {count}"
+        );
+    }
+
+    out.push_str("# HELP braidpool_mempool_vsize_vbytes Sum of mempool virtual sizes per node\n");
+    out.push_str("# TYPE braidpool_mempool_vsize_vbytes gauge\n");
+    for n in &snapshot.nodes {
+        let _ = writeln!(
+            out,
+            "braidpool_mempool_vsize_vbytes{{node=\"{}\"}} {}",
+            n.node, n.mempool_vsize
+        );
+    }
+
+    out.push_str("# HELP braidpool_mempool_fees_sats Total mempool fees per node\n");
+    out.push_str("# TYPE braidpool_mempool_fees_sats gauge\n");
+    for n in &snapshot.nodes {
+        let _ = writeln!(
+            out,
+            "braidpool_mempool_fees_sats{{node=\"{}\"}} {}",
+            n.node, n.mempool_fee_sats
+        );
+    }
+
+    out.push_str("# HELP braidpool_node_height Block height per node\n");
+    out.push_str("# TYPE braidpool_node_height gauge\n");
+    for n in &snapshot.nodes {
+        let _ = writeln!(
+            out,
+            "braidpool_node_height{{node=\"{}\"}} {}",
+            n.node, n.height
+        );
+    }
+
+    // Positive when cmempool is behind bitcoind
+    let heights: Vec<u64> = snapshot.nodes.iter().map(|n| n.height).collect();
+    if let [std_height, cm_height] = heights[..] {
+        out.push_str(
+            "# HELP braidpool_node_sync_lag_blocks bitcoind height minus cmempool height\n",
+        );
+        out.push_str("# TYPE braidpool_node_sync_lag_blocks gauge\n");
+        let _ = writeln!(
+            out,
+            "braidpool_node_sync_lag_blocks {}",
+            std_height as i64 - cm_height as i64
+        );
+    }
+
+    out.push_str("# HELP braidpool_store_entries In-memory entries per store\n");
+    out.push_str("# TYPE braidpool_store_entries gauge\n");
+    for (store, count) in &snapshot.store_sizes {
+        let _ = writeln!(out, "braidpool_store_entries{{store=\"{store}\"}} {count}");
+    }
+
+    let reg = REGISTRY.lock().unwrap();
+
+    out.push_str("# HELP braidpool_transitions_total Pipeline transition attempts\n");
+    out.push_str("# TYPE braidpool_transitions_total counter\n");
+    for ((transition, result, code), count) in &reg.transitions {
+        let _ = writeln!(
+            out,
+            "braidpool_transitions_total{{transition=\"{transition}\",result=\"{result}\",code=\"{code}\"}} {count}"
+        );
+    }
+
+    out.push_str("# HELP braidpool_reorged_blocks_total Blocks disconnected by reorgs\n");
+    out.push_str("# TYPE braidpool_reorged_blocks_total counter\n");
+    let _ = writeln!(out, "braidpool_reorged_blocks_total {}", reg.reorgs);
+
+    out.push_str(
+        "# HELP braidpool_reorged_transactions_total Txs whose confirming block was disconnected\n",
+    );
+    out.push_str("# TYPE braidpool_reorged_transactions_total counter\n");
+    let _ = writeln!(
+        out,
+        "braidpool_reorged_transactions_total {}",
+        reg.reorged_txs
+    );
+
+    out.push_str("# HELP braidpool_gc_evicted_total Entries dropped by garbage collection\n");
+    out.push_str("# TYPE braidpool_gc_evicted_total counter\n");
+    for (store, count) in &reg.evicted {
+        let _ = writeln!(
+            out,
+            "braidpool_gc_evicted_total{{store=\"{store}\"}} {count}"
+        );
+    }
+
+    out.push_str(
+        "# HELP braidpool_reconciled_total Stale pipeline entries fixed by the reconciler\n",
+    );
+    out.push_str("# TYPE braidpool_reconciled_total counter\n");
+    for (reason, count) in &reg.reconciled {
+        let _ = writeln!(
+            out,
+            "braidpool_reconciled_total{{reason=\"{reason}\"}} {count}"
+        );
+    }
+
+    out.push_str("# HELP braidpool_rpc_duration_seconds RPC call latency per node and method\n");
+    out.push_str("# TYPE braidpool_rpc_duration_seconds histogram\n");
+    for ((node, method), hist) in &reg.rpc_latency {
+        hist.render(
+            &mut out,
+            "braidpool_rpc_duration_seconds",
+            &format!("node=\"{node}\",method=\"{method}\""),
+        );
+    }
+
+    out.push_str("# HELP braidpool_http_requests_total HTTP requests per route\n");
+    out.push_str("# TYPE braidpool_http_requests_total counter\n");
+    for ((method, route, status), count) in &reg.http_requests {
+        let _ = writeln!(
+            out,
+            "braidpool_http_requests_total{{method=\"{method}\",route=\"{route}\",status=\"{status}\"}} {count}"
+        );
+    }
+
+    out.push_str("# HELP braidpool_http_request_duration_seconds HTTP request latency per route\n");
+    out.push_str("# TYPE braidpool_http_request_duration_seconds histogram\n");
+    for ((method, route), hist) in &reg.http_latency {
+        hist.render(
+            &mut out,
+            "braidpool_http_request_duration_seconds",
+            &format!("method=\"{method}\",route=\"{route}\""),
+        );
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(heights: &[u64]) -> Snapshot {
+        Snapshot {
+            categories: Vec::new(),
+            nodes: ["bitcoind", "cmempool"]
+                .into_iter()
+                .zip(heights)
+                .map(|(node, height)| NodeSnapshot {
+                    node,
+                    height: *height,
+                    mempool_vsize: 0,
+                    mempool_fee_sats: 0,
+                })
+                .collect(),
+            store_sizes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let mut hist = Histogram::new();
+        for secs in [0.005, 0.05, 0.5, 5.0] {
+            hist.observe(secs);
+        }
+
+        // 5.0s lands only in +Inf, which is the total count
+        assert_eq!(hist.counts, [0, 0, 1, 1, 1, 2, 2, 2, 3, 3, 3]);
+        assert_eq!(hist.count, 4);
+        assert!((hist.sum - 5.555).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_histogram_bucket_bound_is_inclusive() {
+        let mut hist = Histogram::new();
+        hist.observe(0.1);
+
+        assert_eq!(hist.counts, [0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_histogram_renders_inf_bucket_as_total() {
+        let mut hist = Histogram::new();
+        hist.observe(0.002);
+        hist.observe(10.0);
+
+        let mut out = String::new();
+        hist.render(&mut out, "h", "node=\"x\"");
+        assert!(out.contains("h_bucket{node=\"x\",le=\"0.0025\"} 1\n"));
+        assert!(out.contains("h_bucket{node=\"x\",le=\"2.5\"} 1\n"));
+        assert!(out.contains("h_bucket{node=\"x\",le=\"+Inf\"} 2\n"));
+        assert!(out.contains("h_count{node=\"x\"} 2\n"));
+    }
+
+    #[test]
+    fn test_sync_lag_sign() {
+        let lag = |heights: &[u64]| {
+            render(&snapshot(heights))
+                .lines()
+                .find_map(|l| l.strip_prefix("braidpool_node_sync_lag_blocks "))
+                .map(|v| v.parse::<i64>().unwrap())
+        };
+
+        assert_eq!(lag(&[105, 100]), Some(5), "cmempool behind bitcoind");
+        assert_eq!(lag(&[100, 100]), Some(0), "nodes in sync");
+        assert_eq!(lag(&[100, 103]), Some(-3), "cmempool ahead of bitcoind");
+        assert_eq!(lag(&[100]), None, "needs both nodes");
+    }
+}
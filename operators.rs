@@ -0,0 +1,366 @@
+use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoincore_rpc::bitcoin::secp256k1::{
+    schnorr, Message, Secp256k1, VerifyOnly, XOnlyPublicKey,
+};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// BIP340-style tag for the signed transition digest
+const TRANSITION_TAG: &[u8] = b"braidpool/transition";
+
+// How far a signed timestamp may be from the server clock, either way
+pub(crate) const MAX_CLOCK_SKEW_SECS: u64 = 300;
+
+// Longest nonce accepted; anything unique per key will do
+const MAX_NONCE_LEN: usize = 64;
+
+// Signed transitions kept for `/audit/transitions`
+const MAX_SIGNED_ENTRIES: usize = 1_000;
+
+/// A key allowed to move txs through the pipeline.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Operator {
+    pub id: String,
+    pub pubkey: String, // 32-byte x-only key, hex
+}
+
+/// What a client sends alongside a transition request.
+pub(crate) struct SignedRequest {
+    pub pubkey: String,
+    pub nonce: String,
+    pub timestamp: u64,
+    pub signature: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum SignatureError {
+    Missing,
+    Malformed(String),
+    UnknownOperator,
+    Stale { skew: u64 },
+    NonceReused,
+    Invalid,
+}
+
+impl SignatureError {
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            SignatureError::Missing => "signature_required",
+            SignatureError::Malformed(_) => "malformed_signature",
+            SignatureError::UnknownOperator => "unknown_operator",
+            SignatureError::Stale { .. } => "stale_signature",
+            SignatureError::NonceReused => "nonce_reused",
+            SignatureError::Invalid => "invalid_signature",
+        }
+    }
+
+    pub(crate) fn message(&self) -> String {
+        match self {
+            SignatureError::Missing => {
+                "Transitions must be signed by a registered operator key".to_string()
+            }
+            SignatureError::Malformed(e) => e.clone(),
+            SignatureError::UnknownOperator => "Key is not a registered operator".to_string(),
+            SignatureError::Stale { skew } => format!(
+                "Timestamp is {skew}s from server time, at most {MAX_CLOCK_SKEW_SECS}s allowed"
+            ),
+            SignatureError::NonceReused => {
+                "Nonce was already used for this transition with this key".to_string()
+            }
+            SignatureError::Invalid => "Signature does not verify".to_string(),
+        }
+    }
+}
+
+/// One signed transition request, as kept in the audit log.
+#[derive(Clone, Serialize)]
+pub(crate) struct SignedTransition {
+    pub txid: String,
+    pub transition: &'static str,
+    pub operator: String,
+    pub pubkey: String,
+    pub nonce: String,
+    pub timestamp: u64,
+    pub signature: String,
+    pub status: u16, // HTTP status the transition ended with
+    pub at: u64,
+}
+
+struct Registry {
+    keys: HashMap<XOnlyPublicKey, String>, // key -> operator id
+    used: HashMap<(XOnlyPublicKey, [u8; 32]), u64>, // digests already accepted, by signed timestamp
+    log: Vec<SignedTransition>,
+}
+
+impl Registry {
+    fn new() -> Self {
+        Registry {
+            keys: HashMap::new(),
+            used: HashMap::new(),
+            log: Vec::new(),
+        }
+    }
+
+    fn register(&mut self, operators: &[Operator]) -> Result<(), String> {
+        let mut keys = HashMap::new();
+        for op in operators {
+            if op.id.is_empty() {
+                return Err("operator id must not be empty".to_string());
+            }
+            let key = op
+                .pubkey
+                .parse::<XOnlyPublicKey>()
+                .map_err(|e| format!("operator {}: invalid pubkey: {e}", op.id))?;
+            if keys.values().any(|id| *id == op.id) {
+                return Err(format!("operator {} is listed twice", op.id));
+            }
+            if keys.insert(key, op.id.clone()).is_some() {
+                return Err(format!("operator {}: key is already registered", op.id));
+            }
+        }
+        self.keys = keys;
+        Ok(())
+    }
+
+    fn verify(
+        &mut self,
+        txid: &str,
+        transition: &str,
+        req: &SignedRequest,
+        now: u64,
+    ) -> Result<String, SignatureError> {
+        let key = req
+            .pubkey
+            .parse::<XOnlyPublicKey>()
+            .map_err(|e| SignatureError::Malformed(format!("invalid operator key: {e}")))?;
+        let signature = req
+            .signature
+            .parse::<schnorr::Signature>()
+            .map_err(|e| SignatureError::Malformed(format!("invalid signature: {e}")))?;
+        if req.nonce.is_empty() || req.nonce.len() > MAX_NONCE_LEN {
+            return Err(SignatureError::Malformed(format!(
+                "nonce must be 1 to {MAX_NONCE_LEN} bytes"
+            )));
+        }
+
+        let operator = self
+            .keys
+            .get(&key)
+            .cloned()
+            .ok_or(SignatureError::UnknownOperator)?;
+        let skew = now.abs_diff(req.timestamp);
+        if skew > MAX_CLOCK_SKEW_SECS {
+            return Err(SignatureError::Stale { skew });
+        }
+        let digest = digest(txid, transition, &req.nonce, req.timestamp);
+        SECP.verify_schnorr(&signature, &Message::from_digest(digest), &key)
+            .map_err(|_| SignatureError::Invalid)?;
+
+        // Anything older is rejected as stale before it is looked up here
+        self.used
+            .retain(|_, ts| now.saturating_sub(*ts) <= MAX_CLOCK_SKEW_SECS);
+        if self.used.insert((key, digest), req.timestamp).is_some() {
+            return Err(SignatureError::NonceReused);
+        }
+        Ok(operator)
+    }
+}
+
+static REGISTRY: Lazy<Mutex<Registry>> = Lazy::new(|| Mutex::new(Registry::new()));
+
+static SECP: Lazy<Secp256k1<VerifyOnly>> = Lazy::new(Secp256k1::verification_only);
+
+/// The 32 bytes an operator signs for a transition.
+///
+/// `tagged_hash("braidpool/transition", "{txid}:{transition}:{nonce}:{timestamp}")`
+/// with the txid in its usual hex form and the transition name in lowercase,
+/// e.g. `propose`. Settings changes are signed the same way with the
+/// subject for the txid and the action for the transition, e.g.
+/// `policy:update:{nonce}:{timestamp}`.
+pub(crate) fn digest(txid: &str, transition: &str, nonce: &str, timestamp: u64) -> [u8; 32] {
+    let tag = sha256::Hash::hash(TRANSITION_TAG);
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_byte_array());
+    engine.input(tag.as_byte_array());
+    engine.input(format!("{txid}:{transition}:{nonce}:{timestamp}").as_bytes());
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// Replaces the registered operators; ids and keys must be unique.
+pub(crate) fn register(operators: &[Operator]) -> Result<(), String> {
+    REGISTRY.lock().unwrap().register(operators)
+}
+
+/// Whether transitions must be signed; only once an operator is registered.
+pub(crate) fn required() -> bool {
+    !REGISTRY.lock().unwrap().keys.is_empty()
+}
+
+pub(crate) fn list() -> Vec<Operator> {
+    let registry = REGISTRY.lock().unwrap();
+    let mut out: Vec<Operator> = registry
+        .keys
+        .iter()
+        .map(|(key, id)| Operator {
+            id: id.clone(),
+            pubkey: key.to_string(),
+        })
+        .collect();
+    out.sort_by(|a, b| a.id.cmp(&b.id));
+    out
+}
+
+/// Checks `req` against `txid` and `transition`; returns the operator id.
+///
+/// Each signed digest is accepted once, so one nonce may cover several
+/// txs of a batch but never the same transition twice. The nonce is only
+/// used up once the signature verifies, so a forged request cannot burn it.
+pub(crate) fn verify(
+    txid: &str,
+    transition: &str,
+    req: &SignedRequest,
+    now: u64,
+) -> Result<String, SignatureError> {
+    REGISTRY.lock().unwrap().verify(txid, transition, req, now)
+}
+
+pub(crate) fn record(entry: SignedTransition) {
+    let mut registry = REGISTRY.lock().unwrap();
+    registry.log.push(entry);
+    if registry.log.len() > MAX_SIGNED_ENTRIES {
+        let excess = registry.log.len() - MAX_SIGNED_ENTRIES;
+        registry.log.drain(..excess);
+    }
+}
+
+/// Signed transitions, oldest first, optionally for one tx.
+pub(crate) fn log(txid: Option<&str>) -> Vec<SignedTransition> {
+    REGISTRY
+        .lock()
+        .unwrap()
+        .log
+        .iter()
+        .filter(|e| txid.is_none_or(|t| e.txid == t))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::secp256k1::Keypair;
+
+    const TXID: &str = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+    const NOW: u64 = 1_700_000_000;
+
+    fn keypair() -> Keypair {
+        Keypair::from_seckey_slice(&Secp256k1::new(), &[7u8; 32]).unwrap()
+    }
+
+    fn registry() -> Registry {
+        let mut registry = Registry::new();
+        let operator = Operator {
+            id: "op-1".to_string(),
+            pubkey: keypair().x_only_public_key().0.to_string(),
+        };
+        registry.register(&[operator]).unwrap();
+        registry
+    }
+
+    fn signed(txid: &str, transition: &str, nonce: &str, timestamp: u64) -> SignedRequest {
+        let keypair = keypair();
+        let msg = Message::from_digest(digest(txid, transition, nonce, timestamp));
+        let signature = Secp256k1::new().sign_schnorr_with_aux_rand(&msg, &keypair, &[0u8; 32]);
+        SignedRequest {
+            pubkey: keypair.x_only_public_key().0.to_string(),
+            nonce: nonce.to_string(),
+            timestamp,
+            signature: signature.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_signed_transition_verifies() {
+        let req = signed(TXID, "propose", "n-1", NOW);
+        assert_eq!(
+            registry().verify(TXID, "propose", &req, NOW),
+            Ok("op-1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_tampered_fields_are_rejected() {
+        let other = TXID.replace('4', "5");
+        let mut registry = registry();
+        let req = signed(TXID, "propose", "n-1", NOW);
+        assert_eq!(
+            registry.verify(&other, "propose", &req, NOW),
+            Err(SignatureError::Invalid)
+        );
+        assert_eq!(
+            registry.verify(TXID, "schedule", &req, NOW),
+            Err(SignatureError::Invalid)
+        );
+        let nonce = SignedRequest {
+            nonce: "n-2".to_string(),
+            ..signed(TXID, "propose", "n-1", NOW)
+        };
+        assert_eq!(
+            registry.verify(TXID, "propose", &nonce, NOW),
+            Err(SignatureError::Invalid)
+        );
+        let timestamp = SignedRequest {
+            timestamp: NOW + 1,
+            ..signed(TXID, "propose", "n-1", NOW)
+        };
+        assert_eq!(
+            registry.verify(TXID, "propose", &timestamp, NOW),
+            Err(SignatureError::Invalid)
+        );
+        // None of the forgeries used up the nonce
+        assert!(registry.verify(TXID, "propose", &req, NOW).is_ok());
+    }
+
+    #[test]
+    fn test_replayed_nonce_is_rejected_but_batch_may_share_it() {
+        let mut registry = registry();
+        let other = TXID.replace('4', "5");
+        let first = signed(TXID, "commit", "batch-1", NOW);
+        let second = signed(&other, "commit", "batch-1", NOW);
+
+        assert!(registry.verify(TXID, "commit", &first, NOW).is_ok());
+        assert!(registry.verify(&other, "commit", &second, NOW).is_ok());
+        assert_eq!(
+            registry.verify(TXID, "commit", &first, NOW + 10),
+            Err(SignatureError::NonceReused)
+        );
+    }
+
+    #[test]
+    fn test_timestamp_outside_skew_is_stale() {
+        let mut registry = registry();
+        let late = signed(TXID, "commit", "a", NOW - MAX_CLOCK_SKEW_SECS - 1);
+        assert_eq!(
+            registry.verify(TXID, "commit", &late, NOW),
+            Err(SignatureError::Stale { skew: 301 })
+        );
+        let early = signed(TXID, "commit", "b", NOW + MAX_CLOCK_SKEW_SECS + 1);
+        assert_eq!(
+            registry.verify(TXID, "commit", &early, NOW),
+            Err(SignatureError::Stale { skew: 301 })
+        );
+        let edge = signed(TXID, "commit", "c", NOW - MAX_CLOCK_SKEW_SECS);
+        assert!(registry.verify(TXID, "commit", &edge, NOW).is_ok());
+    }
+
+    #[test]
+    fn test_unregistered_key_is_rejected() {
+        let req = signed(TXID, "commit", "n-1", NOW);
+        assert_eq!(
+            Registry::new().verify(TXID, "commit", &req, NOW),
+            Err(SignatureError::UnknownOperator)
+        );
+    }
+}
@@ -0,0 +1,978 @@
+use crate::capacity::{Budget, Cost, Overflow};
+use crate::commitment;
+use crate::gc;
+use bitcoincore_rpc::bitcoin::{BlockHash, Txid};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Transition {
+    Commit,     // Mempool → Committed
+    Propose,    // Committed → Proposed
+    Schedule,   // Proposed → Scheduled
+    Uncommit,   // Committed → Mempool
+    Unpropose,  // Proposed → Committed
+    Unschedule, // Scheduled → Proposed
+}
+
+impl Transition {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Transition::Commit => "commit",
+            Transition::Propose => "propose",
+            Transition::Schedule => "schedule",
+            Transition::Uncommit => "uncommit",
+            Transition::Unpropose => "unpropose",
+            Transition::Unschedule => "unschedule",
+        }
+    }
+
+    pub(crate) fn is_reverse(self) -> bool {
+        matches!(
+            self,
+            Transition::Uncommit | Transition::Unpropose | Transition::Unschedule
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum TransitionError {
+    VersionMismatch { current: u64 },
+    NotCommitted,
+    NotProposed,
+    NotScheduled,
+    StillProposed,  // uncommit before unpropose
+    StillScheduled, // unpropose before unschedule
+    CapacityExceeded { needed: Cost, remaining: Cost },
+}
+
+/// Where a tx sat in the pipeline, remembered across a confirmation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub(crate) enum Stage {
+    Mempool,
+    Committed,
+    Proposed,
+    Scheduled,
+}
+
+impl Stage {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Stage::Mempool => "Mempool",
+            Stage::Committed => "Committed",
+            Stage::Proposed => "Proposed",
+            Stage::Scheduled => "Scheduled",
+        }
+    }
+}
+
+// Enough to put a tx back if its block is reorged out
+#[derive(Clone)]
+pub(crate) struct Confirmation {
+    pub block: BlockHash,
+    pub stage: Stage,
+    cost: Option<Cost>,
+    fee: Option<u64>,
+}
+
+pub(crate) struct Applied {
+    pub version: u64,
+    pub was_withdrawn: bool,
+    pub queued: bool,        // schedule held back for lack of capacity
+    pub promoted: Vec<Txid>, // queued txs scheduled because capacity freed up
+}
+
+#[derive(Clone)]
+pub(crate) struct StateStore {
+    pub(crate) committed: HashSet<Txid>,   // Stage 2: In cmempool
+    pub(crate) proposed: HashSet<Txid>,    // Stage 3: Marked as proposed
+    pub(crate) scheduled: HashSet<Txid>,   // Stage 4: Marked as scheduled
+    pub(crate) withdrawn: HashSet<Txid>,   // Uncommitted but still sitting in cmempool
+    pub(crate) queue: Vec<Txid>,           // Proposed, waiting for block capacity
+    pub(crate) costs: HashMap<Txid, Cost>, // Known block cost per tx
+    pub(crate) fees: HashMap<Txid, u64>,   // Fee in sats, noted alongside the cost
+    pub(crate) budget: Budget,
+    pub(crate) confirmed: HashMap<Txid, Confirmation>, // Stage held before confirming
+    versions: HashMap<Txid, u64>,                      // Bumped on every state change
+    last_version: u64,                                 // Highest version handed out, store-wide
+    version_floor: u64,                                // Highest version GC has dropped
+    entered: HashMap<Txid, u64>,                       // When the tx entered its current stage
+    idle_since: HashMap<Txid, u64>,                    // Out of every stage, as first noticed by GC
+    committed_epoch: u64,                              // Bumped whenever `committed` changes
+}
+
+impl StateStore {
+    pub(crate) fn new() -> Self {
+        StateStore {
+            committed: HashSet::new(),
+            proposed: HashSet::new(),
+            scheduled: HashSet::new(),
+            withdrawn: HashSet::new(),
+            queue: Vec::new(),
+            costs: HashMap::new(),
+            fees: HashMap::new(),
+            budget: Budget::default(),
+            confirmed: HashMap::new(),
+            versions: HashMap::new(),
+            last_version: 0,
+            version_floor: 0,
+            entered: HashMap::new(),
+            idle_since: HashMap::new(),
+            committed_epoch: 0,
+        }
+    }
+
+    /// Current version of a tx.
+    ///
+    /// Versions come from one counter for the whole store, so a tx never
+    /// returns to a version it held before. Untracked txs, including ones
+    /// GC has forgotten, report the highest version GC has dropped (0 until
+    /// the first drop); that is at least any version they held, so a stale
+    /// `If-Match` cannot match once they come back into the pipeline.
+    pub(crate) fn version(&self, txid: &Txid) -> u64 {
+        self.versions
+            .get(txid)
+            .copied()
+            .unwrap_or(self.version_floor)
+    }
+
+    /// Version of the Committed set as a whole, for its Merkle root.
+    pub(crate) fn committed_epoch(&self) -> u64 {
+        self.committed_epoch
+    }
+
+    /// When a tx entered the stage it is in; None while in Mempool.
+    pub(crate) fn entered_at(&self, txid: &Txid) -> Option<u64> {
+        self.entered.get(txid).copied()
+    }
+
+    // Restarts the age in stage of a tx that moved off `before`
+    fn enter(&mut self, txid: Txid, before: Stage, now: u64) {
+        match self.stage_of(&txid) {
+            stage if stage == before => {}
+            Stage::Mempool => {
+                self.entered.remove(&txid);
+            }
+            _ => {
+                self.entered.insert(txid, now);
+            }
+        }
+    }
+
+    fn bump(&mut self, txid: Txid) -> u64 {
+        self.last_version += 1;
+        self.versions.insert(txid, self.last_version);
+        self.last_version
+    }
+
+    fn bump_epoch(&mut self) {
+        self.committed_epoch += 1;
+    }
+
+    // Takes the root for the epoch the store is at
+    fn record_epoch(&self, now: u64) {
+        commitment::record(self.committed_epoch, &self.committed, now);
+    }
+
+    // Runs `f` and takes one root for the epoch it ends on, however many
+    // times it bumped it; epochs passed on the way were never visible
+    // outside the lock, so nothing could have asked for them
+    fn recording<T>(&mut self, now: u64, f: impl FnOnce(&mut Self) -> T) -> T {
+        let from = self.committed_epoch;
+        let out = f(self);
+        if self.committed_epoch != from {
+            self.record_epoch(now);
+        }
+        out
+    }
+
+    fn cost(&self, txid: &Txid) -> Cost {
+        self.costs.get(txid).copied().unwrap_or_default()
+    }
+
+    /// Combined weight and sigops of the Scheduled set.
+    pub(crate) fn scheduled_cost(&self) -> Cost {
+        self.scheduled
+            .iter()
+            .fold(Cost::default(), |acc, txid| acc.add(self.cost(txid)))
+    }
+
+    pub(crate) fn remaining(&self) -> Cost {
+        self.budget.limit().saturating_sub(self.scheduled_cost())
+    }
+
+    // Schedules queued txs, oldest first, for as long as they fit
+    fn drain_queue(&mut self, now: u64) -> Vec<Txid> {
+        let mut promoted = Vec::new();
+        let mut remaining = self.remaining();
+        for txid in std::mem::take(&mut self.queue) {
+            let cost = self.cost(&txid);
+            if cost.fits_in(remaining) {
+                remaining = remaining.saturating_sub(cost);
+                self.scheduled.insert(txid);
+                self.bump(txid);
+                self.entered.insert(txid, now);
+                promoted.push(txid);
+            } else {
+                self.queue.push(txid);
+            }
+        }
+        promoted
+    }
+
+    /// Replaces the block budget; queued txs that now fit are scheduled.
+    pub(crate) fn set_budget(&mut self, budget: Budget, now: u64) -> Vec<Txid> {
+        self.budget = budget;
+        self.drain_queue(now)
+    }
+
+    /// Checks the guards for `transition` and applies it in one step.
+    ///
+    /// `in_cpool` is whether the cmempool node currently holds the tx, which
+    /// callers look up before taking the lock. When `expected` is set the
+    /// change only goes through if the tx is still at that version.
+    pub(crate) fn apply(
+        &mut self,
+        txid: Txid,
+        transition: Transition,
+        in_cpool: bool,
+        expected: Option<u64>,
+        now: u64,
+    ) -> Result<Applied, TransitionError> {
+        self.recording(now, |s| {
+            s.transit(txid, transition, in_cpool, expected, now)
+        })
+    }
+
+    fn transit(
+        &mut self,
+        txid: Txid,
+        transition: Transition,
+        in_cpool: bool,
+        expected: Option<u64>,
+        now: u64,
+    ) -> Result<Applied, TransitionError> {
+        let current = self.version(&txid);
+        if expected.is_some_and(|v| v != current) {
+            return Err(TransitionError::VersionMismatch { current });
+        }
+
+        let before = self.stage_of(&txid);
+        let was_withdrawn = self.withdrawn.contains(&txid);
+        let is_committed = self.committed.contains(&txid) || (in_cpool && !was_withdrawn);
+        let mut queued = false;
+        let mut promoted = Vec::new();
+
+        let changed = match transition {
+            Transition::Commit => {
+                let restored = self.withdrawn.remove(&txid);
+                let inserted = self.committed.insert(txid);
+                if inserted {
+                    self.bump_epoch();
+                }
+                inserted || restored
+            }
+            Transition::Propose => {
+                if !is_committed {
+                    return Err(TransitionError::NotCommitted);
+                }
+                self.proposed.insert(txid)
+            }
+            Transition::Schedule => {
+                if !self.proposed.contains(&txid) {
+                    return Err(TransitionError::NotProposed);
+                }
+                let needed = self.cost(&txid);
+                let remaining = self.remaining();
+                if self.scheduled.contains(&txid) {
+                    false
+                } else if needed.fits_in(remaining) {
+                    self.queue.retain(|t| *t != txid);
+                    self.scheduled.insert(txid)
+                } else if self.budget.overflow == Overflow::Queue {
+                    queued = true;
+                    if self.queue.contains(&txid) {
+                        false
+                    } else {
+                        self.queue.push(txid);
+                        true
+                    }
+                } else {
+                    return Err(TransitionError::CapacityExceeded { needed, remaining });
+                }
+            }
+            Transition::Uncommit => {
+                if self.proposed.contains(&txid) {
+                    return Err(TransitionError::StillProposed);
+                }
+                if !is_committed {
+                    return Err(TransitionError::NotCommitted);
+                }
+                if self.committed.remove(&txid) {
+                    self.bump_epoch();
+                }
+                if in_cpool {
+                    self.withdrawn.insert(txid);
+                }
+                true
+            }
+            Transition::Unpropose => {
+                if self.scheduled.contains(&txid) {
+                    return Err(TransitionError::StillScheduled);
+                }
+                if !self.proposed.remove(&txid) {
+                    return Err(TransitionError::NotProposed);
+                }
+                self.queue.retain(|t| *t != txid);
+                true
+            }
+            Transition::Unschedule => {
+                if self.scheduled.remove(&txid) {
+                    promoted = self.drain_queue(now);
+                } else if self.queue.contains(&txid) {
+                    self.queue.retain(|t| *t != txid);
+                } else {
+                    return Err(TransitionError::NotScheduled);
+                }
+                true
+            }
+        };
+
+        let version = if changed { self.bump(txid) } else { current };
+        self.enter(txid, before, now);
+        Ok(Applied {
+            version,
+            was_withdrawn,
+            queued,
+            promoted,
+        })
+    }
+
+    /// Applies `transition` to every `(txid, in_cpool)` pair or to none of them.
+    ///
+    /// On the first failure the store is restored and the index of the
+    /// offending entry is returned with its error.
+    pub(crate) fn apply_all(
+        &mut self,
+        items: &[(Txid, bool)],
+        transition: Transition,
+        now: u64,
+    ) -> Result<Vec<Applied>, (usize, TransitionError)> {
+        let snapshot = self.clone();
+        self.recording(now, |s| {
+            let mut applied = Vec::with_capacity(items.len());
+            for (i, (txid, in_cpool)) in items.iter().enumerate() {
+                match s.transit(*txid, transition, *in_cpool, None, now) {
+                    Ok(a) => applied.push(a),
+                    Err(e) => {
+                        *s = snapshot;
+                        return Err((i, e));
+                    }
+                }
+            }
+            Ok(applied)
+        })
+    }
+
+    /// Marks a tx that sits in cmempool without having been committed, e.g.
+    /// relayed by a commit that then failed. False if it is committed.
+    pub(crate) fn withdraw(&mut self, txid: Txid) -> bool {
+        if self.committed.contains(&txid) {
+            return false;
+        }
+        self.withdrawn.insert(txid);
+        true
+    }
+
+    pub(crate) fn stage_of(&self, txid: &Txid) -> Stage {
+        if self.scheduled.contains(txid) {
+            Stage::Scheduled
+        } else if self.proposed.contains(txid) {
+            Stage::Proposed
+        } else if self.committed.contains(txid) {
+            Stage::Committed
+        } else {
+            Stage::Mempool
+        }
+    }
+
+    /// Clears a tx confirmed in `block`, remembering its stage for a reorg.
+    ///
+    /// Only the first call per block is recorded, so a later sweep over the
+    /// same tx does not overwrite the stage with Mempool.
+    pub(crate) fn confirm(&mut self, txid: Txid, block: BlockHash, now: u64) -> Vec<Txid> {
+        if self.confirmed.get(&txid).is_none_or(|c| c.block != block) {
+            let record = Confirmation {
+                block,
+                stage: self.stage_of(&txid),
+                cost: self.costs.get(&txid).copied(),
+                fee: self.fees.get(&txid).copied(),
+            };
+            self.confirmed.insert(txid, record);
+        }
+        self.clear(&txid, now)
+    }
+
+    /// Puts a tx back where it was before `block` confirmed it.
+    ///
+    /// A tx that no longer fits in the Scheduled set is queued instead.
+    pub(crate) fn unconfirm(&mut self, txid: Txid, block: &BlockHash, now: u64) -> Option<Stage> {
+        if self.confirmed.get(&txid)?.block != *block {
+            return None;
+        }
+        let record = self.confirmed.remove(&txid)?;
+        if let Some(cost) = record.cost {
+            self.costs.insert(txid, cost);
+        }
+        if let Some(fee) = record.fee {
+            self.fees.insert(txid, fee);
+        }
+
+        let mut stage = record.stage;
+        if stage != Stage::Mempool && self.committed.insert(txid) {
+            self.bump_epoch();
+            self.record_epoch(now);
+        }
+        if matches!(stage, Stage::Proposed | Stage::Scheduled) {
+            self.proposed.insert(txid);
+        }
+        if stage == Stage::Scheduled {
+            if self.cost(&txid).fits_in(self.remaining()) {
+                self.scheduled.insert(txid);
+            } else {
+                self.queue.push(txid);
+                stage = Stage::Proposed;
+            }
+        }
+        self.bump(txid);
+        self.enter(txid, Stage::Mempool, now);
+        Some(stage)
+    }
+
+    /// Forgets reorg records for txs whose block is now too deep to reorg.
+    pub(crate) fn finalize(&mut self, txids: &[Txid]) {
+        for txid in txids {
+            self.confirmed.remove(txid);
+        }
+    }
+
+    /// Every tx held in some stage, queued or withdrawn, with its version.
+    ///
+    /// A tx can be proposed straight from cmempool without ever being in
+    /// `committed`, so every set is walked.
+    pub(crate) fn tracked(&self) -> Vec<(Txid, Stage, bool, u64)> {
+        let txids: HashSet<&Txid> = self
+            .committed
+            .iter()
+            .chain(&self.proposed)
+            .chain(&self.scheduled)
+            .chain(&self.queue)
+            .chain(&self.withdrawn)
+            .collect();
+        txids
+            .into_iter()
+            .map(|t| {
+                (
+                    *t,
+                    self.stage_of(t),
+                    self.withdrawn.contains(t),
+                    self.version(t),
+                )
+            })
+            .collect()
+    }
+
+    /// Entry counts per set, for the size gauges.
+    pub(crate) fn sizes(&self) -> Vec<(&'static str, usize)> {
+        vec![
+            ("committed", self.committed.len()),
+            ("proposed", self.proposed.len()),
+            ("scheduled", self.scheduled.len()),
+            ("withdrawn", self.withdrawn.len()),
+            ("queue", self.queue.len()),
+            ("costs", self.costs.len()),
+            ("fees", self.fees.len()),
+            ("confirmed", self.confirmed.len()),
+            ("versions", self.versions.len()),
+        ]
+    }
+
+    /// Forgets versions and cached costs of txs that have left every stage.
+    ///
+    /// A tx counts as idle from the first pass that finds it outside every
+    /// set; it is dropped once idle for longer than `max_age`, or earlier
+    /// when more than `max_entries` txs are idle. Returns how many went.
+    pub(crate) fn collect_idle(&mut self, now: u64, max_age: u64, max_entries: usize) -> usize {
+        let queued: HashSet<&Txid> = self.queue.iter().collect();
+        let idle: HashSet<Txid> = self
+            .versions
+            .keys()
+            .chain(self.costs.keys())
+            .chain(self.fees.keys())
+            .filter(|t| {
+                !self.committed.contains(*t)
+                    && !self.proposed.contains(*t)
+                    && !self.scheduled.contains(*t)
+                    && !self.withdrawn.contains(*t)
+                    && !queued.contains(*t)
+                    && !self.confirmed.contains_key(*t)
+            })
+            .copied()
+            .collect();
+
+        // Back in the pipeline since the last pass
+        self.idle_since.retain(|t, _| idle.contains(t));
+        for txid in idle {
+            self.idle_since.entry(txid).or_insert(now);
+        }
+
+        let evicted = gc::evict(&mut self.idle_since, now, max_age, max_entries);
+        for txid in &evicted {
+            if let Some(v) = self.versions.remove(txid) {
+                self.version_floor = self.version_floor.max(v);
+            }
+            self.costs.remove(txid);
+            self.fees.remove(txid);
+        }
+        evicted.len()
+    }
+
+    /// Drops all pipeline state for a tx, e.g. once it confirms.
+    ///
+    /// Returns the queued txs that were scheduled into the freed capacity.
+    pub(crate) fn clear(&mut self, txid: &Txid, now: u64) -> Vec<Txid> {
+        let was_scheduled = self.scheduled.remove(txid);
+        let queue_len = self.queue.len();
+        self.queue.retain(|t| t != txid);
+        let uncommitted = self.committed.remove(txid);
+        if uncommitted {
+            self.bump_epoch();
+            self.record_epoch(now);
+        }
+        let removed = uncommitted
+            | self.proposed.remove(txid)
+            | self.withdrawn.remove(txid)
+            | was_scheduled
+            | (self.queue.len() != queue_len);
+        self.costs.remove(txid);
+        self.fees.remove(txid);
+        self.entered.remove(txid);
+        if removed {
+            self.bump(*txid);
+        }
+        if was_scheduled {
+            self.drain_queue(now)
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+pub(crate) static STATE: Lazy<Mutex<StateStore>> = Lazy::new(|| Mutex::new(StateStore::new()));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+
+    fn txid(n: u8) -> Txid {
+        Txid::from_byte_array([n; 32])
+    }
+
+    #[test]
+    fn test_version_never_recurs_after_gc() {
+        let mut state = StateStore::new();
+        let tx = txid(1);
+        let stale = state.version(&tx);
+        state.apply(tx, Transition::Commit, true, None, 10).unwrap();
+        state
+            .apply(tx, Transition::Uncommit, false, None, 20)
+            .unwrap();
+        let idle = state.version(&tx);
+
+        // No room for idle txs, so GC forgets it at once
+        assert_eq!(state.collect_idle(30, 60, 0), 1);
+        assert_eq!(state.version(&tx), idle);
+
+        let err = state.apply(tx, Transition::Commit, true, Some(stale), 50);
+        assert_eq!(
+            err.err(),
+            Some(TransitionError::VersionMismatch { current: idle })
+        );
+        let back = state
+            .apply(tx, Transition::Commit, true, Some(idle), 60)
+            .unwrap();
+        assert!(back.version > idle);
+    }
+
+    #[test]
+    fn test_tracked_includes_txs_proposed_from_cmempool() {
+        let mut state = StateStore::new();
+        let tx = txid(2);
+        // Held by cmempool but never committed through the pipeline
+        state
+            .apply(tx, Transition::Propose, true, None, 10)
+            .unwrap();
+
+        let tracked = state.tracked();
+        assert_eq!(tracked.len(), 1);
+        assert_eq!(tracked[0].0, tx);
+        assert_eq!(tracked[0].1, Stage::Proposed);
+    }
+
+    #[test]
+    fn test_apply_all_applies_none_on_failure() {
+        let mut state = StateStore::new();
+        let (a, b, c) = (txid(3), txid(4), txid(5));
+        for tx in [a, c] {
+            state
+                .apply(tx, Transition::Commit, false, None, 10)
+                .unwrap();
+        }
+        let versions = [state.version(&a), state.version(&c)];
+
+        // Proposing needs every tx committed; the second one is not
+        let items = [(a, false), (b, false), (c, false)];
+        let err = state.apply_all(&items, Transition::Propose, 20);
+        assert_eq!(err.err(), Some((1, TransitionError::NotCommitted)));
+        assert!(state.proposed.is_empty());
+        assert_eq!([state.version(&a), state.version(&c)], versions);
+        assert_eq!(state.stage_of(&a), Stage::Committed);
+    }
+
+    #[test]
+    fn test_version_bumps_on_change_only() {
+        let mut state = StateStore::new();
+        let tx = txid(10);
+        let start = state.version(&tx);
+
+        state
+            .apply(tx, Transition::Commit, false, None, 10)
+            .unwrap();
+        let proposed = state
+            .apply(tx, Transition::Propose, false, None, 20)
+            .unwrap();
+        assert_eq!(proposed.version, start + 2);
+
+        // Re-proposing an already proposed tx changes nothing
+        let again = state
+            .apply(tx, Transition::Propose, false, None, 30)
+            .unwrap();
+        assert_eq!(again.version, proposed.version);
+    }
+
+    #[test]
+    fn test_expected_version_makes_concurrent_writers_race() {
+        let mut state = StateStore::new();
+        let tx = txid(11);
+        state
+            .apply(tx, Transition::Commit, false, None, 10)
+            .unwrap();
+        state
+            .apply(tx, Transition::Propose, false, None, 10)
+            .unwrap();
+
+        // Both clients read the same version, then race to schedule
+        let read = state.version(&tx);
+        let first = state
+            .apply(tx, Transition::Schedule, false, Some(read), 20)
+            .unwrap();
+        let second = state.apply(tx, Transition::Unpropose, false, Some(read), 20);
+
+        assert_eq!(first.version, read + 1);
+        assert_eq!(
+            second.err(),
+            Some(TransitionError::VersionMismatch {
+                current: first.version
+            }),
+            "second writer must see the new version"
+        );
+        assert_eq!(state.stage_of(&tx), Stage::Scheduled);
+    }
+
+    fn proposed_with_cost(state: &mut StateStore, tx: Txid, weight: u64, sigops: u64) {
+        state.costs.insert(tx, Cost { weight, sigops });
+        state
+            .apply(tx, Transition::Commit, false, None, 10)
+            .unwrap();
+        state
+            .apply(tx, Transition::Propose, false, None, 10)
+            .unwrap();
+    }
+
+    fn small_budget(overflow: Overflow) -> Budget {
+        Budget {
+            max_weight: 1_100,
+            coinbase_reserve_weight: 100,
+            max_sigops: 110,
+            coinbase_reserve_sigops: 10,
+            overflow,
+        }
+    }
+
+    #[test]
+    fn test_schedule_rejected_over_weight() {
+        let mut state = StateStore::new();
+        state.set_budget(small_budget(Overflow::Reject), 0);
+        let (a, b, c) = (txid(20), txid(21), txid(22));
+        proposed_with_cost(&mut state, a, 600, 4);
+        proposed_with_cost(&mut state, b, 401, 4);
+        proposed_with_cost(&mut state, c, 400, 4);
+        state
+            .apply(a, Transition::Schedule, false, None, 20)
+            .unwrap();
+
+        let err = state.apply(b, Transition::Schedule, false, None, 20);
+        assert_eq!(
+            err.err(),
+            Some(TransitionError::CapacityExceeded {
+                needed: Cost {
+                    weight: 401,
+                    sigops: 4
+                },
+                remaining: Cost {
+                    weight: 400,
+                    sigops: 96
+                },
+            })
+        );
+        state
+            .apply(c, Transition::Schedule, false, None, 20)
+            .unwrap();
+        assert_eq!(state.remaining().weight, 0);
+    }
+
+    #[test]
+    fn test_schedule_rejected_over_sigops() {
+        let mut state = StateStore::new();
+        state.set_budget(small_budget(Overflow::Reject), 0);
+        let (a, b) = (txid(23), txid(24));
+        proposed_with_cost(&mut state, a, 400, 80);
+        proposed_with_cost(&mut state, b, 400, 24);
+        state
+            .apply(a, Transition::Schedule, false, None, 20)
+            .unwrap();
+
+        let err = state.apply(b, Transition::Schedule, false, None, 20);
+        assert!(matches!(
+            err.err(),
+            Some(TransitionError::CapacityExceeded { .. })
+        ));
+        assert_eq!(state.stage_of(&b), Stage::Proposed);
+    }
+
+    #[test]
+    fn test_queue_drains_oldest_first_when_capacity_frees() {
+        let mut state = StateStore::new();
+        state.set_budget(small_budget(Overflow::Queue), 0);
+        let (big, a, b, c) = (txid(25), txid(26), txid(27), txid(28));
+        proposed_with_cost(&mut state, big, 500, 4);
+        state
+            .apply(big, Transition::Schedule, false, None, 20)
+            .unwrap();
+        for (tx, weight) in [(a, 700), (b, 300), (c, 250)] {
+            proposed_with_cost(&mut state, tx, weight, 4);
+        }
+        for tx in [a, b, c] {
+            state
+                .apply(tx, Transition::Schedule, false, None, 30)
+                .unwrap();
+        }
+        // Only "b" fitted next to "big"; "a" and "c" wait in order
+        assert_eq!(state.queue, vec![a, c]);
+
+        // 700 WU free: "a" fits first, which leaves no room for "c"
+        let freed = state
+            .apply(big, Transition::Unschedule, false, None, 40)
+            .unwrap();
+        assert_eq!(freed.promoted, vec![a]);
+        assert_eq!(state.queue, vec![c]);
+        assert_eq!(state.stage_of(&a), Stage::Scheduled);
+    }
+
+    #[test]
+    fn test_restored_to_stage_held_before_confirmation() {
+        let block = BlockHash::from_byte_array([1; 32]);
+        let mut state = StateStore::new();
+        let (mempool, committed, proposed, scheduled) = (txid(30), txid(31), txid(32), txid(33));
+        let steps = [
+            Transition::Commit,
+            Transition::Propose,
+            Transition::Schedule,
+        ];
+        for (tx, n) in [(committed, 1), (proposed, 2), (scheduled, 3)] {
+            for transition in &steps[..n] {
+                state.apply(tx, *transition, false, None, 10).unwrap();
+            }
+        }
+        for tx in [mempool, committed, proposed, scheduled] {
+            state.confirm(tx, block, 20);
+            assert_eq!(state.stage_of(&tx), Stage::Mempool);
+        }
+
+        for (tx, stage) in [
+            (mempool, Stage::Mempool),
+            (committed, Stage::Committed),
+            (proposed, Stage::Proposed),
+            (scheduled, Stage::Scheduled),
+        ] {
+            assert_eq!(state.unconfirm(tx, &block, 30), Some(stage));
+            assert_eq!(state.stage_of(&tx), stage);
+        }
+    }
+
+    #[test]
+    fn test_scheduled_tx_queued_when_capacity_is_gone() {
+        let block = BlockHash::from_byte_array([2; 32]);
+        let mut state = StateStore::new();
+        state.set_budget(small_budget(Overflow::Reject), 0);
+        let (tx, other) = (txid(34), txid(35));
+        proposed_with_cost(&mut state, tx, 600, 4);
+        state
+            .apply(tx, Transition::Schedule, false, None, 10)
+            .unwrap();
+        state.confirm(tx, block, 20);
+
+        // Its room went to another tx while it was confirmed
+        proposed_with_cost(&mut state, other, 600, 4);
+        state
+            .apply(other, Transition::Schedule, false, None, 30)
+            .unwrap();
+
+        assert_eq!(state.unconfirm(tx, &block, 40), Some(Stage::Proposed));
+        assert_eq!(state.queue, vec![tx]);
+    }
+
+    #[test]
+    fn test_first_confirmation_record_wins_for_same_block() {
+        let block = BlockHash::from_byte_array([3; 32]);
+        let mut state = StateStore::new();
+        let tx = txid(36);
+        state
+            .apply(tx, Transition::Commit, false, None, 10)
+            .unwrap();
+        state
+            .apply(tx, Transition::Propose, false, None, 10)
+            .unwrap();
+
+        // A later sweep over the same block must not overwrite Proposed
+        state.confirm(tx, block, 20);
+        state.confirm(tx, block, 30);
+        assert_eq!(state.unconfirm(tx, &block, 40), Some(Stage::Proposed));
+
+        // Nothing to restore for a block that never confirmed it
+        let other = BlockHash::from_byte_array([4; 32]);
+        assert_eq!(state.unconfirm(tx, &other, 50), None);
+    }
+
+    #[test]
+    fn test_only_txs_outside_every_stage_are_idle() {
+        let mut state = StateStore::new();
+        let (committed, confirmed, idle) = (txid(40), txid(41), txid(42));
+        for tx in [committed, confirmed, idle] {
+            state
+                .apply(tx, Transition::Commit, false, None, 10)
+                .unwrap();
+        }
+        state.confirm(confirmed, BlockHash::from_byte_array([5; 32]), 20);
+        state
+            .apply(idle, Transition::Uncommit, false, None, 20)
+            .unwrap();
+
+        assert_eq!(state.collect_idle(30, 60, 0), 1);
+        assert!(state.versions.contains_key(&committed));
+        assert!(state.versions.contains_key(&confirmed));
+        assert!(!state.versions.contains_key(&idle));
+    }
+
+    #[test]
+    fn test_idle_clock_restarts_after_reentering_pipeline() {
+        let mut state = StateStore::new();
+        let tx = txid(43);
+        state
+            .apply(tx, Transition::Commit, false, None, 10)
+            .unwrap();
+        state
+            .apply(tx, Transition::Uncommit, false, None, 10)
+            .unwrap();
+        assert_eq!(state.collect_idle(100, 60, 10), 0);
+
+        // Committed again between passes, then left once more
+        state
+            .apply(tx, Transition::Commit, false, None, 120)
+            .unwrap();
+        assert_eq!(state.collect_idle(130, 60, 10), 0);
+        state
+            .apply(tx, Transition::Uncommit, false, None, 140)
+            .unwrap();
+
+        // Idle since 150, not since 100
+        assert_eq!(state.collect_idle(150, 60, 10), 0);
+        assert_eq!(state.collect_idle(200, 60, 10), 0);
+        assert_eq!(state.collect_idle(211, 60, 10), 1);
+    }
+
+    fn scheduled(tx: Txid) -> StateStore {
+        let mut state = StateStore::new();
+        for transition in [
+            Transition::Commit,
+            Transition::Propose,
+            Transition::Schedule,
+        ] {
+            state.apply(tx, transition, false, None, 10).unwrap();
+        }
+        state
+    }
+
+    #[test]
+    fn test_reverse_transitions_walk_back_one_stage() {
+        let tx = txid(6);
+        let mut state = scheduled(tx);
+
+        for (transition, to) in [
+            (Transition::Unschedule, Stage::Proposed),
+            (Transition::Unpropose, Stage::Committed),
+            (Transition::Uncommit, Stage::Mempool),
+        ] {
+            assert!(transition.is_reverse());
+            state.apply(tx, transition, false, None, 20).unwrap();
+            assert_eq!(state.stage_of(&tx), to, "{}", transition.name());
+        }
+    }
+
+    #[test]
+    fn test_reverse_requires_later_stages_undone_first() {
+        let tx = txid(7);
+        let mut state = scheduled(tx);
+
+        let err = state.apply(tx, Transition::Unpropose, false, None, 20);
+        assert_eq!(err.err(), Some(TransitionError::StillScheduled));
+        let err = state.apply(tx, Transition::Uncommit, false, None, 20);
+        assert_eq!(err.err(), Some(TransitionError::StillProposed));
+        assert_eq!(state.stage_of(&tx), Stage::Scheduled);
+    }
+
+    #[test]
+    fn test_reverse_from_wrong_stage() {
+        let tx = txid(8);
+        let mut state = StateStore::new();
+
+        let err = state.apply(tx, Transition::Uncommit, false, None, 10);
+        assert_eq!(err.err(), Some(TransitionError::NotCommitted));
+
+        state
+            .apply(tx, Transition::Commit, false, None, 10)
+            .unwrap();
+        let err = state.apply(tx, Transition::Unpropose, false, None, 20);
+        assert_eq!(err.err(), Some(TransitionError::NotProposed));
+
+        state
+            .apply(tx, Transition::Propose, false, None, 20)
+            .unwrap();
+        let err = state.apply(tx, Transition::Unschedule, false, None, 30);
+        assert_eq!(err.err(), Some(TransitionError::NotScheduled));
+        assert_eq!(state.stage_of(&tx), Stage::Proposed);
+    }
+}
@@ -0,0 +1,327 @@
+use crate::pipeline::Transition;
+use bitcoincore_rpc::bitcoin::Txid;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RuleAction {
+    Commit,
+    Propose,
+    Schedule,
+}
+
+impl RuleAction {
+    // Category a tx must be in for the action to apply
+    fn source_category(self) -> &'static str {
+        match self {
+            RuleAction::Commit => "Mempool",
+            RuleAction::Propose => "Committed",
+            RuleAction::Schedule => "Proposed",
+        }
+    }
+
+    pub(crate) fn transition(self) -> Transition {
+        match self {
+            RuleAction::Commit => Transition::Commit,
+            RuleAction::Propose => Transition::Propose,
+            RuleAction::Schedule => Transition::Schedule,
+        }
+    }
+}
+
+/// A declarative promotion rule; every condition that is set must hold.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Rule {
+    pub name: String,
+    pub action: RuleAction,
+    #[serde(default)]
+    pub min_fee_rate: Option<f64>, // sat/vB
+    #[serde(default)]
+    pub max_fee_rate: Option<f64>, // sat/vB
+    #[serde(default)]
+    pub min_age_secs: Option<u64>, // time spent in the current stage
+    #[serde(default)]
+    pub max_package_size: Option<u64>, // in-mempool ancestors + descendants + self
+    #[serde(default)]
+    pub rbf_signaled: Option<bool>,
+    #[serde(default)]
+    pub max_scheduled_weight: Option<u64>, // WU; schedule rules only
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Policy {
+    pub enabled: bool,
+    pub dry_run: bool,
+    pub interval_secs: u64,
+    pub rules: Vec<Rule>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            enabled: false,
+            dry_run: true,
+            interval_secs: 30,
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl Policy {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.interval_secs == 0 {
+            return Err("interval_secs must be at least 1".to_string());
+        }
+        for rule in &self.rules {
+            if rule.name.trim().is_empty() {
+                return Err("every rule needs a name".to_string());
+            }
+            if let (Some(min), Some(max)) = (rule.min_fee_rate, rule.max_fee_rate) {
+                if min > max {
+                    return Err(format!(
+                        "rule {}: min_fee_rate above max_fee_rate",
+                        rule.name
+                    ));
+                }
+            }
+            if rule.max_scheduled_weight.is_some() && rule.action != RuleAction::Schedule {
+                return Err(format!(
+                    "rule {}: max_scheduled_weight only applies to schedule rules",
+                    rule.name
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What the engine knows about a tx at evaluation time.
+pub(crate) struct Candidate {
+    pub txid: Txid,
+    pub category: String,
+    pub fee_rate: f64,
+    pub weight: u64,
+    pub rbf_signaled: bool,
+    pub age_in_stage: u64,
+    pub package_size: u64,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct Decision {
+    pub txid: String,
+    pub rule: String,
+    pub action: RuleAction,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<serde_json::Value>,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct Report {
+    pub evaluated_at: u64,
+    pub dry_run: bool,
+    pub candidates: usize,
+    pub decisions: Vec<Decision>,
+}
+
+pub(crate) static POLICY: Lazy<Mutex<Policy>> = Lazy::new(|| Mutex::new(Policy::default()));
+pub(crate) static LAST_REPORT: Lazy<Mutex<Option<Report>>> = Lazy::new(|| Mutex::new(None));
+
+fn matches(rule: &Rule, c: &Candidate) -> bool {
+    c.category == rule.action.source_category()
+        && rule.min_fee_rate.is_none_or(|min| c.fee_rate >= min)
+        && rule.max_fee_rate.is_none_or(|max| c.fee_rate <= max)
+        && rule.min_age_secs.is_none_or(|min| c.age_in_stage >= min)
+        && rule
+            .max_package_size
+            .is_none_or(|max| c.package_size <= max)
+        && rule.rbf_signaled.is_none_or(|rbf| c.rbf_signaled == rbf)
+}
+
+/// Picks at most one action per tx, highest fee rate first.
+///
+/// `scheduled_weight` is the weight already in the Scheduled set; schedule
+/// rules with a `max_scheduled_weight` stop once that budget is used.
+pub(crate) fn plan(
+    policy: &Policy,
+    candidates: &[Candidate],
+    scheduled_weight: u64,
+) -> Vec<Decision> {
+    let mut order: Vec<&Candidate> = candidates.iter().collect();
+    order.sort_by(|a, b| b.fee_rate.total_cmp(&a.fee_rate));
+
+    let mut scheduled_weight = scheduled_weight;
+    let mut decisions = Vec::new();
+    for c in order {
+        let rule = policy.rules.iter().find(|rule| {
+            matches(rule, c)
+                && rule
+                    .max_scheduled_weight
+                    .is_none_or(|max| scheduled_weight + c.weight <= max)
+        });
+
+        if let Some(rule) = rule {
+            if rule.action == RuleAction::Schedule {
+                scheduled_weight += c.weight;
+            }
+            decisions.push(Decision {
+                txid: c.txid.to_string(),
+                rule: rule.name.clone(),
+                action: rule.action,
+                outcome: None,
+            });
+        }
+    }
+    decisions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+
+    fn txid(n: u8) -> Txid {
+        Txid::from_byte_array([n; 32])
+    }
+
+    fn rule(action: RuleAction) -> Rule {
+        Rule {
+            name: format!("{action:?}"),
+            action,
+            min_fee_rate: None,
+            max_fee_rate: None,
+            min_age_secs: None,
+            max_package_size: None,
+            rbf_signaled: None,
+            max_scheduled_weight: None,
+        }
+    }
+
+    fn candidate(n: u8, category: &str, fee_rate: f64, age_in_stage: u64) -> Candidate {
+        Candidate {
+            txid: txid(n),
+            category: category.to_string(),
+            fee_rate,
+            weight: 800,
+            rbf_signaled: false,
+            age_in_stage,
+            package_size: 1,
+        }
+    }
+
+    fn planned(rules: Vec<Rule>, candidates: &[Candidate], scheduled_weight: u64) -> Vec<Txid> {
+        let policy = Policy {
+            rules,
+            ..Policy::default()
+        };
+        plan(&policy, candidates, scheduled_weight)
+            .iter()
+            .map(|d| d.txid.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_fee_rate_threshold() {
+        let commit = Rule {
+            min_fee_rate: Some(10.0),
+            ..rule(RuleAction::Commit)
+        };
+        let txs = [
+            candidate(1, "Mempool", 5.0, 0),
+            candidate(2, "Mempool", 12.0, 0),
+        ];
+
+        assert_eq!(planned(vec![commit], &txs, 0), vec![txid(2)]);
+    }
+
+    #[test]
+    fn test_rule_only_applies_to_its_source_stage() {
+        let txs = [
+            candidate(1, "Mempool", 50.0, 0),
+            candidate(2, "Committed", 1.0, 0),
+        ];
+
+        assert_eq!(
+            planned(vec![rule(RuleAction::Propose)], &txs, 0),
+            vec![txid(2)]
+        );
+    }
+
+    #[test]
+    fn test_min_age_in_stage() {
+        let propose = Rule {
+            min_age_secs: Some(60),
+            ..rule(RuleAction::Propose)
+        };
+        let txs = [
+            candidate(1, "Committed", 5.0, 30),
+            candidate(2, "Committed", 5.0, 90),
+        ];
+
+        assert_eq!(planned(vec![propose], &txs, 0), vec![txid(2)]);
+    }
+
+    #[test]
+    fn test_schedule_fills_weight_budget_by_fee_rate() {
+        let schedule = Rule {
+            max_scheduled_weight: Some(2_000),
+            ..rule(RuleAction::Schedule)
+        };
+        let txs = [
+            candidate(1, "Proposed", 2.0, 0),
+            candidate(2, "Proposed", 30.0, 0),
+            candidate(3, "Proposed", 10.0, 0),
+        ];
+
+        // 400 WU already scheduled leaves room for two 800 WU txs
+        assert_eq!(planned(vec![schedule], &txs, 400), vec![txid(2), txid(3)]);
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let rules = vec![
+            Rule {
+                name: "rich".to_string(),
+                min_fee_rate: Some(20.0),
+                ..rule(RuleAction::Commit)
+            },
+            Rule {
+                name: "patient".to_string(),
+                min_age_secs: Some(600),
+                ..rule(RuleAction::Commit)
+            },
+        ];
+        let txs = [
+            candidate(1, "Mempool", 25.0, 900),
+            candidate(2, "Mempool", 1.0, 0),
+        ];
+
+        let decisions = plan(
+            &Policy {
+                rules,
+                ..Policy::default()
+            },
+            &txs,
+            0,
+        );
+        assert_eq!(decisions.len(), 1);
+        assert_eq!(decisions[0].txid, txid(1).to_string());
+        assert_eq!(decisions[0].rule, "rich");
+    }
+
+    #[test]
+    fn test_validate_rejects_weight_cap_outside_schedule() {
+        let mut policy = Policy {
+            rules: vec![Rule {
+                max_scheduled_weight: Some(1_000),
+                ..rule(RuleAction::Commit)
+            }],
+            ..Policy::default()
+        };
+        assert!(policy.validate().is_err());
+
+        policy.rules[0].action = RuleAction::Schedule;
+        assert!(policy.validate().is_ok());
+    }
+}
@@ -0,0 +1,243 @@
+use bitcoincore_rpc::bitcoin::Txid;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// M-of-N approval for propose and schedule, N being the registered operators.
+///
+/// Only operator requests are voted on; the policy loop cannot vote, and
+/// only reports, as a dry run, once operators are registered.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Settings {
+    pub enabled: bool,
+    pub threshold: usize, // M
+    pub expiry_secs: u64, // how long a vote counts towards the quorum
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            enabled: false,
+            threshold: 2,
+            expiry_secs: 60 * 60,
+        }
+    }
+}
+
+impl Settings {
+    pub(crate) fn validate(&self, operators: usize) -> Result<(), String> {
+        if self.threshold == 0 {
+            return Err("threshold must be at least 1".to_string());
+        }
+        if self.expiry_secs == 0 {
+            return Err("expiry_secs must be at least 1".to_string());
+        }
+        if self.enabled && operators == 0 {
+            return Err("quorum mode needs registered operators".to_string());
+        }
+        if self.enabled && self.threshold > operators {
+            return Err(format!(
+                "threshold {} exceeds the {operators} registered operators",
+                self.threshold
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct Vote {
+    pub operator: String,
+    pub at: u64,
+    pub expires_at: u64,
+}
+
+/// Live votes for one transition of one tx.
+#[derive(Serialize)]
+pub(crate) struct Tally {
+    pub transition: &'static str,
+    pub votes: Vec<Vote>,
+    pub count: usize,
+    pub needed: usize,
+    pub reached: bool,
+}
+
+pub(crate) static SETTINGS: Lazy<Mutex<Settings>> = Lazy::new(|| Mutex::new(Settings::default()));
+
+// A tx and the transition voted on for it
+type Ballot = (Txid, &'static str);
+
+static VOTES: Lazy<Mutex<HashMap<Ballot, Vec<Vote>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn tally_of(transition: &'static str, votes: &[Vote], threshold: usize) -> Tally {
+    Tally {
+        transition,
+        votes: votes.to_vec(),
+        count: votes.len(),
+        needed: threshold,
+        reached: votes.len() >= threshold,
+    }
+}
+
+/// Records `operator`'s vote and returns the tally including it.
+///
+/// Voting again refreshes the operator's vote rather than counting twice;
+/// expired votes are dropped first.
+pub(crate) fn cast(
+    txid: Txid,
+    transition: &'static str,
+    operator: &str,
+    now: u64,
+    settings: &Settings,
+) -> Tally {
+    let mut all = VOTES.lock().unwrap();
+    let votes = all.entry((txid, transition)).or_default();
+    votes.retain(|v| v.expires_at > now && v.operator != operator);
+    votes.push(Vote {
+        operator: operator.to_string(),
+        at: now,
+        expires_at: now + settings.expiry_secs,
+    });
+    tally_of(transition, votes, settings.threshold)
+}
+
+/// The tally `cast` would return, without recording the vote.
+pub(crate) fn preview(
+    txid: Txid,
+    transition: &'static str,
+    operator: &str,
+    now: u64,
+    settings: &Settings,
+) -> Tally {
+    let all = VOTES.lock().unwrap();
+    let mut votes: Vec<Vote> = all
+        .get(&(txid, transition))
+        .map(|v| {
+            v.iter()
+                .filter(|v| v.expires_at > now && v.operator != operator)
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default();
+    votes.push(Vote {
+        operator: operator.to_string(),
+        at: now,
+        expires_at: now + settings.expiry_secs,
+    });
+    tally_of(transition, &votes, settings.threshold)
+}
+
+pub(crate) fn tally(txid: &Txid, transition: &'static str, now: u64, threshold: usize) -> Tally {
+    let all = VOTES.lock().unwrap();
+    let votes: Vec<Vote> = all
+        .get(&(*txid, transition))
+        .map(|v| v.iter().filter(|v| v.expires_at > now).cloned().collect())
+        .unwrap_or_default();
+    tally_of(transition, &votes, threshold)
+}
+
+/// Forgets the votes once the transition has gone through.
+pub(crate) fn clear(txid: &Txid, transition: &'static str) {
+    VOTES.lock().unwrap().remove(&(*txid, transition));
+}
+
+/// Drops expired votes; returns how many went.
+pub(crate) fn prune(now: u64) -> usize {
+    let mut all = VOTES.lock().unwrap();
+    let before: usize = all.values().map(Vec::len).sum();
+    all.retain(|_, votes| {
+        votes.retain(|v| v.expires_at > now);
+        !votes.is_empty()
+    });
+    before - all.values().map(Vec::len).sum::<usize>()
+}
+
+pub(crate) fn len() -> usize {
+    VOTES.lock().unwrap().values().map(Vec::len).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+
+    fn settings(threshold: usize) -> Settings {
+        Settings {
+            enabled: true,
+            threshold,
+            expiry_secs: 60,
+        }
+    }
+
+    #[test]
+    fn test_preview_counts_the_vote_without_keeping_it() {
+        let txid = Txid::from_byte_array([3; 32]);
+        let settings = settings(2);
+        cast(txid, "propose", "alice", 100, &settings);
+
+        assert!(preview(txid, "propose", "bob", 100, &settings).reached);
+        assert_eq!(tally(&txid, "propose", 100, 2).count, 1);
+        assert!(!preview(txid, "propose", "alice", 100, &settings).reached);
+    }
+
+    #[test]
+    fn test_quorum_reached_at_threshold() {
+        let txid = Txid::from_byte_array([4; 32]);
+        let settings = settings(2);
+        assert!(!cast(txid, "schedule", "alice", 100, &settings).reached);
+        let tally = cast(txid, "schedule", "bob", 101, &settings);
+        assert_eq!((tally.count, tally.needed), (2, 2));
+        assert!(tally.reached);
+    }
+
+    #[test]
+    fn test_revote_refreshes_instead_of_counting_twice() {
+        let txid = Txid::from_byte_array([5; 32]);
+        let settings = settings(2);
+        cast(txid, "propose", "alice", 100, &settings);
+        let tally = cast(txid, "propose", "alice", 130, &settings);
+        assert_eq!(tally.count, 1);
+        assert!(!tally.reached);
+        assert_eq!(tally.votes[0].expires_at, 190);
+        assert_eq!(preview(txid, "propose", "alice", 140, &settings).count, 1);
+    }
+
+    #[test]
+    fn test_expired_votes_no_longer_count() {
+        let txid = Txid::from_byte_array([6; 32]);
+        let settings = settings(2);
+        cast(txid, "propose", "alice", 100, &settings);
+
+        // Alice's vote lapses at 160, so Bob's alone falls short
+        assert!(preview(txid, "propose", "bob", 159, &settings).reached);
+        assert!(!preview(txid, "propose", "bob", 160, &settings).reached);
+        let tally = cast(txid, "propose", "bob", 160, &settings);
+        assert_eq!(tally.votes.len(), 1);
+        assert_eq!(tally.votes[0].operator, "bob");
+    }
+
+    #[test]
+    fn test_settings_validation() {
+        assert!(Settings::default().validate(0).is_ok());
+        assert!(settings(2).validate(3).is_ok());
+        assert!(settings(2).validate(0).is_err());
+        assert_eq!(
+            settings(4).validate(3),
+            Err("threshold 4 exceeds the 3 registered operators".to_string())
+        );
+        let disabled = Settings {
+            enabled: false,
+            threshold: 4,
+            ..settings(4)
+        };
+        assert!(disabled.validate(3).is_ok());
+        assert!(settings(0).validate(3).is_err());
+        let no_expiry = Settings {
+            expiry_secs: 0,
+            ..settings(2)
+        };
+        assert!(no_expiry.validate(3).is_err());
+    }
+}
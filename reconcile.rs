@@ -0,0 +1,181 @@
+use crate::pipeline::Stage;
+use bitcoincore_rpc::bitcoin::{BlockHash, OutPoint, Txid};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+// Corrections kept for `/reconciliations`
+const MAX_CORRECTIONS: usize = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Reason {
+    Confirmed,           // in a connected block the chain watcher missed
+    Conflicted,          // an input is already spent elsewhere
+    EvictedFromCmempool, // still in bitcoind, gone from cmempool
+    Dropped,             // gone from both nodes for no visible reason
+    WithdrawnGone,       // withdrawn and no longer in cmempool
+}
+
+impl Reason {
+    pub(crate) fn code(self) -> &'static str {
+        match self {
+            Reason::Confirmed => "confirmed",
+            Reason::Conflicted => "conflicted",
+            Reason::EvictedFromCmempool => "evicted_from_cmempool",
+            Reason::Dropped => "dropped",
+            Reason::WithdrawnGone => "withdrawn_gone",
+        }
+    }
+}
+
+/// What the nodes and the chain say, gathered after the state snapshot.
+pub(crate) struct View {
+    pub in_std: HashSet<Txid>,
+    pub in_cpool: HashSet<Txid>,
+    pub confirmed_in: HashMap<Txid, BlockHash>,
+    pub conflicted: HashSet<Txid>, // missing txs with an input no longer unspent
+}
+
+/// One stale entry fixed up by the reconciler.
+#[derive(Clone, Serialize)]
+pub(crate) struct Correction {
+    pub txid: String,
+    pub stage: &'static str, // held before the correction
+    pub reason: &'static str,
+    pub at: u64,
+}
+
+static CORRECTIONS: Lazy<Mutex<Vec<Correction>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+// Inputs of staged txs, noted while they are still in a mempool so a
+// conflict can be told apart once the tx itself is gone
+static SPENDS: Lazy<Mutex<HashMap<Txid, Vec<OutPoint>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Why the state held for a tx no longer matches the nodes, if it doesn't.
+///
+/// Committed, Proposed and Scheduled all mean the cmempool node holds the
+/// tx, so any of them is stale once it is gone from there.
+pub(crate) fn diagnose(txid: &Txid, stage: Stage, withdrawn: bool, view: &View) -> Option<Reason> {
+    if view.confirmed_in.contains_key(txid) {
+        return Some(Reason::Confirmed);
+    }
+    if view.in_cpool.contains(txid) {
+        return None;
+    }
+    if stage != Stage::Mempool {
+        if view.conflicted.contains(txid) {
+            return Some(Reason::Conflicted);
+        }
+        if view.in_std.contains(txid) {
+            return Some(Reason::EvictedFromCmempool);
+        }
+        return Some(Reason::Dropped);
+    }
+    if withdrawn {
+        return Some(Reason::WithdrawnGone);
+    }
+    None
+}
+
+pub(crate) fn spends_of(txid: &Txid) -> Option<Vec<OutPoint>> {
+    SPENDS.lock().unwrap().get(txid).cloned()
+}
+
+pub(crate) fn note_spends(txid: Txid, inputs: Vec<OutPoint>) {
+    SPENDS.lock().unwrap().insert(txid, inputs);
+}
+
+/// Forgets inputs of txs the pipeline no longer tracks.
+pub(crate) fn retain_spends(tracked: &HashSet<Txid>) {
+    SPENDS.lock().unwrap().retain(|t, _| tracked.contains(t));
+}
+
+pub(crate) fn record(correction: Correction) {
+    let mut corrections = CORRECTIONS.lock().unwrap();
+    corrections.push(correction);
+    if corrections.len() > MAX_CORRECTIONS {
+        let excess = corrections.len() - MAX_CORRECTIONS;
+        corrections.drain(..excess);
+    }
+}
+
+pub(crate) fn corrections() -> Vec<Correction> {
+    CORRECTIONS.lock().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+
+    fn txid(n: u8) -> Txid {
+        Txid::from_byte_array([n; 32])
+    }
+
+    fn view() -> View {
+        View {
+            in_std: HashSet::new(),
+            in_cpool: HashSet::new(),
+            confirmed_in: HashMap::new(),
+            conflicted: HashSet::new(),
+        }
+    }
+
+    #[test]
+    fn test_committed_tx_still_in_cmempool_left_alone() {
+        let mut v = view();
+        v.in_cpool.insert(txid(1));
+        assert_eq!(diagnose(&txid(1), Stage::Committed, false, &v), None);
+        assert_eq!(diagnose(&txid(1), Stage::Scheduled, false, &v), None);
+    }
+
+    #[test]
+    fn test_evicted_from_cmempool_only() {
+        let mut v = view();
+        v.in_std.insert(txid(1));
+        assert_eq!(
+            diagnose(&txid(1), Stage::Proposed, false, &v),
+            Some(Reason::EvictedFromCmempool)
+        );
+    }
+
+    #[test]
+    fn test_conflict_beats_plain_drop() {
+        let mut v = view();
+        v.conflicted.insert(txid(1));
+        assert_eq!(
+            diagnose(&txid(1), Stage::Committed, false, &v),
+            Some(Reason::Conflicted)
+        );
+        assert_eq!(
+            diagnose(&txid(2), Stage::Committed, false, &v),
+            Some(Reason::Dropped)
+        );
+    }
+
+    #[test]
+    fn test_confirmed_in_tracked_block_wins() {
+        let mut v = view();
+        v.confirmed_in
+            .insert(txid(1), BlockHash::from_byte_array([9; 32]));
+        v.in_cpool.insert(txid(1));
+        assert_eq!(
+            diagnose(&txid(1), Stage::Scheduled, false, &v),
+            Some(Reason::Confirmed)
+        );
+    }
+
+    #[test]
+    fn test_withdrawn_entry_cleared_once_out_of_cmempool() {
+        let mut v = view();
+        assert_eq!(
+            diagnose(&txid(1), Stage::Mempool, true, &v),
+            Some(Reason::WithdrawnGone)
+        );
+        v.in_cpool.insert(txid(1));
+        assert_eq!(diagnose(&txid(1), Stage::Mempool, true, &v), None);
+        // A plain mempool tx is never stale
+        assert_eq!(diagnose(&txid(2), Stage::Mempool, false, &view()), None);
+    }
+}
@@ -0,0 +1,501 @@
+use crate::capacity::Cost;
+use bitcoincore_rpc::bitcoin::absolute::LockTime;
+use bitcoincore_rpc::bitcoin::blockdata::block::Block;
+use bitcoincore_rpc::bitcoin::hash_types::WitnessMerkleNode;
+use bitcoincore_rpc::bitcoin::hashes::Hash;
+use bitcoincore_rpc::bitcoin::opcodes::all::OP_RETURN;
+use bitcoincore_rpc::bitcoin::script::{Builder, PushBytesBuf};
+use bitcoincore_rpc::bitcoin::transaction::Version;
+use bitcoincore_rpc::bitcoin::{
+    merkle_tree, Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxMerkleNode, TxOut,
+    Txid, Witness, Wtxid,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+// BIP141 commitment header: OP_RETURN, push 36, then 0xaa21a9ed
+const WITNESS_COMMITMENT_HEADER: [u8; 4] = [0xaa, 0x21, 0xa9, 0xed];
+
+// All-zero witness reserved value carried in the coinbase witness
+const WITNESS_RESERVED_VALUE: [u8; 32] = [0; 32];
+
+// Core's dust threshold for P2PKH at the default 3 sat/vB dust relay fee
+pub(crate) const DUST_LIMIT: u64 = 546;
+
+// Payout outputs per coinbase; 16 P2TR outputs plus the commitment stay
+// within COINBASE_RESERVE_WEIGHT
+pub(crate) const MAX_PAYOUT_OUTPUTS: usize = 16;
+
+/// A coinbase output owed to one miner.
+pub(crate) struct Payee {
+    pub script: ScriptBuf,
+    pub amount: u64,
+}
+
+/// A mempool tx that may go into the template, either scheduled or an ancestor of one.
+pub(crate) struct PoolTx {
+    pub tx: Transaction,
+    pub fee: u64,
+    pub cost: Cost,
+    pub parents: Vec<Txid>, // unconfirmed parents only
+}
+
+pub(crate) struct Selection {
+    pub included: Vec<Txid>, // parents before children
+    pub excluded: Vec<Txid>, // scheduled txs whose package did not fit
+    pub cost: Cost,
+    pub fees: u64,
+}
+
+// The tx plus every unconfirmed ancestor not already in `taken`
+fn package(txid: Txid, pool: &HashMap<Txid, PoolTx>, taken: &HashSet<Txid>) -> Vec<Txid> {
+    let mut out = Vec::new();
+    let mut seen = HashSet::new();
+    let mut stack = vec![txid];
+    while let Some(t) = stack.pop() {
+        if taken.contains(&t) || !seen.insert(t) {
+            continue;
+        }
+        if let Some(p) = pool.get(&t) {
+            out.push(t);
+            stack.extend(p.parents.iter().copied());
+        }
+    }
+    out
+}
+
+// Kahn's algorithm; ties broken by txid so the result is deterministic
+fn topo_order(set: &HashSet<Txid>, pool: &HashMap<Txid, PoolTx>) -> Vec<Txid> {
+    let mut pending: HashMap<Txid, usize> = set
+        .iter()
+        .map(|t| {
+            let parents = pool[t].parents.iter().filter(|p| set.contains(*p)).count();
+            (*t, parents)
+        })
+        .collect();
+    let mut ready: BTreeSet<Txid> = pending
+        .iter()
+        .filter(|(_, n)| **n == 0)
+        .map(|(t, _)| *t)
+        .collect();
+
+    let mut order = Vec::with_capacity(set.len());
+    while let Some(t) = ready.pop_first() {
+        pending.remove(&t);
+        order.push(t);
+        for (child, n) in pending.iter_mut() {
+            if pool[child].parents.contains(&t) {
+                *n -= 1;
+                if *n == 0 {
+                    ready.insert(*child);
+                }
+            }
+        }
+    }
+    order
+}
+
+/// Picks scheduled txs by package fee rate until `limit` is reached.
+///
+/// Each scheduled tx brings its unconfirmed ancestors along and is ranked
+/// by the fees over the weight of that whole package, so a child can pay
+/// for its parent; a package that would overflow the limit is left out as
+/// a whole.
+pub(crate) fn select(scheduled: &[Txid], pool: &HashMap<Txid, PoolTx>, limit: Cost) -> Selection {
+    let feerate = |txid: &Txid| {
+        let pkg = package(*txid, pool, &HashSet::new());
+        let fees: u64 = pkg.iter().map(|t| pool[t].fee).sum();
+        let weight: u64 = pkg.iter().map(|t| pool[t].tx.weight().to_wu()).sum();
+        fees as f64 / weight.max(1) as f64
+    };
+    let mut order: Vec<(f64, Txid)> = scheduled
+        .iter()
+        .filter(|t| pool.contains_key(*t))
+        .map(|t| (feerate(t), *t))
+        .collect();
+    order.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+
+    let mut taken = HashSet::new();
+    let mut excluded = Vec::new();
+    let mut cost = Cost::default();
+    let mut fees = 0;
+    for (_, txid) in order {
+        let pkg = package(txid, pool, &taken);
+        let pkg_cost = pkg
+            .iter()
+            .fold(Cost::default(), |acc, t| acc.add(pool[t].cost));
+        if !cost.add(pkg_cost).fits_in(limit) {
+            excluded.push(txid);
+            continue;
+        }
+        cost = cost.add(pkg_cost);
+        fees += pkg.iter().map(|t| pool[t].fee).sum::<u64>();
+        taken.extend(pkg);
+    }
+
+    Selection {
+        included: topo_order(&taken, pool),
+        excluded,
+        cost,
+        fees,
+    }
+}
+
+/// BIP22 `depends` of each included tx: the 1-based positions of its
+/// parents in the template's transaction list.
+pub(crate) fn depends(included: &[Txid], pool: &HashMap<Txid, PoolTx>) -> Vec<Vec<usize>> {
+    let position: HashMap<Txid, usize> = included
+        .iter()
+        .enumerate()
+        .map(|(i, t)| (*t, i + 1))
+        .collect();
+    included
+        .iter()
+        .map(|t| {
+            let mut depends: Vec<usize> = pool[t]
+                .parents
+                .iter()
+                .filter_map(|p| position.get(p).copied())
+                .collect();
+            depends.sort_unstable();
+            depends
+        })
+        .collect()
+}
+
+/// Witness merkle root with the coinbase's wtxid taken as all zeros.
+pub(crate) fn witness_root(wtxids: &[Wtxid]) -> WitnessMerkleNode {
+    let hashes = std::iter::once(Wtxid::all_zeros())
+        .chain(wtxids.iter().copied())
+        .map(|w| w.to_raw_hash());
+    merkle_tree::calculate_root(hashes)
+        .map(WitnessMerkleNode::from_raw_hash)
+        .expect("coinbase is always present")
+}
+
+/// The BIP141 commitment output script for the given non-coinbase wtxids.
+pub(crate) fn witness_commitment_script(wtxids: &[Wtxid]) -> ScriptBuf {
+    let commitment =
+        Block::compute_witness_commitment(&witness_root(wtxids), &WITNESS_RESERVED_VALUE);
+    let mut payload = WITNESS_COMMITMENT_HEADER.to_vec();
+    payload.extend_from_slice(commitment.as_byte_array());
+    Builder::new()
+        .push_opcode(OP_RETURN)
+        .push_slice(PushBytesBuf::try_from(payload).expect("36 bytes"))
+        .into_script()
+}
+
+pub(crate) fn merkle_root(txids: &[Txid]) -> Option<TxMerkleNode> {
+    merkle_tree::calculate_root(txids.iter().map(|t| t.to_raw_hash()))
+        .map(TxMerkleNode::from_raw_hash)
+}
+
+/// BIP34 height push followed by an 8-byte extranonce placeholder.
+pub(crate) fn coinbase_script_sig(height: u64) -> ScriptBuf {
+    Builder::new()
+        .push_int(height as i64)
+        .push_slice([0u8; 8])
+        .into_script()
+}
+
+/// Coinbase outputs splitting `value` between `payees` and `fallback`.
+///
+/// Payees sharing a script are merged, then paid largest first, ties
+/// broken by script. Amounts below `dust` and payees past `max_outputs`
+/// go to `fallback` along with whatever `value` the payees leave over;
+/// a leftover that is itself dust, or has no room, tops up the largest
+/// payee instead. Payee amounts must not add up to more than `value`.
+pub(crate) fn payout_outputs(
+    value: u64,
+    payees: &[Payee],
+    fallback: ScriptBuf,
+    dust: u64,
+    max_outputs: usize,
+) -> Vec<TxOut> {
+    let mut merged: BTreeMap<&ScriptBuf, u64> = BTreeMap::new();
+    for p in payees {
+        *merged.entry(&p.script).or_insert(0) += p.amount;
+    }
+    let mut kept: Vec<(&ScriptBuf, u64)> = merged.into_iter().filter(|(_, a)| *a >= dust).collect();
+    kept.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    // Over the cap, the last slot goes to the fallback
+    if kept.len() > max_outputs {
+        kept.truncate(max_outputs.saturating_sub(1));
+    }
+
+    let mut rest = value.saturating_sub(kept.iter().map(|(_, a)| a).sum::<u64>());
+    let mut outputs: Vec<TxOut> = kept
+        .into_iter()
+        .map(|(script, amount)| TxOut {
+            value: Amount::from_sat(amount),
+            script_pubkey: script.clone(),
+        })
+        .collect();
+    if rest > 0 && !outputs.is_empty() && (rest < dust || outputs.len() >= max_outputs) {
+        outputs[0].value += Amount::from_sat(rest);
+        rest = 0;
+    }
+    if rest > 0 || outputs.is_empty() {
+        outputs.push(TxOut {
+            value: Amount::from_sat(rest),
+            script_pubkey: fallback,
+        });
+    }
+    outputs
+}
+
+/// Coinbase with the given outputs, followed by the commitment to `wtxids`.
+pub(crate) fn coinbase(height: u64, mut outputs: Vec<TxOut>, wtxids: &[Wtxid]) -> Transaction {
+    outputs.push(TxOut {
+        value: Amount::ZERO,
+        script_pubkey: witness_commitment_script(wtxids),
+    });
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: coinbase_script_sig(height),
+            sequence: Sequence::MAX,
+            witness: Witness::from_slice(&[WITNESS_RESERVED_VALUE]),
+        }],
+        output: outputs,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUBSIDY: u64 = 5_000_000_000;
+
+    fn pool_tx(n: u8, fee: u64) -> (Txid, PoolTx) {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([n; 32]), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let cost = Cost {
+            weight: tx.weight().to_wu(),
+            sigops: 0,
+        };
+        let pool_tx = PoolTx {
+            tx,
+            fee,
+            cost,
+            parents: Vec::new(),
+        };
+        (pool_tx.tx.txid(), pool_tx)
+    }
+
+    #[test]
+    fn test_child_ranks_by_its_package_with_the_parent() {
+        let parent = pool_tx(1, 0);
+        let mut child = pool_tx(2, 5_000);
+        child.1.parents = vec![parent.0];
+        let single = pool_tx(3, 3_000);
+        let (child_id, single_id) = (child.0, single.0);
+        let weight = single.1.tx.weight().to_wu();
+        let pool: HashMap<Txid, PoolTx> = [parent, child, single].into_iter().collect();
+
+        // Room for two txs: the child alone pays 5_000 but its package only 2_500 per tx
+        let limit = Cost {
+            weight: 2 * weight,
+            sigops: 80_000,
+        };
+        let selection = select(&[child_id, single_id], &pool, limit);
+        assert_eq!(selection.included, vec![single_id]);
+        assert_eq!(selection.excluded, vec![child_id]);
+    }
+
+    fn room() -> Cost {
+        Cost {
+            weight: 4_000_000,
+            sigops: 80_000,
+        }
+    }
+
+    #[test]
+    fn test_parents_ordered_before_children() {
+        // c spends b, b spends a; only c is scheduled
+        let a = pool_tx(4, 100);
+        let mut b = pool_tx(5, 100);
+        b.1.parents = vec![a.0];
+        let mut c = pool_tx(6, 100);
+        c.1.parents = vec![b.0];
+        let ids = [a.0, b.0, c.0];
+        let pool: HashMap<Txid, PoolTx> = [a, b, c].into_iter().collect();
+
+        let selection = select(&[ids[2]], &pool, room());
+        assert_eq!(selection.included, ids);
+        assert_eq!(selection.fees, 300);
+    }
+
+    #[test]
+    fn test_independent_txs_ordered_by_txid() {
+        let txs = [pool_tx(7, 100), pool_tx(8, 900), pool_tx(9, 500)];
+        let mut ids: Vec<Txid> = txs.iter().map(|t| t.0).collect();
+        let pool: HashMap<Txid, PoolTx> = txs.into_iter().collect();
+
+        // Fee rate decides what fits, not where it goes
+        let selection = select(&ids, &pool, room());
+        ids.sort();
+        assert_eq!(selection.included, ids);
+    }
+
+    #[test]
+    fn test_depends_are_one_based_positions() {
+        let a = pool_tx(10, 100);
+        let mut b = pool_tx(11, 100);
+        b.1.parents = vec![a.0];
+        // c's parents: b is in the template, the other is already confirmed
+        let mut c = pool_tx(12, 100);
+        c.1.parents = vec![b.0, Txid::from_byte_array([99; 32])];
+        let included = [a.0, b.0, c.0];
+        let pool: HashMap<Txid, PoolTx> = [a, b, c].into_iter().collect();
+
+        assert_eq!(depends(&included, &pool), vec![vec![], vec![1], vec![2]]);
+    }
+
+    fn script(n: u8) -> ScriptBuf {
+        ScriptBuf::from_bytes(vec![0x00, 0x14, n])
+    }
+
+    fn payees(shares: &[(u8, u64)]) -> Vec<Payee> {
+        shares
+            .iter()
+            .map(|(n, amount)| Payee {
+                script: script(*n),
+                amount: *amount,
+            })
+            .collect()
+    }
+
+    // Fallback is script 0
+    fn paid(value: u64, shares: &[(u8, u64)], max_outputs: usize) -> Vec<(u8, u64)> {
+        payout_outputs(value, &payees(shares), script(0), DUST_LIMIT, max_outputs)
+            .iter()
+            .map(|o| (o.script_pubkey.as_bytes()[2], o.value.to_sat()))
+            .collect()
+    }
+
+    #[test]
+    fn test_ledger_split_paid_exactly() {
+        let shares = [(1, 1_250_003_086), (2, 1_250_003_086), (3, 2_500_006_173)];
+        let outputs = paid(5_000_012_345, &shares, MAX_PAYOUT_OUTPUTS);
+        assert_eq!(
+            outputs,
+            vec![(3, 2_500_006_173), (1, 1_250_003_086), (2, 1_250_003_086)]
+        );
+        assert_eq!(outputs.iter().map(|o| o.1).sum::<u64>(), 5_000_012_345);
+    }
+
+    #[test]
+    fn test_dust_payee_goes_to_fallback() {
+        // 545 sats is dust on its own, so it joins what the payees leave over
+        let outputs = paid(
+            10_000,
+            &[(1, 6_000), (2, 545), (3, 2_000)],
+            MAX_PAYOUT_OUTPUTS,
+        );
+        assert_eq!(outputs, vec![(1, 6_000), (3, 2_000), (0, 2_000)]);
+    }
+
+    #[test]
+    fn test_dust_leftover_tops_up_largest_payee() {
+        let outputs = paid(10_000, &[(1, 4_800), (2, 4_800)], MAX_PAYOUT_OUTPUTS);
+        assert_eq!(outputs, vec![(1, 5_200), (2, 4_800)]);
+    }
+
+    #[test]
+    fn test_payees_sharing_a_script_are_merged() {
+        // Two dust shares to one script add up to a payable output
+        let outputs = paid(1_000, &[(1, 300), (1, 300), (2, 400)], MAX_PAYOUT_OUTPUTS);
+        assert_eq!(outputs, vec![(1, 1_000)]);
+    }
+
+    #[test]
+    fn test_max_outputs_keeps_largest_and_a_fallback_slot() {
+        // One payee too many: the smallest two make way for the fallback
+        let shares: Vec<(u8, u64)> = (1..=MAX_PAYOUT_OUTPUTS as u8 + 1)
+            .map(|n| (n, 1_000 * n as u64))
+            .collect();
+        let value: u64 = shares.iter().map(|s| s.1).sum();
+
+        let outputs = paid(value, &shares, MAX_PAYOUT_OUTPUTS);
+        assert_eq!(outputs.len(), MAX_PAYOUT_OUTPUTS);
+        assert_eq!(outputs[0], (17, 17_000));
+        assert_eq!(outputs[MAX_PAYOUT_OUTPUTS - 2], (3, 3_000));
+        assert_eq!(outputs[MAX_PAYOUT_OUTPUTS - 1], (0, 3_000));
+    }
+
+    #[test]
+    fn test_leftover_with_no_free_slot_tops_up_largest_payee() {
+        let outputs = paid(10_000, &[(1, 3_000), (2, 4_000)], 2);
+        assert_eq!(outputs, vec![(2, 7_000), (1, 3_000)]);
+    }
+
+    #[test]
+    fn test_no_payees_pays_fallback() {
+        let outputs = paid(SUBSIDY, &[], MAX_PAYOUT_OUTPUTS);
+        assert_eq!(outputs, vec![(0, SUBSIDY)]);
+    }
+
+    #[test]
+    fn test_bip34_height_push() {
+        for (height, push) in [
+            (1, vec![0x51]),
+            (16, vec![0x60]),
+            (17, vec![0x01, 0x11]),
+            // 128 needs a sign byte
+            (128, vec![0x02, 0x80, 0x00]),
+            (840_000, vec![0x03, 0x40, 0xd1, 0x0c]),
+        ] {
+            let script_sig = coinbase_script_sig(height);
+            let (head, extranonce) = script_sig.as_bytes().split_at(push.len());
+            assert_eq!(head, push, "height {height}");
+            assert_eq!(extranonce, [&[0x08][..], &[0; 8]].concat());
+        }
+    }
+
+    #[test]
+    fn test_coinbase_value_is_subsidy_plus_selected_fees() {
+        let (a, b, other) = (pool_tx(1, 1_000), pool_tx(2, 3_500), pool_tx(3, 2_000));
+        let scheduled = [a.0, b.0];
+        let pool: HashMap<Txid, PoolTx> = [a, b, other].into_iter().collect();
+        let limit = Cost {
+            weight: 4_000_000,
+            sigops: 80_000,
+        };
+
+        // Only scheduled txs count; the node's own picks are left out
+        let selection = select(&scheduled, &pool, limit);
+        assert_eq!(selection.fees, 4_500);
+        assert_eq!(selection.included.len(), 2);
+
+        let fallback = ScriptBuf::from_bytes(vec![0x51]);
+        let outputs = payout_outputs(
+            SUBSIDY + selection.fees,
+            &[],
+            fallback,
+            DUST_LIMIT,
+            MAX_PAYOUT_OUTPUTS,
+        );
+        let wtxids: Vec<Wtxid> = selection
+            .included
+            .iter()
+            .map(|t| pool[t].tx.wtxid())
+            .collect();
+        let coinbase = coinbase(1, outputs, &wtxids);
+        let value: u64 = coinbase.output.iter().map(|o| o.value.to_sat()).sum();
+        assert_eq!(value, 5_000_004_500);
+    }
+}
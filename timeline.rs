@@ -0,0 +1,391 @@
+use crate::gc;
+use bitcoincore_rpc::bitcoin::Txid;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// Unix timestamps for each point in a transaction's trip through the pipeline.
+#[derive(Clone, Default, Serialize)]
+pub(crate) struct Timeline {
+    pub first_seen_bitcoind: Option<u64>,
+    pub first_seen_cmempool: Option<u64>,
+    pub committed: Option<u64>,
+    pub proposed: Option<u64>,
+    pub scheduled: Option<u64>,
+    pub confirmed: Option<u64>,
+    pub confirmed_height: Option<u64>,
+    pub dropped: Option<u64>,
+    pub reversals: Vec<Reversal>,
+    pub reorgs: Vec<Reorged>,
+    pub proposals: Vec<Proposal>,
+}
+
+/// An operator walking a tx back one stage, e.g. `unpropose`.
+#[derive(Clone, Serialize)]
+pub(crate) struct Reversal {
+    pub transition: &'static str,
+    pub reason: Option<String>,
+    pub at: u64,
+}
+
+/// Who moved a tx forward on propose or schedule, and why.
+#[derive(Clone, Serialize)]
+pub(crate) struct Proposal {
+    pub transition: &'static str,
+    pub proposer: Option<String>,
+    pub bead: Option<String>, // bead the proposer expects to carry the tx
+    pub note: Option<String>,
+    pub target_height: Option<u64>,
+    pub at: u64,
+    pub withdrawn_at: Option<u64>, // set once an unpropose or unschedule walks it back
+}
+
+/// A confirming block that was later disconnected.
+#[derive(Clone, Serialize)]
+pub(crate) struct Reorged {
+    pub block: String,
+    pub restored_to: &'static str,
+    pub at: u64,
+}
+
+pub(crate) enum Event {
+    SeenBitcoind(u64),
+    SeenCmempool(u64),
+    Committed(u64),
+    Proposed(u64),
+    Scheduled(u64),
+    Confirmed {
+        height: u64,
+        time: u64,
+    },
+    Dropped(u64),
+    Reverted {
+        transition: &'static str,
+        reason: Option<String>,
+        at: u64,
+    },
+    Reorged {
+        block: String,
+        restored_to: &'static str,
+        at: u64,
+    },
+    Proposal(Proposal),
+}
+
+#[derive(Serialize)]
+pub(crate) struct StageLatency {
+    pub count: usize,
+    pub p50: Option<u64>,
+    pub p90: Option<u64>,
+    pub p99: Option<u64>,
+}
+
+static TIMELINES: Lazy<Mutex<HashMap<Txid, Timeline>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Records an event; only the first occurrence of each stage is kept,
+/// while every reversal and reorg is appended.
+pub(crate) fn record(txid: Txid, event: Event) {
+    let mut timelines = TIMELINES.lock().unwrap();
+    let t = timelines.entry(txid).or_default();
+    match event {
+        Event::SeenBitcoind(ts) => {
+            t.first_seen_bitcoind.get_or_insert(ts);
+        }
+        Event::SeenCmempool(ts) => {
+            t.first_seen_cmempool.get_or_insert(ts);
+        }
+        Event::Committed(ts) => {
+            t.committed.get_or_insert(ts);
+        }
+        Event::Proposed(ts) => {
+            t.proposed.get_or_insert(ts);
+        }
+        Event::Scheduled(ts) => {
+            t.scheduled.get_or_insert(ts);
+        }
+        Event::Confirmed { height, time } => {
+            t.confirmed.get_or_insert(time);
+            t.confirmed_height.get_or_insert(height);
+        }
+        Event::Dropped(ts) => {
+            t.dropped.get_or_insert(ts);
+        }
+        Event::Reverted {
+            transition,
+            reason,
+            at,
+        } => {
+            // Unpropose walks back everything; unschedule only the schedule
+            for p in t.proposals.iter_mut().filter(|p| p.withdrawn_at.is_none()) {
+                if transition == "unpropose"
+                    || (transition == "unschedule" && p.transition == "schedule")
+                {
+                    p.withdrawn_at = Some(at);
+                }
+            }
+            t.reversals.push(Reversal {
+                transition,
+                reason,
+                at,
+            })
+        }
+        // The tx is unconfirmed again, so a later block can set these afresh
+        Event::Reorged {
+            block,
+            restored_to,
+            at,
+        } => {
+            t.confirmed = None;
+            t.confirmed_height = None;
+            t.reorgs.push(Reorged {
+                block,
+                restored_to,
+                at,
+            });
+        }
+        Event::Proposal(p) => t.proposals.push(p),
+    }
+}
+
+pub(crate) fn get(txid: &Txid) -> Option<Timeline> {
+    TIMELINES.lock().unwrap().get(txid).cloned()
+}
+
+/// Drops timelines whose latest event is older than `max_age`, then the
+/// least recently updated past `max_entries`. Returns how many went.
+pub(crate) fn prune(now: u64, max_age: u64, max_entries: usize) -> usize {
+    let mut timelines = TIMELINES.lock().unwrap();
+    let mut stamps: HashMap<Txid, u64> = timelines
+        .iter()
+        .map(|(txid, t)| (*txid, t.last_event()))
+        .collect();
+    let evicted = gc::evict(&mut stamps, now, max_age, max_entries);
+    for txid in &evicted {
+        timelines.remove(txid);
+    }
+    evicted.len()
+}
+
+pub(crate) fn len() -> usize {
+    TIMELINES.lock().unwrap().len()
+}
+
+impl Timeline {
+    /// The latest propose or schedule metadata that still stands.
+    pub(crate) fn current_proposal(&self) -> Option<&Proposal> {
+        self.proposals
+            .iter()
+            .rev()
+            .find(|p| p.withdrawn_at.is_none())
+    }
+
+    // Latest timestamp of any event, for GC
+    fn last_event(&self) -> u64 {
+        let stages = [
+            self.first_seen_bitcoind,
+            self.first_seen_cmempool,
+            self.committed,
+            self.proposed,
+            self.scheduled,
+            self.confirmed,
+            self.dropped,
+        ];
+        let walked_back = self.reversals.iter().map(|r| r.at);
+        let reorged = self.reorgs.iter().map(|r| r.at);
+        let proposals = self
+            .proposals
+            .iter()
+            .map(|p| p.withdrawn_at.unwrap_or(p.at).max(p.at));
+        stages
+            .into_iter()
+            .flatten()
+            .chain(walked_back)
+            .chain(reorged)
+            .chain(proposals)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn first_seen(&self) -> Option<u64> {
+        match (self.first_seen_bitcoind, self.first_seen_cmempool) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Seconds spent between consecutive stages, keyed by stage name.
+    pub(crate) fn stage_latencies(&self) -> Vec<(&'static str, u64)> {
+        let pairs = [
+            ("seen_to_committed", self.first_seen(), self.committed),
+            ("committed_to_proposed", self.committed, self.proposed),
+            ("proposed_to_scheduled", self.proposed, self.scheduled),
+            ("scheduled_to_confirmed", self.scheduled, self.confirmed),
+            ("end_to_end", self.first_seen(), self.confirmed),
+        ];
+
+        pairs
+            .into_iter()
+            .filter_map(|(stage, from, to)| match (from, to) {
+                (Some(from), Some(to)) => Some((stage, to.saturating_sub(from))),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+// Nearest-rank percentile over an ascending slice
+fn percentile(sorted: &[u64], pct: usize) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (pct * sorted.len()).div_ceil(100).max(1);
+    Some(sorted[rank - 1])
+}
+
+/// Aggregates stage latencies across every tracked timeline.
+pub(crate) fn latency_stats() -> BTreeMap<&'static str, StageLatency> {
+    let mut samples: BTreeMap<&'static str, Vec<u64>> = BTreeMap::new();
+    for t in TIMELINES.lock().unwrap().values() {
+        for (stage, secs) in t.stage_latencies() {
+            samples.entry(stage).or_default().push(secs);
+        }
+    }
+
+    samples
+        .into_iter()
+        .map(|(stage, mut values)| {
+            values.sort_unstable();
+            (
+                stage,
+                StageLatency {
+                    count: values.len(),
+                    p50: percentile(&values, 50),
+                    p90: percentile(&values, 90),
+                    p99: percentile(&values, 99),
+                },
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+
+    fn txid(n: u8) -> Txid {
+        Txid::from_byte_array([n; 32])
+    }
+
+    #[test]
+    fn test_percentile_nearest_rank() {
+        let samples: Vec<u64> = (1..=10).collect();
+        assert_eq!(percentile(&samples, 50), Some(5));
+        assert_eq!(percentile(&samples, 90), Some(9));
+        assert_eq!(percentile(&samples, 99), Some(10));
+        assert_eq!(percentile(&[42], 50), Some(42));
+        assert_eq!(percentile(&[], 50), None);
+    }
+
+    #[test]
+    fn test_stage_latencies_need_both_ends_and_saturate() {
+        let t = Timeline {
+            first_seen_bitcoind: Some(110),
+            first_seen_cmempool: Some(100),
+            committed: Some(130),
+            scheduled: Some(200),
+            // Block time can precede the local scheduling timestamp
+            confirmed: Some(150),
+            ..Timeline::default()
+        };
+        let latencies: BTreeMap<_, _> = t.stage_latencies().into_iter().collect();
+        assert_eq!(latencies["seen_to_committed"], 30);
+        assert_eq!(latencies["scheduled_to_confirmed"], 0);
+        assert_eq!(latencies["end_to_end"], 50);
+        assert!(!latencies.contains_key("committed_to_proposed"));
+    }
+
+    #[test]
+    fn test_first_stage_stamp_kept_and_reorg_clears_confirmation() {
+        let tx = txid(1);
+        record(tx, Event::Committed(10));
+        record(tx, Event::Committed(20));
+        record(
+            tx,
+            Event::Confirmed {
+                height: 5,
+                time: 30,
+            },
+        );
+        record(
+            tx,
+            Event::Reorged {
+                block: "b".to_string(),
+                restored_to: "Committed",
+                at: 40,
+            },
+        );
+
+        let t = get(&tx).unwrap();
+        assert_eq!(t.committed, Some(10));
+        assert_eq!(t.confirmed, None);
+        assert_eq!(t.reorgs.len(), 1);
+        assert_eq!(t.last_event(), 40);
+    }
+
+    fn proposal(transition: &'static str, proposer: &str, at: u64) -> Event {
+        Event::Proposal(Proposal {
+            transition,
+            proposer: Some(proposer.to_string()),
+            bead: None,
+            note: None,
+            target_height: None,
+            at,
+            withdrawn_at: None,
+        })
+    }
+
+    fn revert(transition: &'static str, at: u64) -> Event {
+        Event::Reverted {
+            transition,
+            reason: None,
+            at,
+        }
+    }
+
+    #[test]
+    fn test_unschedule_withdraws_only_the_schedule() {
+        let tx = txid(50);
+        record(tx, proposal("propose", "alice", 1));
+        record(tx, proposal("schedule", "bob", 2));
+        record(tx, revert("unschedule", 10));
+
+        let t = get(&tx).unwrap();
+        assert_eq!(t.proposals[0].withdrawn_at, None);
+        assert_eq!(t.proposals[1].withdrawn_at, Some(10));
+        let current = t.current_proposal().unwrap();
+        assert_eq!(current.proposer.as_deref(), Some("alice"));
+    }
+
+    #[test]
+    fn test_unpropose_withdraws_everything_and_reproposal_takes_over() {
+        let tx = txid(51);
+        record(tx, proposal("propose", "alice", 1));
+        record(tx, proposal("schedule", "bob", 2));
+        record(tx, revert("unpropose", 10));
+        assert!(get(&tx).unwrap().current_proposal().is_none());
+
+        record(tx, proposal("propose", "carol", 15));
+        let t = get(&tx).unwrap();
+        let current = t.current_proposal().unwrap();
+        assert_eq!(current.proposer.as_deref(), Some("carol"));
+
+        record(tx, revert("unpropose", 20));
+        let t = get(&tx).unwrap();
+        // Earlier withdrawals keep their own time
+        let withdrawn: Vec<_> = t.proposals.iter().map(|p| p.withdrawn_at).collect();
+        assert_eq!(withdrawn, [Some(10), Some(10), Some(20)]);
+        assert_eq!(t.reversals.len(), 2);
+    }
+}